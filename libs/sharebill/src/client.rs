@@ -0,0 +1,265 @@
+extern crate reqwest;
+extern crate serde_json;
+
+use models::Post;
+use rational::Rational;
+use std::collections::HashMap;
+use std::fmt;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum Error {
+        Http(err: reqwest::Error) { from() }
+        Json(err: serde_json::Error) { from() }
+        NotFound
+        UnexpectedStatus(status: reqwest::StatusCode)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PutOutcome {
+    Created,
+
+    /// A post with the given id already exists. Since post ids are
+    /// chosen by the client, this means an earlier attempt got through
+    AlreadyExists,
+}
+
+#[derive(Clone)]
+pub enum Auth {
+    None,
+    Bearer(String),
+    Basic {
+        user: String,
+        password: Option<String>,
+    },
+
+    /// Cookies given as `name=value` pairs
+    Cookies(Vec<String>),
+}
+
+// Written by hand to keep the secrets out of logs
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Auth::None => write!(f, "None"),
+            Auth::Bearer(_) => write!(f, "Bearer(..)"),
+            Auth::Basic { ref user, .. } => write!(f, "Basic {{ user: {:?}, .. }}", user),
+            Auth::Cookies(ref cookies) => write!(f, "Cookies({} cookies)", cookies.len()),
+        }
+    }
+}
+
+pub struct Client {
+    base_url: String,
+    auth: Auth,
+    http: reqwest::Client,
+}
+
+impl Client {
+    pub fn new(base_url: &str, auth: Auth) -> Client {
+        let base_url = if base_url.ends_with('/') {
+            base_url.to_owned()
+        } else {
+            format!("{}/", base_url)
+        };
+
+        Client {
+            base_url,
+            auth,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub fn post_url(&self, post_id: &str) -> String {
+        format!("{}post/{}", &self.base_url, post_id)
+    }
+
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        let req = self
+            .http
+            .request(method, url)
+            .header(reqwest::header::ACCEPT, "application/json");
+
+        match self.auth {
+            Auth::None => req,
+            Auth::Bearer(ref token) => req.bearer_auth(token),
+            Auth::Basic {
+                ref user,
+                ref password,
+            } => req.basic_auth(user, password.as_ref()),
+            Auth::Cookies(ref cookies) => req.header(reqwest::header::COOKIE, cookies.join("; ")),
+        }
+    }
+
+    pub fn put_post(&self, post_id: &str, post: &Post) -> Result<PutOutcome, Error> {
+        let res = self
+            .request(reqwest::Method::PUT, &self.post_url(post_id))
+            .json(post)
+            .send()?;
+
+        match res.status() {
+            reqwest::StatusCode::CREATED => Ok(PutOutcome::Created),
+            reqwest::StatusCode::CONFLICT => Ok(PutOutcome::AlreadyExists),
+            status => Err(Error::UnexpectedStatus(status)),
+        }
+    }
+
+    pub fn post(&self, post_id: &str) -> Result<Post, Error> {
+        let mut res = self
+            .request(reqwest::Method::GET, &self.post_url(post_id))
+            .send()?;
+
+        match res.status() {
+            reqwest::StatusCode::OK => Ok(res.json()?),
+            reqwest::StatusCode::NOT_FOUND => Err(Error::NotFound),
+            status => Err(Error::UnexpectedStatus(status)),
+        }
+    }
+
+    pub fn balances(&self) -> Result<HashMap<String, Rational>, Error> {
+        #[derive(Deserialize)]
+        struct Row {
+            key: String,
+            value: Rational,
+        }
+
+        #[derive(Deserialize)]
+        struct Balances {
+            rows: Vec<Row>,
+        }
+
+        let mut res = self
+            .request(reqwest::Method::GET, &format!("{}balances", &self.base_url))
+            .send()?;
+
+        if !res.status().is_success() {
+            return Err(Error::UnexpectedStatus(res.status()));
+        }
+        let balances: Balances = res.json()?;
+
+        Ok(balances
+            .rows
+            .into_iter()
+            .map(|row| (row.key, row.value))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate time;
+
+    use super::*;
+    use mock::MockSharebill;
+    use models::{Meta, Transaction};
+
+    fn fabricate_post() -> Post {
+        let mut debits = HashMap::new();
+        debits.insert("alice".to_owned(), "59".parse::<Rational>().unwrap());
+        debits.insert("bob".to_owned(), "69".parse::<Rational>().unwrap());
+
+        let mut credits = HashMap::new();
+        credits.insert("alice".to_owned(), "128".parse::<Rational>().unwrap());
+
+        Post {
+            meta: Meta {
+                description: "Fabricated post".to_owned(),
+                timestamp: time::strptime("2016-01-01T12:00:00", "%FT%T").unwrap(),
+            },
+            transaction: Transaction { debits, credits },
+        }
+    }
+
+    #[test]
+    fn put_and_fetch_post() {
+        let mock = MockSharebill::start();
+        let client = Client::new(mock.url(), Auth::None);
+
+        let outcome = client.put_post("some-id", &fabricate_post()).unwrap();
+        assert_eq!(PutOutcome::Created, outcome);
+
+        let post = client.post("some-id").unwrap();
+        assert_eq!("Fabricated post", &post.meta.description);
+        assert_eq!(
+            Some(&"69".parse::<Rational>().unwrap()),
+            post.transaction.debits.get("bob")
+        );
+    }
+
+    #[test]
+    fn put_existing_post() {
+        let mock = MockSharebill::start();
+        let client = Client::new(mock.url(), Auth::None);
+
+        client.put_post("some-id", &fabricate_post()).unwrap();
+        let outcome = client.put_post("some-id", &fabricate_post()).unwrap();
+        assert_eq!(PutOutcome::AlreadyExists, outcome);
+    }
+
+    #[test]
+    fn fetch_missing_post() {
+        let mock = MockSharebill::start();
+        let client = Client::new(mock.url(), Auth::None);
+
+        match client.post("missing") {
+            Err(Error::NotFound) => (),
+            x => panic!("Expected NotFound, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn balances_include_posts() {
+        let mock = MockSharebill::start();
+        mock.set_balance("bob", "10".parse().unwrap());
+        let client = Client::new(mock.url(), Auth::None);
+
+        client.put_post("some-id", &fabricate_post()).unwrap();
+
+        let balances = client.balances().unwrap();
+        assert_eq!(Some(&"69".parse::<Rational>().unwrap()), balances.get("alice"));
+        assert_eq!(Some(&"-59".parse::<Rational>().unwrap()), balances.get("bob"));
+    }
+
+    fn single_request_with(auth: Auth) -> ::mock::Request {
+        let mock = MockSharebill::start();
+        let client = Client::new(mock.url(), auth);
+
+        client.balances().unwrap();
+
+        let mut requests = mock.requests();
+        assert_eq!(1, requests.len());
+        requests.pop().unwrap()
+    }
+
+    #[test]
+    fn no_auth() {
+        let request = single_request_with(Auth::None);
+        assert_eq!(None, request.header("Authorization"));
+        assert_eq!(None, request.header("Cookie"));
+    }
+
+    #[test]
+    fn bearer_auth() {
+        let request = single_request_with(Auth::Bearer("s3cret".to_owned()));
+        assert_eq!(Some("Bearer s3cret"), request.header("Authorization"));
+    }
+
+    #[test]
+    fn basic_auth() {
+        let request = single_request_with(Auth::Basic {
+            user: "user".to_owned(),
+            password: Some("pass".to_owned()),
+        });
+        assert_eq!(Some("Basic dXNlcjpwYXNz"), request.header("Authorization"));
+    }
+
+    #[test]
+    fn cookie_auth() {
+        let request = single_request_with(Auth::Cookies(vec![
+            "AuthSession=abc".to_owned(),
+            "other=def".to_owned(),
+        ]));
+        assert_eq!(Some("AuthSession=abc; other=def"), request.header("Cookie"));
+    }
+}
@@ -2,10 +2,16 @@
 extern crate lazy_static;
 #[macro_use]
 extern crate quick_error;
+#[cfg(test)]
+#[macro_use]
+extern crate quickcheck;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde;
 
+pub mod client;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
 pub mod models;
 pub mod rational;
 
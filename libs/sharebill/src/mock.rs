@@ -0,0 +1,231 @@
+//! An in-process stand-in for a Sharebill server, so code talking to
+//! Sharebill can be tested without network access. It understands just
+//! enough HTTP to serve the requests made by `client::Client`.
+
+extern crate serde_json;
+
+use models::Post;
+use rational::Rational;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|&&(ref key, _)| key.eq_ignore_ascii_case(name))
+            .map(|&(_, ref value)| value as &str)
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    posts: HashMap<String, String>,
+    opening_balances: HashMap<String, Rational>,
+    requests: Vec<Request>,
+    responses_to_drop: usize,
+}
+
+pub struct MockSharebill {
+    url: String,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl MockSharebill {
+    pub fn start() -> MockSharebill {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Unable to bind mock server");
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        let inner = Arc::new(Mutex::new(Inner::default()));
+        let server_inner = inner.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Ok(stream) = stream {
+                    let _ignore_failure = handle(stream, &server_inner);
+                }
+            }
+        });
+
+        MockSharebill { url, inner }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Sets the balance an account has before any posts are counted
+    pub fn set_balance(&self, account: &str, balance: Rational) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.opening_balances.insert(account.to_owned(), balance);
+    }
+
+    /// Handles the next request, but closes the connection without
+    /// responding, like a response lost on the way back to the client
+    pub fn drop_next_response(&self) {
+        self.inner.lock().unwrap().responses_to_drop += 1;
+    }
+
+    pub fn posts(&self) -> HashMap<String, Post> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .posts
+            .iter()
+            .map(|(id, post)| {
+                let post = serde_json::from_str(post).expect("Validated when stored");
+                (id.clone(), post)
+            })
+            .collect()
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.inner.lock().unwrap().requests.clone()
+    }
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut split = request_line.split_whitespace();
+    let method = split.next().unwrap_or("").to_owned();
+    let path = split.next().unwrap_or("").to_owned();
+
+    let mut headers = vec![];
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(colon) = line.find(':') {
+            headers.push((
+                line[..colon].trim().to_owned(),
+                line[colon + 1..].trim().to_owned(),
+            ));
+        }
+    }
+
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: String::new(),
+    };
+
+    let content_length = request
+        .header("Content-Length")
+        .and_then(|x| x.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    request.body = String::from_utf8_lossy(&body).into_owned();
+
+    Ok(request)
+}
+
+fn balances(inner: &Inner) -> String {
+    #[derive(Serialize)]
+    struct Row {
+        key: String,
+        value: Rational,
+    }
+
+    #[derive(Serialize)]
+    struct Balances {
+        rows: Vec<Row>,
+    }
+
+    let mut balances = inner.opening_balances.clone();
+    for post in inner.posts.values() {
+        let post: Post = serde_json::from_str(post).expect("Validated when stored");
+
+        for (account, value) in post.transaction.credits {
+            let balance = balances.entry(account).or_insert_with(|| Rational::from(0));
+            *balance = &*balance + value;
+        }
+        for (account, value) in post.transaction.debits {
+            let balance = balances.entry(account).or_insert_with(|| Rational::from(0));
+            *balance = &*balance - &value;
+        }
+    }
+
+    let mut rows = balances
+        .into_iter()
+        .map(|(key, value)| Row { key, value })
+        .collect::<Vec<_>>();
+    rows.sort_by(|a, b| a.key.cmp(&b.key));
+
+    serde_json::to_string(&Balances { rows }).unwrap()
+}
+
+fn route(inner: &mut Inner, request: &Request) -> (&'static str, String) {
+    let post_id = if request.path.starts_with("/post/") {
+        Some(request.path["/post/".len()..].to_owned())
+    } else {
+        None
+    };
+
+    match (request.method.as_str(), post_id) {
+        ("PUT", Some(post_id)) => {
+            if inner.posts.contains_key(&post_id) {
+                return ("409 Conflict", r#"{"error":"conflict"}"#.to_owned());
+            }
+            if serde_json::from_str::<Post>(&request.body).is_err() {
+                return ("400 Bad Request", r#"{"error":"bad_request"}"#.to_owned());
+            }
+            inner.posts.insert(post_id, request.body.clone());
+            ("201 Created", r#"{"ok":true}"#.to_owned())
+        }
+        ("GET", Some(post_id)) => match inner.posts.get(&post_id) {
+            Some(post) => ("200 OK", post.clone()),
+            None => ("404 Not Found", r#"{"error":"not_found"}"#.to_owned()),
+        },
+        ("GET", None) if request.path == "/balances" => ("200 OK", balances(inner)),
+        _ => ("404 Not Found", r#"{"error":"not_found"}"#.to_owned()),
+    }
+}
+
+fn handle(mut stream: TcpStream, inner: &Mutex<Inner>) -> io::Result<()> {
+    let request = read_request(&stream)?;
+
+    let (status, body) = {
+        let mut inner = inner.lock().unwrap();
+        inner.requests.push(request.clone());
+        let response = route(&mut inner, &request);
+
+        if inner.responses_to_drop > 0 {
+            inner.responses_to_drop -= 1;
+            return Ok(());
+        }
+
+        response
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -4,15 +4,18 @@ use rational::Rational;
 use serde;
 use std::collections::HashMap;
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Meta {
     pub description: String,
 
-    #[serde(serialize_with = "serialize_time")]
+    #[serde(
+        serialize_with = "serialize_time",
+        deserialize_with = "deserialize_time"
+    )]
     pub timestamp: time::Tm,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Transaction {
     #[serde(rename = "debets")]
     pub debits: HashMap<String, Rational>,
@@ -20,7 +23,7 @@ pub struct Transaction {
     pub credits: HashMap<String, Rational>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Post {
     pub meta: Meta,
     pub transaction: Transaction,
@@ -33,6 +36,20 @@ where
     ser.serialize_str(&format!("{}", t.rfc3339()))
 }
 
+fn deserialize_time<'a, D>(de: D) -> Result<time::Tm, D::Error>
+where
+    D: serde::Deserializer<'a>,
+{
+    use serde::de::Error;
+    use serde::Deserialize;
+
+    let s = String::deserialize(de)?;
+
+    time::strptime(&s, "%Y-%m-%dT%H:%M:%SZ")
+        .or_else(|_| time::strptime(&s, "%Y-%m-%dT%H:%M:%S%z"))
+        .map_err(|err| D::Error::custom(format!("Invalid timestamp {:?}: {}", s, err)))
+}
+
 #[cfg(test)]
 mod test {
     extern crate serde_json;
@@ -72,4 +89,11 @@ mod test {
         let serialized = serde_json::to_string(&post).unwrap();
         assert_eq!(&expected, &serialized);
     }
+
+    #[test]
+    fn deserializes_what_it_serializes() {
+        let serialized = serde_json::to_string(&fabricate_post()).unwrap();
+        let post: Post = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(&serialized, &serde_json::to_string(&post).unwrap());
+    }
 }
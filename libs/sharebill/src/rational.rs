@@ -2,22 +2,74 @@ extern crate num;
 extern crate regex;
 
 use std::fmt;
+use std::iter;
 use std::ops;
 use std::str::FromStr;
 
-use self::num::{BigRational, One, ToPrimitive, Zero};
+use self::num::{BigInt, BigRational, Integer, One, Signed, ToPrimitive, Zero};
 use self::regex::Regex;
 
 quick_error! {
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Eq)]
     pub enum ParseRationalError {
-        InvalidRationalNumber
+        Empty {
+            display("cannot parse a number from an empty string")
+        }
+        NoDigits {
+            display("expected at least one digit")
+        }
+        UnexpectedCharacter(c: char, position: usize) {
+            display("unexpected character {:?} at position {}", c, position)
+        }
+        MultipleDecimalSeparators {
+            display("more than one decimal separator")
+        }
+        InvalidExponent {
+            display("invalid exponent")
+        }
+        ExponentOutOfRange {
+            display("exponent out of range, the limit is {}", MAX_EXPONENT)
+        }
+        ZeroDenominator {
+            display("the denominator of a fraction cannot be zero")
+        }
     }
 }
 
+/// Larger exponents are almost certainly typos, and would make for
+/// needlessly huge numbers
+const MAX_EXPONENT: i32 = 64;
+
+/// Currency decorations that `Rational::parse_amount` accepts around an
+/// amount. Longer entries must come first, so `kr.` is stripped whole
+const CURRENCY_MARKERS: &[&str] = &[
+    "kr.", "kr", "nok", "sek", "dkk", "eur", "usd", "gbp", "$", "€", "£",
+];
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct Rational(pub num::BigRational);
 
+/// How to get rid of the digits that do not fit when converting to a
+/// fixed number of decimals
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Rounding {
+    /// Towards negative infinity
+    Floor,
+
+    /// Towards positive infinity
+    Ceil,
+
+    /// Towards zero, discarding the extra digits
+    Truncate,
+
+    /// To the nearest, with halves away from zero
+    HalfUp,
+
+    /// To the nearest, with halves to the even neighbour. This is unbiased,
+    /// which makes it a good choice for distributing sums
+    HalfEven,
+}
+
 impl Rational {
     pub fn from_cents(cents: i32) -> Rational {
         Rational(num::BigRational::new(cents.into(), 100.into()))
@@ -26,6 +78,75 @@ impl Rational {
     pub fn to_f64(&self) -> f64 {
         self.0.numer().to_f64().unwrap() / self.0.denom().to_f64().unwrap()
     }
+
+    /// Returns `percent` percent of this number
+    pub fn percent(&self, percent: &Rational) -> Rational {
+        Rational(&self.0 * &percent.0 / BigRational::from_integer(100.into()))
+    }
+
+    /// Returns how many percent this number is of `whole`, or `None`
+    /// if `whole` is zero
+    pub fn as_percentage_of(&self, whole: &Rational) -> Option<Rational> {
+        if whole.is_zero() {
+            None
+        } else {
+            Some(Rational(&self.0 * BigRational::from_integer(100.into()) / &whole.0))
+        }
+    }
+
+    /// Returns this number multiplied by `10^places` and rounded to an integer
+    fn round_scaled(&self, places: usize, rounding: Rounding) -> BigInt {
+        let scale = BigRational::from_integer(num::pow(BigInt::from(10), places));
+        let x = &self.0 * scale;
+
+        let rounded = match rounding {
+            Rounding::Floor => x.floor(),
+            Rounding::Ceil => x.ceil(),
+            Rounding::Truncate => x.trunc(),
+            Rounding::HalfUp => x.round(),
+            Rounding::HalfEven => {
+                let floor = x.floor();
+                let half = BigRational::new(1.into(), 2.into());
+                let fraction = &x - &floor;
+
+                if fraction > half || (fraction == half && floor.to_integer().is_odd()) {
+                    floor + BigRational::one()
+                } else {
+                    floor
+                }
+            }
+        };
+
+        rounded.to_integer()
+    }
+
+    /// Converts to whole cents, that is hundredths.
+    ///
+    /// Panics if the result does not fit in an `i64`.
+    pub fn to_cents(&self, rounding: Rounding) -> i64 {
+        self.checked_to_cents(rounding).expect("Amount out of range for cents")
+    }
+
+    /// Converts to whole cents like `to_cents`, or returns `None` if the
+    /// result does not fit in an `i64`
+    pub fn checked_to_cents(&self, rounding: Rounding) -> Option<i64> {
+        self.round_scaled(2, rounding).to_i64()
+    }
+
+    /// Formats as a decimal number with the given number of decimals,
+    /// such as `12.50`
+    pub fn to_decimal_string(&self, places: usize, rounding: Rounding) -> String {
+        let scaled = self.round_scaled(places, rounding);
+        let sign = if scaled.is_negative() { "-" } else { "" };
+        let digits = format!("{:0>width$}", scaled.abs(), width = places + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - places);
+
+        if places == 0 {
+            format!("{}{}", sign, whole)
+        } else {
+            format!("{}{}.{}", sign, whole, fraction)
+        }
+    }
 }
 
 lazy_static! {
@@ -34,24 +155,154 @@ lazy_static! {
     };
 }
 
+/// Parses a fraction such as `3/4` or `-3/4`, which the caller has
+/// checked to consist of digits on both sides of the slash
+fn parse_fraction(fraction: &str) -> Result<BigRational, ParseRationalError> {
+    let mut split = fraction.splitn(2, '/');
+    let numer = split.next().unwrap().parse::<BigInt>().unwrap();
+    let denom = split.next().unwrap().parse::<BigInt>().unwrap();
+
+    if denom.is_zero() {
+        return Err(ParseRationalError::ZeroDenominator);
+    }
+
+    Ok(BigRational::new(numer, denom))
+}
+
+/// Parses a decimal number such as `12.50`, `-0,3` or `1e2`. Both `.`
+/// and `,` are accepted as the decimal separator
+fn parse_decimal(number: &str) -> Result<BigRational, ParseRationalError> {
+    let (negative, start) = match number.chars().next() {
+        Some('-') => (true, 1),
+        Some('+') => (false, 1),
+        _ => (false, 0),
+    };
+
+    let mut digits = String::new();
+    let mut decimals = 0i32;
+    let mut seen_separator = false;
+    let mut exponent_at = None;
+
+    for (position, c) in number.char_indices().skip(start) {
+        match c {
+            c if c.is_ascii_digit() => {
+                digits.push(c);
+                if seen_separator {
+                    decimals += 1;
+                }
+            }
+            '.' | ',' if seen_separator => {
+                return Err(ParseRationalError::MultipleDecimalSeparators);
+            }
+            '.' | ',' => seen_separator = true,
+            'e' | 'E' if !digits.is_empty() => {
+                exponent_at = Some(position);
+                break;
+            }
+            c => return Err(ParseRationalError::UnexpectedCharacter(c, position)),
+        }
+    }
+
+    if digits.is_empty() {
+        return Err(ParseRationalError::NoDigits);
+    }
+
+    let exponent = match exponent_at {
+        Some(position) => number[position + 1..]
+            .parse::<i32>()
+            .map_err(|_| ParseRationalError::InvalidExponent)?,
+        None => 0,
+    };
+    if exponent.abs() > MAX_EXPONENT {
+        return Err(ParseRationalError::ExponentOutOfRange);
+    }
+
+    // Only ASCII digits were pushed, so this cannot fail
+    let mantissa = digits.parse::<BigInt>().unwrap();
+    let power = exponent - decimals;
+    let scale = num::pow(BigInt::from(10), power.unsigned_abs() as usize);
+
+    let result = if power >= 0 {
+        BigRational::from_integer(mantissa * scale)
+    } else {
+        BigRational::new(mantissa, scale)
+    };
+
+    Ok(if negative { -result } else { result })
+}
+
+impl Rational {
+    /// Parses an amount as written by a human, which may include a
+    /// currency, as in `kr 12,-`, `12,50 kr` or `$12.50`. Anything else is
+    /// parsed as by `from_str`.
+    pub fn parse_amount(amount: &str) -> Result<Rational, ParseRationalError> {
+        let mut amount = amount.trim();
+
+        // Markers are compared in place, ignoring ASCII case only, since
+        // lowercasing other characters can change their length
+        let matches = |part: Option<&str>, marker: &str| match part {
+            Some(part) => part.eq_ignore_ascii_case(marker),
+            None => false,
+        };
+
+        loop {
+            // A marker must not be glued to other letters, or `kroner`
+            // would be taken for `kr` followed by garbage
+            let stripped = CURRENCY_MARKERS.iter().find_map(|marker| {
+                let len = marker.len();
+                if matches(amount.get(..len), marker) {
+                    let rest = &amount[len..];
+                    match rest.chars().next() {
+                        Some(c) if c.is_alphabetic() => None,
+                        _ => Some(rest),
+                    }
+                } else if amount.len() >= len && matches(amount.get(amount.len() - len..), marker) {
+                    let rest = &amount[..amount.len() - len];
+                    match rest.chars().next_back() {
+                        Some(c) if c.is_alphabetic() => None,
+                        _ => Some(rest),
+                    }
+                } else {
+                    None
+                }
+            });
+
+            match stripped {
+                Some(rest) => amount = rest.trim(),
+                None => break,
+            }
+        }
+
+        // `12,-` and `12.-` are common ways of writing whole amounts
+        let amount = amount.trim_end_matches(",-").trim_end_matches(".-");
+
+        amount.parse()
+    }
+}
+
+/// Parses mixed numbers such as `12 1/2`, which is the format used by
+/// Sharebill, as well as fractions and decimal numbers such as `12.50`
 impl FromStr for Rational {
     type Err = ParseRationalError;
 
     fn from_str(number: &str) -> Result<Rational, ParseRationalError> {
-        use self::num::BigRational as BR;
+        let number = number.trim();
+        if number.is_empty() {
+            return Err(ParseRationalError::Empty);
+        }
 
         match MIXED_NUMBER.captures(number) {
             Some(groups) => {
                 // The parsing below must succeed because of the regex match, unwrap is ok.
-                let mut result = BR::zero();
+                let mut result = BigRational::zero();
                 if let Some(x) = groups.at(3) {
-                    result = result + x.parse::<BR>().unwrap();
+                    result = result + x.parse::<BigRational>().unwrap();
                 }
                 if let Some(x) = groups.at(5) {
-                    result = result + x.parse::<BR>().unwrap();
+                    result = result + parse_fraction(x)?;
                 }
                 if let Some(x) = groups.at(6) {
-                    result = result + x.parse::<BR>().unwrap();
+                    result = result + parse_fraction(x)?;
                 }
                 if let Some(_) = groups.at(2) {
                     result = -result;
@@ -59,13 +310,21 @@ impl FromStr for Rational {
 
                 Ok(Rational(result))
             }
-            None => Err(ParseRationalError::InvalidRationalNumber),
+            None => parse_decimal(number).map(Rational),
         }
     }
 }
 
+/// Formats as a mixed number, such as `12 1/2`, which is the format used
+/// by Sharebill. The alternate form, `{:#}`, formats as a decimal number
+/// instead, with two decimals unless another precision is given.
 impl fmt::Display for Rational {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            let places = f.precision().unwrap_or(2);
+            return f.write_str(&self.to_decimal_string(places, Rounding::HalfUp));
+        }
+
         let x = &self.0;
 
         if x.denom() == &num::BigInt::one() {
@@ -77,10 +336,15 @@ impl fmt::Display for Rational {
         if whole.is_zero() {
             write!(f, "{}/{}", &x.numer(), &x.denom())
         } else {
-            let numer = x.numer() - x.denom() * &whole;
-            let denom = x.denom();
-
-            write!(f, "{} {}/{}", &whole, &numer, &denom)
+            // The sign goes in front of the whole number only, as in
+            // `-12 1/2`, which is how it is parsed
+            let abs = x.abs();
+            let whole = abs.to_integer();
+            let numer = abs.numer() - abs.denom() * &whole;
+            let denom = abs.denom();
+            let sign = if x.is_negative() { "-" } else { "" };
+
+            write!(f, "{}{} {}/{}", sign, &whole, &numer, &denom)
         }
     }
 }
@@ -100,16 +364,37 @@ impl<'a> serde::de::Visitor<'a> for RationalVisitor {
     type Value = Rational;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str(
-            "a string for a rational number, matching ^((-)?(\\d+)( (\\d+/\\d+))?|(-?\\d+/\\d+))$",
-        )
+        formatter.write_str("a number, or a string for a rational number, such as \"12 1/2\"")
     }
 
     fn visit_str<E>(self, value: &str) -> Result<Rational, E>
     where
         E: serde::de::Error,
     {
-        Rational::from_str(value).map_err(|_| E::custom("Nope!"))
+        Rational::from_str(value).map_err(E::custom)
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Rational, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Rational::from(value))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Rational, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Rational::from(value))
+    }
+
+    /// Goes by the shortest decimal representation of the number, so
+    /// `0.1` becomes exactly 1/10 rather than the nearest binary fraction
+    fn visit_f64<E>(self, value: f64) -> Result<Rational, E>
+    where
+        E: serde::de::Error,
+    {
+        Rational::from_str(&value.to_string()).map_err(E::custom)
     }
 }
 
@@ -118,7 +403,9 @@ impl<'a> serde::Deserialize<'a> for Rational {
     where
         D: serde::Deserializer<'a>,
     {
-        de.deserialize_str(RationalVisitor)
+        // Sharebill always uses strings, but numbers are handy in
+        // configuration files
+        de.deserialize_any(RationalVisitor)
     }
 }
 
@@ -169,6 +456,27 @@ impl<'a, 'b> ops::Add<&'a Rational> for &'b Rational {
     }
 }
 
+impl ops::Sub<Rational> for Rational {
+    type Output = Rational;
+    fn sub(self, other: Rational) -> Rational {
+        Rational(self.0 - other.0)
+    }
+}
+
+impl<'a> ops::Sub<Rational> for &'a Rational {
+    type Output = Rational;
+    fn sub(self, other: Rational) -> Rational {
+        Rational(&self.0 - other.0)
+    }
+}
+
+impl<'a> ops::Sub<&'a Rational> for Rational {
+    type Output = Rational;
+    fn sub(self, other: &Rational) -> Rational {
+        Rational(self.0 - &other.0)
+    }
+}
+
 impl<'a, 'b> ops::Sub<&'a Rational> for &'b Rational {
     type Output = Rational;
     fn sub(self, other: &Rational) -> Rational {
@@ -176,6 +484,34 @@ impl<'a, 'b> ops::Sub<&'a Rational> for &'b Rational {
     }
 }
 
+impl ops::Mul<Rational> for Rational {
+    type Output = Rational;
+    fn mul(self, other: Rational) -> Rational {
+        Rational(self.0 * other.0)
+    }
+}
+
+impl<'a> ops::Mul<Rational> for &'a Rational {
+    type Output = Rational;
+    fn mul(self, other: Rational) -> Rational {
+        Rational(&self.0 * other.0)
+    }
+}
+
+impl<'a> ops::Mul<&'a Rational> for Rational {
+    type Output = Rational;
+    fn mul(self, other: &Rational) -> Rational {
+        Rational(self.0 * &other.0)
+    }
+}
+
+impl<'a, 'b> ops::Mul<&'a Rational> for &'b Rational {
+    type Output = Rational;
+    fn mul(self, other: &Rational) -> Rational {
+        Rational(&self.0 * &other.0)
+    }
+}
+
 impl ops::Div<Rational> for Rational {
     type Output = Rational;
     fn div(self, other: Rational) -> Rational {
@@ -183,9 +519,88 @@ impl ops::Div<Rational> for Rational {
     }
 }
 
+impl<'a> ops::Div<Rational> for &'a Rational {
+    type Output = Rational;
+    fn div(self, other: Rational) -> Rational {
+        Rational(&self.0 / other.0)
+    }
+}
+
+impl<'a> ops::Div<&'a Rational> for Rational {
+    type Output = Rational;
+    fn div(self, other: &Rational) -> Rational {
+        Rational(self.0 / &other.0)
+    }
+}
+
+impl<'a, 'b> ops::Div<&'a Rational> for &'b Rational {
+    type Output = Rational;
+    fn div(self, other: &Rational) -> Rational {
+        Rational(&self.0 / &other.0)
+    }
+}
+
+impl ops::Neg for Rational {
+    type Output = Rational;
+    fn neg(self) -> Rational {
+        Rational(-self.0)
+    }
+}
+
+impl<'a> ops::Neg for &'a Rational {
+    type Output = Rational;
+    fn neg(self) -> Rational {
+        Rational(-&self.0)
+    }
+}
+
+impl ops::AddAssign<Rational> for Rational {
+    fn add_assign(&mut self, other: Rational) {
+        self.0 = &self.0 + other.0;
+    }
+}
+
+impl<'a> ops::AddAssign<&'a Rational> for Rational {
+    fn add_assign(&mut self, other: &Rational) {
+        self.0 = &self.0 + &other.0;
+    }
+}
+
+impl ops::SubAssign<Rational> for Rational {
+    fn sub_assign(&mut self, other: Rational) {
+        self.0 = &self.0 - other.0;
+    }
+}
+
+impl<'a> ops::SubAssign<&'a Rational> for Rational {
+    fn sub_assign(&mut self, other: &Rational) {
+        self.0 = &self.0 - &other.0;
+    }
+}
+
+impl One for Rational {
+    fn one() -> Rational {
+        Rational(BigRational::one())
+    }
+}
+
+impl iter::Sum<Rational> for Rational {
+    fn sum<I: Iterator<Item = Rational>>(iter: I) -> Rational {
+        iter.fold(Rational::zero(), |acc, x| acc + x)
+    }
+}
+
+impl<'a> iter::Sum<&'a Rational> for Rational {
+    fn sum<I: Iterator<Item = &'a Rational>>(iter: I) -> Rational {
+        iter.fold(Rational::zero(), |acc, x| acc + x)
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::num::Zero;
+    extern crate serde_json;
+
+    use super::num::{Signed, Zero};
     use super::*;
 
     #[test]
@@ -260,4 +675,246 @@ mod test {
         let (a, b) = fabricate_to_add();
         assert_eq!("5/6".parse::<Rational>().unwrap(), &a + &b);
     }
+
+    #[test]
+    fn sub_m_m() {
+        let (a, b) = fabricate_to_add();
+        assert_eq!("1/6".parse::<Rational>().unwrap(), a - b);
+    }
+
+    #[test]
+    fn sub_m_b() {
+        let (a, b) = fabricate_to_add();
+        assert_eq!("1/6".parse::<Rational>().unwrap(), a - &b);
+    }
+
+    #[test]
+    fn sub_b_m() {
+        let (a, b) = fabricate_to_add();
+        assert_eq!("1/6".parse::<Rational>().unwrap(), &a - b);
+    }
+
+    #[test]
+    fn mul_b_b() {
+        let (a, b) = fabricate_to_add();
+        assert_eq!("1/6".parse::<Rational>().unwrap(), &a * &b);
+    }
+
+    #[test]
+    fn percent() {
+        let r = Rational::from(250);
+        assert_eq!(Rational::from(50), r.percent(&Rational::from(20)));
+        assert_eq!(
+            Some(Rational::from(20)),
+            Rational::from(50).as_percentage_of(&r)
+        );
+        assert_eq!(None, r.as_percentage_of(&Rational::zero()));
+    }
+
+    #[test]
+    fn display_negative_mixed_number() {
+        let r = "-12.50".parse::<Rational>().unwrap();
+        assert_eq!("-12 1/2", format!("{}", r));
+        assert_eq!(Ok(r), "-12 1/2".parse::<Rational>());
+        assert_eq!("-1/2", format!("{}", "-0.5".parse::<Rational>().unwrap()));
+    }
+
+    #[test]
+    fn checked_to_cents_out_of_range() {
+        let cents =
+            |amount: &str| amount.parse::<Rational>().unwrap().checked_to_cents(Rounding::HalfUp);
+        assert_eq!(Some(1250), cents("12.50"));
+        assert_eq!(None, cents("1e30"));
+    }
+
+    #[test]
+    fn to_cents_rounding() {
+        let r = "1/8".parse::<Rational>().unwrap(); // 12.5 cents
+        assert_eq!(12, r.to_cents(Rounding::Floor));
+        assert_eq!(13, r.to_cents(Rounding::Ceil));
+        assert_eq!(12, r.to_cents(Rounding::Truncate));
+        assert_eq!(13, r.to_cents(Rounding::HalfUp));
+        assert_eq!(12, r.to_cents(Rounding::HalfEven));
+
+        let r = -r;
+        assert_eq!(-13, r.to_cents(Rounding::Floor));
+        assert_eq!(-12, r.to_cents(Rounding::Ceil));
+        assert_eq!(-12, r.to_cents(Rounding::Truncate));
+        assert_eq!(-13, r.to_cents(Rounding::HalfUp));
+        assert_eq!(-12, r.to_cents(Rounding::HalfEven));
+    }
+
+    #[test]
+    fn format_decimal() {
+        assert_eq!("12.50", format!("{:#}", "12 1/2".parse::<Rational>().unwrap()));
+        assert_eq!("-0.33", format!("{:#}", "-1/3".parse::<Rational>().unwrap()));
+        assert_eq!("0.667", format!("{:#.3}", "2/3".parse::<Rational>().unwrap()));
+        assert_eq!("-1", format!("{:#.0}", "-1/2".parse::<Rational>().unwrap()));
+        assert_eq!("0.05", format!("{:#}", Rational::from_cents(5)));
+    }
+
+    #[test]
+    fn parse_decimal() {
+        assert_eq!(Rational::from_cents(1250), "12.50".parse::<Rational>().unwrap());
+        assert_eq!(Rational::from_cents(-30), "-0.3".parse::<Rational>().unwrap());
+        assert_eq!(Rational::from_cents(1250), "12,5".parse::<Rational>().unwrap());
+        assert_eq!(Rational::from_cents(50), ".5".parse::<Rational>().unwrap());
+        assert_eq!(Rational::from(100), "1e2".parse::<Rational>().unwrap());
+        assert_eq!(Rational::from_cents(125), "+1.25e0".parse::<Rational>().unwrap());
+        assert_eq!(Rational::from_cents(1), "1E-2".parse::<Rational>().unwrap());
+    }
+
+    #[test]
+    fn parse_amount() {
+        let twelve = Rational::from(12);
+        assert_eq!(twelve, Rational::parse_amount("kr 12,-").unwrap());
+        assert_eq!(twelve, Rational::parse_amount("12,- kr").unwrap());
+        assert_eq!(twelve, Rational::parse_amount("NOK 12").unwrap());
+        assert_eq!(twelve, Rational::parse_amount("$12.00").unwrap());
+        assert_eq!(twelve, Rational::parse_amount(" 12 € ").unwrap());
+        assert_eq!(Rational::from_cents(1250), Rational::parse_amount("12,50 kr.").unwrap());
+        assert_eq!(
+            "12 1/2".parse::<Rational>().unwrap(),
+            Rational::parse_amount("12 1/2").unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        use super::ParseRationalError::*;
+
+        assert_eq!(Err(Empty), "".parse::<Rational>());
+        assert_eq!(Err(NoDigits), "-".parse::<Rational>());
+        assert_eq!(Err(NoDigits), ".".parse::<Rational>());
+        assert_eq!(Err(UnexpectedCharacter('x', 2)), "12x".parse::<Rational>());
+        assert_eq!(Err(MultipleDecimalSeparators), "1.000,50".parse::<Rational>());
+        assert_eq!(Err(InvalidExponent), "1e".parse::<Rational>());
+        assert_eq!(Err(ExponentOutOfRange), "1e1000".parse::<Rational>());
+        assert_eq!(Err(ZeroDenominator), "1/0".parse::<Rational>());
+        assert_eq!(Err(ZeroDenominator), "1 1/0".parse::<Rational>());
+        assert_eq!(Err(UnexpectedCharacter('k', 0)), Rational::parse_amount("kroner 12"));
+        assert!(Rational::parse_amount("\u{212a}r 12").is_err());
+        assert!(Rational::parse_amount("12 \u{212a}r").is_err());
+    }
+
+    #[test]
+    fn deserialize_numbers() {
+        assert_eq!(Rational::from(12), serde_json::from_str::<Rational>("12").unwrap());
+        assert_eq!(
+            "1/10".parse::<Rational>().unwrap(),
+            serde_json::from_str::<Rational>("0.1").unwrap()
+        );
+    }
+
+    #[test]
+    fn deserialize_reports_parse_error() {
+        let err = serde_json::from_str::<Rational>("\"12x\"").unwrap_err();
+        assert!(format!("{}", err).contains("unexpected character 'x' at position 2"));
+    }
+
+    impl quickcheck::Arbitrary for Rational {
+        fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Rational {
+            let numer = i32::arbitrary(g);
+            let denom = match i32::arbitrary(g) {
+                0 => 1,
+                x => x,
+            };
+            Rational(BigRational::new(numer.into(), denom.into()))
+        }
+    }
+
+    quickcheck! {
+        fn prop_add_commutes(a: Rational, b: Rational) -> bool {
+            &a + &b == &b + &a
+        }
+
+        fn prop_sub_inverts_add(a: Rational, b: Rational) -> bool {
+            (&a + &b) - b == a
+        }
+
+        fn prop_neg_inverts_add(a: Rational) -> bool {
+            (&a + -&a).is_zero()
+        }
+
+        fn prop_mul_commutes(a: Rational, b: Rational) -> bool {
+            &a * &b == &b * &a
+        }
+
+        fn prop_mul_distributes(a: Rational, b: Rational, c: Rational) -> bool {
+            &a * (&b + &c) == &a * &b + &a * &c
+        }
+
+        fn prop_div_inverts_mul(a: Rational, b: Rational) -> bool {
+            b.is_zero() || (&a * &b) / b == a
+        }
+
+        fn prop_one_is_identity(a: Rational) -> bool {
+            &a * Rational::one() == a
+        }
+
+        fn prop_sum_is_repeated_add(xs: Vec<Rational>) -> bool {
+            let by_ref: Rational = xs.iter().sum();
+            let by_value: Rational = xs.clone().into_iter().sum();
+            let folded = xs.iter().fold(Rational::zero(), |acc, x| acc + x);
+            by_ref == folded && by_value == folded
+        }
+
+        fn prop_assign_ops_match_binary_ops(a: Rational, b: Rational) -> bool {
+            let mut added = a.clone();
+            added += &b;
+            let mut subtracted = a.clone();
+            subtracted -= b.clone();
+            added == &a + &b && subtracted == &a - &b
+        }
+
+        fn prop_percentage_round_trips(a: Rational, whole: Rational) -> bool {
+            match a.as_percentage_of(&whole) {
+                Some(percent) => whole.percent(&percent) == a,
+                None => whole.is_zero(),
+            }
+        }
+
+        fn prop_display_round_trips(a: Rational) -> bool {
+            a.to_string().parse::<Rational>() == Ok(a)
+        }
+
+        fn prop_display_round_trips_for_negative_values(a: Rational) -> bool {
+            let negative = Rational(-a.0.abs());
+            negative.to_string().parse::<Rational>() == Ok(negative)
+        }
+
+        fn prop_decimal_string_round_trips(cents: i32) -> bool {
+            let r = Rational::from_cents(cents);
+            format!("{:#}", r).parse::<Rational>() == Ok(r)
+        }
+
+        fn prop_to_cents_is_exact_for_cents(cents: i32) -> bool {
+            let r = Rational::from_cents(cents);
+            [Rounding::Floor, Rounding::Ceil, Rounding::Truncate, Rounding::HalfUp, Rounding::HalfEven]
+                .iter()
+                .all(|&rounding| r.to_cents(rounding) == cents as i64)
+        }
+
+        fn prop_to_cents_rounds_within_a_cent(a: Rational) -> bool {
+            let floor = Rational::from_cents(a.to_cents(Rounding::Floor) as i32);
+            let ceil = Rational::from_cents(a.to_cents(Rounding::Ceil) as i32);
+            let half_cent = Rational::from_cents(1) / Rational::from(2);
+
+            let within_half_cent = |rounding| {
+                let rounded = Rational::from_cents(a.to_cents(rounding) as i32);
+                (&rounded - &a).0.abs() <= half_cent.0
+            };
+
+            floor <= a && a <= ceil && &ceil - &floor <= Rational::from_cents(1)
+                && within_half_cent(Rounding::HalfUp)
+                && within_half_cent(Rounding::HalfEven)
+        }
+
+        fn prop_decimal_display_matches_cents(cents: i32) -> bool {
+            let sign = if cents < 0 { "-" } else { "" };
+            let abs = (cents as i64).abs();
+            format!("{:#}", Rational::from_cents(cents))
+                == format!("{}{}.{:02}", sign, abs / 100, abs % 100)
+        }
+    }
 }
@@ -0,0 +1,20 @@
+PRAGMA foreign_keys=OFF;
+
+CREATE TABLE new_orders (
+    id INTEGER PRIMARY KEY NOT NULL,
+    menu INTEGER NOT NULL,
+    overhead_in_cents INTEGER NOT NULL,
+    opened INTEGER NOT NULL,
+    closed INTEGER,
+    FOREIGN KEY(menu) REFERENCES menus(id)
+);
+
+INSERT INTO new_orders
+    SELECT id, menu, overhead_in_cents, opened, closed FROM orders;
+
+DROP TABLE orders;
+ALTER TABLE new_orders RENAME TO orders;
+
+PRAGMA foreign_key_check;
+
+PRAGMA foreign_keys=ON;
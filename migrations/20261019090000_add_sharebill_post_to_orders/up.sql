@@ -0,0 +1,5 @@
+-- The Sharebill post id and payload are stored before the post is sent,
+-- so a retry after a lost response reuses them instead of billing twice
+ALTER TABLE orders ADD COLUMN sharebill_post_id TEXT;
+ALTER TABLE orders ADD COLUMN sharebill_payload TEXT;
+ALTER TABLE orders ADD COLUMN billed_post_url TEXT;
@@ -0,0 +1,3 @@
+DROP INDEX ledger_entries_account;
+DROP TABLE ledger_entries;
+DROP TABLE ledger_transactions;
@@ -0,0 +1,25 @@
+CREATE TABLE ledger_transactions (
+    id INTEGER PRIMARY KEY NOT NULL,
+    'order' INTEGER,
+    description TEXT NOT NULL,
+
+    -- created is Unix time. It should have been
+    -- DATETIME, but Diesel does not support that
+    created INTEGER NOT NULL,
+
+    FOREIGN KEY('order') REFERENCES orders(id)
+);
+
+CREATE TABLE ledger_entries (
+    id INTEGER PRIMARY KEY NOT NULL,
+    'transaction' INTEGER NOT NULL,
+    account TEXT NOT NULL COLLATE NOCASE,
+
+    -- amount is an exact rational number in the format used by Sharebill,
+    -- such as "12 1/3". Credits are positive and debits negative
+    amount TEXT NOT NULL,
+
+    FOREIGN KEY('transaction') REFERENCES ledger_transactions(id)
+);
+
+CREATE INDEX ledger_entries_account ON ledger_entries (account);
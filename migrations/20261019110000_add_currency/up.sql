@@ -0,0 +1,8 @@
+-- An ISO 4217 code such as EUR. NULL means the home currency, which
+-- is the one the ledger and Sharebill are kept in
+ALTER TABLE restaurants ADD COLUMN currency TEXT;
+
+-- A manually entered exchange rate, as a rational number of home
+-- currency units per unit of the restaurant's currency. Overrides any
+-- configured rate
+ALTER TABLE orders ADD COLUMN exchange_rate TEXT;
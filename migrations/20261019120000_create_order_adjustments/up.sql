@@ -0,0 +1,17 @@
+CREATE TABLE order_adjustments (
+    id INTEGER PRIMARY KEY NOT NULL,
+    'order' INTEGER NOT NULL,
+
+    -- fixed: amount is split evenly between everyone in the order
+    -- percentage: amount is a percentage of what each person ordered
+    -- per_person: amount is added for each person in the order
+    kind TEXT NOT NULL CHECK (kind IN ('fixed', 'percentage', 'per_person')),
+
+    -- amount is an exact rational number, such as "12 1/2". Discounts
+    -- and coupons are negative
+    amount TEXT NOT NULL,
+
+    description TEXT NOT NULL,
+
+    FOREIGN KEY('order') REFERENCES orders(id)
+);
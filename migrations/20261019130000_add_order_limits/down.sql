@@ -0,0 +1,40 @@
+PRAGMA foreign_keys=OFF;
+
+CREATE TABLE new_restaurants (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT UNIQUE NOT NULL COLLATE NOCASE,
+    currency TEXT
+);
+
+INSERT INTO new_restaurants
+    SELECT id, name, currency FROM restaurants;
+
+DROP TABLE restaurants;
+ALTER TABLE new_restaurants RENAME TO restaurants;
+
+CREATE TABLE new_orders (
+    id INTEGER PRIMARY KEY NOT NULL,
+    menu INTEGER NOT NULL,
+    overhead_in_cents INTEGER NOT NULL,
+    opened INTEGER NOT NULL,
+    closed INTEGER,
+    sharebill_post_id TEXT,
+    sharebill_payload TEXT,
+    billed_post_url TEXT,
+    exchange_rate TEXT,
+    FOREIGN KEY(menu) REFERENCES menus(id)
+);
+
+INSERT INTO new_orders
+    SELECT
+        id, menu, overhead_in_cents, opened, closed,
+        sharebill_post_id, sharebill_payload, billed_post_url,
+        exchange_rate
+    FROM orders;
+
+DROP TABLE orders;
+ALTER TABLE new_orders RENAME TO orders;
+
+PRAGMA foreign_key_check;
+
+PRAGMA foreign_keys=ON;
@@ -0,0 +1,8 @@
+-- Defaults for new orders from the restaurant. The minimum is the
+-- smallest total the restaurant accepts, and the budget is what each
+-- person may spend. Both are in the restaurant's currency
+ALTER TABLE restaurants ADD COLUMN minimum_order_in_cents INTEGER;
+ALTER TABLE restaurants ADD COLUMN budget_per_person_in_cents INTEGER;
+
+ALTER TABLE orders ADD COLUMN minimum_order_in_cents INTEGER;
+ALTER TABLE orders ADD COLUMN budget_per_person_in_cents INTEGER;
@@ -0,0 +1,2 @@
+-- When the order was last emailed to the restaurant
+ALTER TABLE orders ADD COLUMN sent INTEGER;
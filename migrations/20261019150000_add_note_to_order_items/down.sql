@@ -0,0 +1,20 @@
+PRAGMA foreign_keys=OFF;
+
+CREATE TABLE new_order_items (
+    id INTEGER PRIMARY KEY NOT NULL,
+    'order' INTEGER NOT NULL,
+    person_name TEXT NOT NULL,
+    menu_item INTEGER NOT NULL,
+    FOREIGN KEY('order') REFERENCES orders(id),
+    FOREIGN KEY(menu_item) REFERENCES menu_items(id)
+);
+
+INSERT INTO new_order_items
+    SELECT id, "order", person_name, menu_item FROM order_items;
+
+DROP TABLE order_items;
+ALTER TABLE new_order_items RENAME TO order_items;
+
+PRAGMA foreign_key_check;
+
+PRAGMA foreign_keys=ON;
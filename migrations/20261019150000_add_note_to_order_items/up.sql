@@ -0,0 +1,2 @@
+-- Free text for the restaurant, like "no wasabi"
+ALTER TABLE order_items ADD COLUMN note TEXT;
@@ -0,0 +1,21 @@
+PRAGMA foreign_keys=OFF;
+
+CREATE TABLE new_restaurants (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT UNIQUE NOT NULL COLLATE NOCASE,
+    currency TEXT,
+    minimum_order_in_cents INTEGER,
+    budget_per_person_in_cents INTEGER
+);
+
+INSERT INTO new_restaurants
+    SELECT
+        id, name, currency, minimum_order_in_cents, budget_per_person_in_cents
+    FROM restaurants;
+
+DROP TABLE restaurants;
+ALTER TABLE new_restaurants RENAME TO restaurants;
+
+PRAGMA foreign_key_check;
+
+PRAGMA foreign_keys=ON;
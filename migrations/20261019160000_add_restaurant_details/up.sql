@@ -0,0 +1,6 @@
+-- Archived restaurants are hidden when ordering, but kept for the history
+ALTER TABLE restaurants ADD COLUMN archived BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE restaurants ADD COLUMN website TEXT;
+ALTER TABLE restaurants ADD COLUMN address TEXT;
+ALTER TABLE restaurants ADD COLUMN cuisine TEXT;
+ALTER TABLE restaurants ADD COLUMN notes TEXT;
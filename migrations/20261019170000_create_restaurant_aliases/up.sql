@@ -0,0 +1,5 @@
+CREATE TABLE restaurant_aliases (
+    alias TEXT PRIMARY KEY NOT NULL COLLATE NOCASE,
+    restaurant INTEGER NOT NULL,
+    FOREIGN KEY(restaurant) REFERENCES restaurants(id)
+);
@@ -0,0 +1,21 @@
+PRAGMA foreign_keys=OFF;
+
+CREATE TABLE new_menu_items (
+    id INTEGER PRIMARY KEY NOT NULL,
+    menu INTEGER NOT NULL,
+    'number' INTEGER NOT NULL,
+    name TEXT NOT NULL COLLATE NOCASE,
+    price_in_cents INTEGER NOT NULL,
+    FOREIGN KEY(menu) REFERENCES menus(id),
+    UNIQUE (menu, 'number')
+);
+
+INSERT INTO new_menu_items
+    SELECT id, menu, "number", name, price_in_cents FROM menu_items;
+
+DROP TABLE menu_items;
+ALTER TABLE new_menu_items RENAME TO menu_items;
+
+PRAGMA foreign_key_check;
+
+PRAGMA foreign_keys=ON;
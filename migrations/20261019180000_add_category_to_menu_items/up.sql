@@ -0,0 +1,2 @@
+-- The heading the item was listed under, which menu search also looks at
+ALTER TABLE menu_items ADD COLUMN category TEXT;
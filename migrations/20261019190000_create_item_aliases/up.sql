@@ -0,0 +1,9 @@
+-- Nicknames for menu items. They refer to the item number rather than
+-- the menu item, so they keep working when the menu is imported again
+CREATE TABLE item_aliases (
+    restaurant INTEGER NOT NULL,
+    alias TEXT NOT NULL COLLATE NOCASE,
+    number INTEGER NOT NULL,
+    FOREIGN KEY(restaurant) REFERENCES restaurants(id),
+    PRIMARY KEY(restaurant, alias)
+);
@@ -0,0 +1,16 @@
+-- The shortlist someone was shown when their order was ambiguous, so
+-- they can answer with the number of the item they meant
+CREATE TABLE pending_choices (
+    person_name TEXT PRIMARY KEY NOT NULL COLLATE NOCASE,
+    'order' INTEGER NOT NULL,
+
+    -- numbers is the menu numbers of the shortlist, in the order they
+    -- were shown, separated by commas
+    numbers TEXT NOT NULL,
+    note TEXT,
+
+    -- created is Unix time
+    created INTEGER NOT NULL,
+
+    FOREIGN KEY('order') REFERENCES orders(id)
+);
@@ -0,0 +1,15 @@
+-- The changes people have made to orders, most recent last, so they can
+-- be undone
+CREATE TABLE journal_entries (
+    id INTEGER PRIMARY KEY NOT NULL,
+    person_name TEXT NOT NULL COLLATE NOCASE,
+    'order' INTEGER NOT NULL,
+
+    -- created is Unix time
+    created INTEGER NOT NULL,
+
+    -- undo is how to undo the change, as JSON
+    undo TEXT NOT NULL,
+
+    FOREIGN KEY('order') REFERENCES orders(id)
+);
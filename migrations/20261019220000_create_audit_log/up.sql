@@ -0,0 +1,27 @@
+-- Every command run and every change made through the web pages
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY NOT NULL,
+
+    -- created is Unix time
+    created INTEGER NOT NULL,
+
+    frontend TEXT NOT NULL CHECK (frontend IN ('slack', 'matrix', 'web')),
+
+    -- real_user ran the command as effective_user, which differs for sudo.
+    -- For the web pages, both are the address of the client
+    real_user TEXT NOT NULL,
+    effective_user TEXT NOT NULL,
+
+    -- order is the order that was open when the command ran, if any
+    'order' INTEGER,
+
+    command TEXT NOT NULL,
+    args TEXT NOT NULL,
+
+    -- outcome is ok or the error, as text
+    outcome TEXT NOT NULL,
+
+    FOREIGN KEY('order') REFERENCES orders(id)
+);
+
+CREATE INDEX audit_log_order ON audit_log ('order');
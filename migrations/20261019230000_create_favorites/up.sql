@@ -0,0 +1,13 @@
+-- Named sets of items people like to order from a restaurant, one row per
+-- item. Items are given by number, so favorites survive new menus
+CREATE TABLE favorites (
+    id INTEGER PRIMARY KEY NOT NULL,
+    person_name TEXT NOT NULL COLLATE NOCASE,
+    restaurant INTEGER NOT NULL,
+    name TEXT NOT NULL COLLATE NOCASE,
+    number INTEGER NOT NULL,
+    note TEXT,
+    FOREIGN KEY(restaurant) REFERENCES restaurants(id)
+);
+
+CREATE INDEX favorites_person_name ON favorites (person_name, restaurant);
@@ -0,0 +1,25 @@
+-- Polls for deciding where to order from. At most one poll is open, that
+-- is without closed, at a time
+CREATE TABLE polls (
+    id INTEGER PRIMARY KEY NOT NULL,
+    started_by TEXT NOT NULL,
+    opened INTEGER NOT NULL,
+    closed INTEGER
+);
+
+CREATE TABLE poll_options (
+    poll INTEGER NOT NULL,
+    restaurant INTEGER NOT NULL,
+    PRIMARY KEY(poll, restaurant),
+    FOREIGN KEY(poll) REFERENCES polls(id),
+    FOREIGN KEY(restaurant) REFERENCES restaurants(id)
+);
+
+-- Everyone has one vote per poll, and voting again changes it
+CREATE TABLE poll_votes (
+    poll INTEGER NOT NULL,
+    person_name TEXT NOT NULL COLLATE NOCASE,
+    restaurant INTEGER NOT NULL,
+    PRIMARY KEY(poll, person_name),
+    FOREIGN KEY(poll, restaurant) REFERENCES poll_options(poll, restaurant)
+);
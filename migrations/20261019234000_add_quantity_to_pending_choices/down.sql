@@ -0,0 +1,20 @@
+PRAGMA foreign_keys=OFF;
+
+CREATE TABLE new_pending_choices (
+    person_name TEXT PRIMARY KEY NOT NULL COLLATE NOCASE,
+    'order' INTEGER NOT NULL,
+    numbers TEXT NOT NULL,
+    note TEXT,
+    created INTEGER NOT NULL,
+    FOREIGN KEY('order') REFERENCES orders(id)
+);
+
+INSERT INTO new_pending_choices
+    SELECT person_name, "order", numbers, note, created FROM pending_choices;
+
+DROP TABLE pending_choices;
+ALTER TABLE new_pending_choices RENAME TO pending_choices;
+
+PRAGMA foreign_key_check;
+
+PRAGMA foreign_keys=ON;
@@ -0,0 +1,2 @@
+-- How many of the picked item to order, as given with xN in the order
+ALTER TABLE pending_choices ADD COLUMN quantity INTEGER NOT NULL DEFAULT 1;
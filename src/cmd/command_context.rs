@@ -1,3 +1,4 @@
+use models::Frontend;
 use state;
 use web;
 
@@ -8,4 +9,9 @@ pub struct CommandContext<'a, 'b, 'c, 'd> {
     pub args: &'b str,
     pub user_name: &'c str,
     pub env: &'d web::Env,
+    pub frontend: Frontend,
+
+    /// Who actually ran the command. The same as `user_name`, except for
+    /// commands run with `sudo`
+    pub real_user_name: &'c str,
 }
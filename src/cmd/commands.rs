@@ -1,18 +1,111 @@
+use config;
+use currency::Currency;
+use mail;
+use models::{
+    AdjustmentKind, MenuItem, Order, OrderAdjustmentId, OrderItem, Poll, Restaurant, Undo,
+};
+use ordersheet;
+use search;
+use settlement;
 use state;
 use web;
 
 use itertools::*;
+use sharebill::rational::Rounding;
 use sharebill::Rational;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use super::command_context::CommandContext;
 use super::error::*;
 use super::response::*;
 
+/// Returns the total of `person_name` if it is over the budget of the order
+fn over_budget(
+    state: &state::State,
+    env: &web::Env,
+    order: &Order,
+    person_name: &str,
+) -> Result<Option<OverBudget>, Error> {
+    let budget = match order.budget_per_person_in_cents {
+        Some(budget) => Rational::from_cents(budget),
+        None => return Ok(None),
+    };
+
+    let total = order_totals(state, order)?
+        .persons
+        .into_iter()
+        .find(|x| x.0.to_lowercase() == person_name.to_lowercase())
+        .map(|x| x.1);
+
+    match total {
+        Some(total) if total > budget => Ok(Some(OverBudget {
+            currency: restaurant_currency(env, &order_restaurant(state, order)?),
+            total,
+            budget,
+        })),
+        _ => Ok(None),
+    }
+}
+
+/// Adds the items, each with an optional note, to the order in one
+/// transaction, unless they put the person over the budget and the budget
+/// is enforced
+fn place_items(
+    state: &state::State,
+    env: &web::Env,
+    order: &Order,
+    user_name: &str,
+    items: Vec<(MenuItem, Option<&str>)>,
+) -> Result<Response, Error> {
+    state.transaction(|| {
+        let mut added = vec![];
+        for &(ref menu_item, note) in items.iter() {
+            added.push(state.add_order_item(order.id, user_name, menu_item.id, note)?);
+        }
+
+        let undo = Undo::RemoveOrderItems(
+            items
+                .iter()
+                .map(|&(ref menu_item, note)| (i32::from(menu_item.id), note.map(|x| x.to_owned())))
+                .collect(),
+        );
+
+        let menu_items = items.into_iter().map(|x| x.0).collect();
+
+        match over_budget(state, env, order, user_name)? {
+            Some(over_budget) if env.enforce_budget => {
+                for order_item_id in added {
+                    state.remove_order_item(order_item_id)?;
+                }
+                Ok(Response::OrderRefused {
+                    menu_items,
+                    over_budget,
+                })
+            }
+            over_budget => {
+                state.record_undo(order.id, user_name, &undo)?;
+
+                Ok(Response::PlacedOrder {
+                    menu_items,
+                    over_budget,
+                })
+            }
+        }
+    })
+}
+
+/// How many of your earlier orders `repeat` chooses from
+const REPEAT_CHOICES: usize = 5;
+
+/// `repeat [N]` repeats your Nth most recent order from the restaurant,
+/// by default the most recent one, and `repeat list` lists them. Orders
+/// with the same items as a more recent one are left out
 fn cmd_repeat(
     &CommandContext {
         state_mutex,
+        args,
         user_name,
+        env,
         ..
     }: &CommandContext,
 ) -> Result<Response, Error> {
@@ -22,28 +115,83 @@ fn cmd_repeat(
         .menu_object(open_order.menu)?
         .expect("Database invariant");
 
-    let menu_items = state.previous_orders(user_name, menu.restaurant)?;
+    let candidates = state.previous_orders(user_name, menu.restaurant, 4 * REPEAT_CHOICES as i64)?;
 
-    let menu_items = menu_items
-        .into_iter()
-        .map(|menu_item| -> Result<_, Error> {
-            let query = state::Query::ExactInteger(menu_item.number);
-            Ok(state.query_menu(open_order.menu, &query)?.pop())
-        })
-        .collect::<Result<Vec<_>, Error>>()?
-        .into_iter()
-        .filter_map(|x| x)
-        .collect::<Vec<_>>();
+    let mut seen = vec![];
+    let mut previous_orders = vec![];
+    for (order, items) in candidates {
+        let mut key = items
+            .iter()
+            .map(|x| (x.0.number, x.1.note.clone()))
+            .collect::<Vec<_>>();
+        key.sort();
+
+        if !seen.contains(&key) {
+            seen.push(key);
+            previous_orders.push((order, items));
+        }
+    }
+    previous_orders.truncate(REPEAT_CHOICES);
 
-    if menu_items.is_empty() {
+    if previous_orders.is_empty() {
         return Ok(Response::RepeatNoMatch);
     }
 
-    for menu_item in menu_items.iter() {
-        state.add_order_item(open_order.id, user_name, menu_item.id)?;
+    let index = match args.trim() {
+        "list" => {
+            return Ok(Response::PreviousOrders {
+                orders: previous_orders
+                    .into_iter()
+                    .map(|(order, items)| PreviousOrder {
+                        closed: order.closed.unwrap_or(0),
+                        menu_items: items.into_iter().map(|x| x.0).collect(),
+                    })
+                    .collect(),
+            })
+        }
+        "" => 1,
+        index => index.parse::<usize>()?,
+    };
+    if index < 1 || index > previous_orders.len() {
+        return Err(Error::InvalidChoice(previous_orders.len()));
+    }
+    let (_, previous_items) = previous_orders.swap_remove(index - 1);
+
+    // Menu numbers are reused when menus change, so an item is only taken
+    // to be the same if it also has the same price
+    let current_menu = state.menu(open_order.menu)?;
+    let mut items = vec![];
+    let mut misses = vec![];
+    for (previous, order_item) in previous_items {
+        let current = current_menu
+            .iter()
+            .find(|x| x.number == previous.number && x.price_in_cents == previous.price_in_cents);
+
+        match current {
+            Some(current) => items.push((current.clone(), order_item.note)),
+            None => misses.push(RepeatMiss {
+                replacement: search::rank(&previous.name, current_menu.clone())
+                    .into_iter()
+                    .next()
+                    .map(|x| x.menu_item),
+                previous,
+            }),
+        }
+    }
+
+    if !misses.is_empty() {
+        return Ok(Response::RepeatChanged {
+            currency: restaurant_currency(env, &order_restaurant(&state, &open_order)?),
+            unchanged: items.into_iter().map(|x| x.0).collect(),
+            misses,
+        });
     }
 
-    Ok(Response::PlacedOrder { menu_items })
+    let items = items
+        .iter()
+        .map(|x| (x.0.clone(), x.1.as_ref().map(|x| x.as_str())))
+        .collect();
+    place_items(&state, env, &open_order, user_name, items)
 }
 
 fn cmd_restaurants(
@@ -55,413 +203,1952 @@ fn cmd_restaurants(
     Ok(Response::Restaurants { restaurants })
 }
 
+/// Picks `min=AMOUNT` and `budget=AMOUNT` out of `args` and returns the
+/// remaining words. An amount of `none` removes the limit
+fn parse_limits(
+    args: &str,
+    minimum: &mut Option<i32>,
+    budget: &mut Option<i32>,
+) -> Result<String, Error> {
+    let mut rest = vec![];
+
+    for word in args.split_whitespace() {
+        let (limit, value) = if word.starts_with("min=") {
+            (&mut *minimum, &word["min=".len()..])
+        } else if word.starts_with("budget=") {
+            (&mut *budget, &word["budget=".len()..])
+        } else {
+            rest.push(word);
+            continue;
+        };
+
+        *limit = match value {
+            "none" => None,
+            value => Some(parse_cents(value)?),
+        };
+    }
+
+    Ok(rest.join(" "))
+}
+
+/// Parses an amount as written by a human, as by `Rational::parse_amount`,
+/// into cents that fit in the database
+fn parse_cents(amount: &str) -> Result<i32, Error> {
+    use num::ToPrimitive;
+
+    Rational::parse_amount(amount)?
+        .checked_to_cents(Rounding::HalfUp)
+        .and_then(|cents| cents.to_i32())
+        .ok_or(Error::InputError)
+}
+
 fn cmd_openorder(
     &CommandContext {
         state_mutex,
         args,
-        env: &web::Env { ref base_url, .. },
+        env,
         ..
     }: &CommandContext,
 ) -> Result<Response, Error> {
     let state = state_mutex.lock()?;
 
-    let restaurant = match state.restaurant_by_name(args)? {
-        Some(resturant) => resturant,
-        None => {
-            return Ok(Response::RestaurantsNoMatch {
-                restaurants: state.restaurants()?,
-            })
-        }
+    let name = parse_limits(args, &mut None, &mut None)?;
+    let retry = |suggestion: &str| {
+        let mut words = vec![suggestion];
+        words.extend(
+            args.split_whitespace()
+                .filter(|x| x.starts_with("min=") || x.starts_with("budget=")),
+        );
+        format!("openorder {}", words.join(" "))
+    };
+    let restaurant = match find_restaurant(&state, &name, &retry)? {
+        Ok(restaurant) => restaurant,
+        Err(response) => return Ok(response),
     };
 
+    // Limits given here override the defaults for the restaurant
+    let mut minimum = restaurant.minimum_order_in_cents;
+    let mut budget = restaurant.budget_per_person_in_cents;
+    parse_limits(args, &mut minimum, &mut budget)?;
+
+    open_order(&state, env, restaurant, minimum, budget)
+}
+
+/// Looks up the restaurant by name, or gives the response to make when it
+/// is not clear which restaurant is meant. `retry` gives the command to run
+/// with a suggested name in place of `name`
+fn find_restaurant(
+    state: &state::State,
+    name: &str,
+    retry: &Fn(&str) -> String,
+) -> Result<Result<Restaurant, Response>, Error> {
+    Ok(match state.find_restaurant(name)? {
+        state::RestaurantMatch::Found(restaurant) => Ok(restaurant),
+        state::RestaurantMatch::Suggestion(restaurant) => Err(Response::RestaurantSuggestion {
+            command: retry(&restaurant.name),
+            restaurant_name: restaurant.name,
+        }),
+        state::RestaurantMatch::Ambiguous(restaurants) => Err(Response::RestaurantAmbiguous {
+            query: name.to_owned(),
+            restaurants,
+        }),
+        state::RestaurantMatch::NoMatch => Err(Response::RestaurantsNoMatch {
+            restaurants: state.restaurants()?,
+        }),
+    })
+}
+
+fn open_order(
+    state: &state::State,
+    env: &web::Env,
+    restaurant: Restaurant,
+    minimum: Option<i32>,
+    budget: Option<i32>,
+) -> Result<Response, Error> {
     let menu = state.current_menu_for_restaurant(restaurant.id)?;
 
-    state.create_order(menu.id)?;
+    state.create_order(menu.id, minimum, budget)?;
 
-    let menu_url = format!("{}menu/{}", base_url, i32::from(menu.id));
+    let phone_numbers = state
+        .phone_numbers(restaurant.id)?
+        .into_iter()
+        .map(|x| x.number)
+        .collect();
+
+    let menu_url = format!("{}menu/{}", env.base_url, i32::from(menu.id));
 
     Ok(Response::OpenedOrder {
         menu_url,
+        currency: restaurant_currency(env, &restaurant),
         restaurant_name: restaurant.name,
+        minimum_order_in_cents: minimum,
+        budget_per_person_in_cents: budget,
+        phone_numbers,
     })
 }
 
-fn cmd_closeorder(&CommandContext { state_mutex, .. }: &CommandContext) -> Result<Response, Error> {
+fn cmd_limits(
+    &CommandContext {
+        state_mutex,
+        args,
+        env,
+        ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
     let state = state_mutex.lock()?;
+    let open_order = state.demand_open_order()?;
+    let restaurant = order_restaurant(&state, &open_order)?;
+    let currency = restaurant_currency(env, &restaurant);
+
+    let mut minimum = open_order.minimum_order_in_cents;
+    let mut budget = open_order.budget_per_person_in_cents;
+
+    if args.trim().len() == 0 {
+        return Ok(Response::Limits {
+            currency,
+            minimum_order_in_cents: minimum,
+            budget_per_person_in_cents: budget,
+        });
+    }
 
-    state.close_current_order()?;
+    if parse_limits(args, &mut minimum, &mut budget)?.len() != 0 {
+        return Err(Error::InputError);
+    }
 
-    Ok(Response::ClosedOrder)
+    // Remember the limits for the next order from the same restaurant
+    state.set_order_limits(open_order.id, minimum, budget)?;
+    state.set_restaurant_limits(restaurant.id, minimum, budget)?;
+
+    Ok(Response::LimitsSet {
+        restaurant_name: restaurant.name,
+        currency,
+        minimum_order_in_cents: minimum,
+        budget_per_person_in_cents: budget,
+    })
 }
 
-fn cmd_search(
+fn cmd_closeorder(
     &CommandContext {
-        state_mutex, args, ..
+        state_mutex,
+        user_name,
+        ..
     }: &CommandContext,
 ) -> Result<Response, Error> {
-    let query = state::Query::interpret_string(&args);
-
     let state = state_mutex.lock()?;
     let open_order = state.demand_open_order()?;
 
-    let items = state.query_menu(open_order.menu, &query)?;
+    state.transaction(|| -> Result<(), Error> {
+        state.close_current_order()?;
+        state.record_undo(open_order.id, user_name, &Undo::Reopen)?;
+        Ok(())
+    })?;
 
-    Ok(Response::SearchResults {
-        query: args.to_string(),
-        items,
-    })
+    Ok(Response::ClosedOrder)
 }
 
-fn cmd_order(
+/// Counts the votes for each restaurant in the poll, keeping the order
+/// the restaurants are numbered in
+fn poll_tally(state: &state::State, poll: &Poll) -> Result<Vec<PollChoice>, Error> {
+    let votes = state.poll_votes(poll.id)?;
+
+    Ok(state
+        .poll_restaurants(poll.id)?
+        .into_iter()
+        .map(|restaurant| PollChoice {
+            voters: votes
+                .iter()
+                .filter(|vote| vote.restaurant == restaurant.id)
+                .map(|vote| vote.person_name.clone())
+                .collect(),
+            restaurant_id: restaurant.id,
+            restaurant_name: restaurant.name,
+        })
+        .collect())
+}
+
+fn cmd_poll(
     &CommandContext {
         state_mutex,
         args,
         user_name,
+        env,
         ..
     }: &CommandContext,
 ) -> Result<Response, Error> {
-    let query = state::Query::interpret_string(&args);
-
     let state = state_mutex.lock()?;
-    let open_order = state.demand_open_order()?;
 
-    match state.query_menu(open_order.menu, &query)?.pop() {
-        Some(menu_item) => {
-            state.add_order_item(open_order.id, user_name, menu_item.id)?;
+    let mut split = args.trim().splitn(2, ' ');
+    let first = split.next().unwrap();
+    let rest = split.next().unwrap_or("").trim();
 
-            Ok(Response::PlacedOrder {
-                menu_items: vec![menu_item],
+    match first {
+        "" => {
+            let poll = state.demand_open_poll()?;
+            Ok(Response::Poll {
+                tally: poll_tally(&state, &poll)?,
+                started_by: poll.started_by,
             })
         }
-        None => Ok(Response::OrderNoMatch {
-            search_string: args.to_string(),
-        }),
+        "start" => {
+            // Without any names, every restaurant is up for the vote
+            let restaurants = if rest.len() == 0 {
+                state.restaurants()?
+            } else {
+                let mut restaurants: Vec<Restaurant> = Vec::new();
+                for name in rest.split(',').map(|x| x.trim()).filter(|x| x.len() > 0) {
+                    let retry = |x: &str| format!("poll start {}", rest.replacen(name, x, 1));
+                    let restaurant = match find_restaurant(&state, name, &retry)? {
+                        Ok(restaurant) => restaurant,
+                        Err(response) => return Ok(response),
+                    };
+                    if !restaurants.iter().any(|x| x.id == restaurant.id) {
+                        restaurants.push(restaurant);
+                    }
+                }
+                restaurants
+            };
+
+            if restaurants.len() < 2 {
+                return Err(Error::MissingArgument("restaurants to choose between"));
+            }
+
+            let ids = restaurants.iter().map(|x| x.id).collect::<Vec<_>>();
+            let poll_id = state.create_poll(user_name, &ids)?;
+
+            Ok(Response::PollStarted {
+                started_by: user_name.to_owned(),
+                choices: state
+                    .poll_restaurants(poll_id)?
+                    .into_iter()
+                    .map(|x| x.name)
+                    .collect(),
+            })
+        }
+        "close" => {
+            let open = match rest {
+                "" => false,
+                "open" => true,
+                _ => return Err(Error::InputError),
+            };
+
+            // Closing the poll and opening the order go together, so a
+            // failure to open leaves the poll open
+            state.transaction(|| -> Result<Response, Error> {
+                let poll = state.demand_open_poll()?;
+                let tally = poll_tally(&state, &poll)?;
+                state.close_current_poll()?;
+
+                let most_votes = tally.iter().map(|x| x.voters.len()).max().unwrap_or(0);
+                let mut leaders = tally.iter().filter(|x| x.voters.len() == most_votes);
+                let winner = match (leaders.next(), leaders.next()) {
+                    (Some(leader), None) if most_votes > 0 => Some(leader),
+                    _ => None,
+                };
+
+                let opened = match winner {
+                    Some(winner) if open => {
+                        // By id, as the restaurant may have been renamed or
+                        // archived since the poll started
+                        let restaurant = state
+                            .restaurant(winner.restaurant_id)?
+                            .ok_or(Error::NotFound)?;
+                        let minimum = restaurant.minimum_order_in_cents;
+                        let budget = restaurant.budget_per_person_in_cents;
+                        Some(Box::new(open_order(&state, env, restaurant, minimum, budget)?))
+                    }
+                    _ => None,
+                };
+
+                let winner = winner.map(|x| x.restaurant_name.clone());
+
+                Ok(Response::PollClosed {
+                    tally,
+                    winner,
+                    opened,
+                })
+            })
+        }
+        _ => Err(Error::InputError),
     }
 }
 
-fn cmd_clear(
+fn cmd_vote(
     &CommandContext {
         state_mutex,
+        args,
         user_name,
         ..
     }: &CommandContext,
 ) -> Result<Response, Error> {
     let state = state_mutex.lock()?;
-    let open_order = state.demand_open_order()?;
+    let poll = state.demand_open_poll()?;
+    let choices = state.poll_restaurants(poll.id)?;
 
-    state.clear_orders_for_person(open_order.id, user_name)?;
+    let name = args.trim();
+    if name.len() == 0 {
+        return Err(Error::MissingArgument("restaurant"));
+    }
 
-    Ok(Response::Clear)
-}
+    // Numbers refer to the choices as listed for the poll
+    let restaurant = if let Ok(number) = name.parse::<usize>() {
+        let count = choices.len();
+        choices
+            .into_iter()
+            .nth(number.wrapping_sub(1))
+            .ok_or(Error::InvalidChoice(count))?
+    } else {
+        let restaurant = match find_restaurant(&state, name, &|x| format!("vote {}", x))? {
+            Ok(restaurant) => restaurant,
+            Err(response) => return Ok(response),
+        };
+        if !choices.iter().any(|x| x.id == restaurant.id) {
+            return Err(Error::NotInPoll(restaurant.name));
+        }
+        restaurant
+    };
 
-fn cmd_summary(&CommandContext { state_mutex, .. }: &CommandContext) -> Result<Response, Error> {
-    let state = state_mutex.lock()?;
-    let open_order = state.demand_open_order()?;
-    let items = state.items_in_order(open_order.id)?;
+    state.vote(poll.id, user_name, restaurant.id)?;
 
-    Ok(Response::Summary {
-        orders: items
-            .into_iter()
-            .group_by(|&(_, ref order_item)| order_item.person_name.clone())
-            .into_iter()
-            .map(|(person_name, items)| {
-                (
-                    person_name,
-                    items.map(|(menu_item, _order_item)| menu_item).collect(),
-                )
-            })
-            .collect(),
+    Ok(Response::Voted {
+        restaurant_name: restaurant.name,
     })
 }
 
-fn cmd_price(&CommandContext { state_mutex, .. }: &CommandContext) -> Result<Response, Error> {
+fn cmd_restaurant(
+    &CommandContext {
+        state_mutex, args, ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
     let state = state_mutex.lock()?;
-    let open_order = state.demand_open_order()?;
-    let items = state.items_in_order(open_order.id)?;
 
-    let persons = Rational::from(
-        items
-            .iter()
-            .group_by(|&&(_, ref order_item)| order_item.person_name.clone())
-            .into_iter()
-            .count(),
-    );
+    let mut split = args.trim().splitn(2, ' ');
+    let first = split.next().unwrap();
+    let rest = split.next().unwrap_or("").trim();
 
-    let overhead = Rational::from_cents(open_order.overhead_in_cents);
-    let overhead_per_person = overhead.clone() / persons; // Divide by zero for empty orders
+    // Archived restaurants can still be looked at and restored
+    let find = |name: &str| -> Result<Result<Restaurant, Response>, Error> {
+        match state.any_restaurant_by_name(name)? {
+            Some(restaurant) => Ok(Ok(restaurant)),
+            None => Ok(Err(Response::RestaurantsNoMatch {
+                restaurants: state.restaurants()?,
+            })),
+        }
+    };
 
-    let summary = items
-        .into_iter()
-        .group_by(|&(_, ref order_item)| order_item.person_name.clone())
-        .into_iter()
-        .map(|(person_name, items)| {
-            let items = items.map(|(menu_item, _)| menu_item).collect::<Vec<_>>();
-            let total = items
-                .iter()
-                .map(|ref menu_item| menu_item.price_in_cents)
-                .sum();
-            let total = Rational::from_cents(total) + &overhead_per_person;
-            let total = total.to_f64();
-            (person_name, total, items)
-        })
-        .collect::<Vec<_>>();
+    match first {
+        "rename" => {
+            let mut names = rest.splitn(2, '=');
+            let old_name = names.next().unwrap().trim();
+            let new_name = names.next().ok_or(Error::MissingArgument("new name"))?.trim();
+            if new_name.len() == 0 {
+                return Err(Error::MissingArgument("new name"));
+            }
+
+            let restaurant = match find(old_name)? {
+                Ok(restaurant) => restaurant,
+                Err(response) => return Ok(response),
+            };
+            state.rename_restaurant(restaurant.id, new_name)?;
+
+            Ok(Response::RestaurantRenamed {
+                old_name: restaurant.name,
+                new_name: new_name.to_owned(),
+            })
+        }
+        "alias" => {
+            let mut names = rest.splitn(2, '=');
+            let name = names.next().unwrap().trim();
+            let alias = names.next().ok_or(Error::MissingArgument("alias"))?.trim();
+            if alias.len() == 0 {
+                return Err(Error::MissingArgument("alias"));
+            }
+
+            let restaurant = match find(name)? {
+                Ok(restaurant) => restaurant,
+                Err(response) => return Ok(response),
+            };
+            state.add_restaurant_alias(restaurant.id, alias)?;
+
+            Ok(Response::RestaurantAliasAdded {
+                restaurant_name: restaurant.name,
+                alias: alias.to_owned(),
+            })
+        }
+        "unalias" => {
+            if !state.remove_restaurant_alias(rest)? {
+                return Err(Error::NotFound);
+            }
+            Ok(Response::RestaurantAliasRemoved {
+                alias: rest.to_owned(),
+            })
+        }
+        "archive" | "unarchive" => {
+            let restaurant = match find(rest)? {
+                Ok(restaurant) => restaurant,
+                Err(response) => return Ok(response),
+            };
+            let archived = first == "archive";
+            state.set_restaurant_archived(restaurant.id, archived)?;
+
+            Ok(Response::RestaurantArchived {
+                restaurant_name: restaurant.name,
+                archived,
+            })
+        }
+        _ => match find(args.trim())? {
+            Ok(restaurant) => Ok(Response::RestaurantDetails {
+                aliases: state
+                    .restaurant_aliases()?
+                    .into_iter()
+                    .filter(|x| x.restaurant == restaurant.id)
+                    .map(|x| x.alias)
+                    .collect(),
+                restaurant,
+            }),
+            Err(response) => Ok(response),
+        },
+    }
+}
 
-    Ok(Response::Price {
-        overhead,
-        overhead_per_person,
-        summary,
-    })
+/// Interprets `input` with the item aliases of the restaurant of `order`
+fn interpret_query<'a>(
+    state: &state::State,
+    order: &Order,
+    input: &'a str,
+) -> Result<state::Query<'a>, Error> {
+    let menu = state.menu_object(order.menu)?.ok_or(Error::NotFound)?;
+    let aliases = state.item_aliases(menu.restaurant)?;
+
+    Ok(state::Query::interpret_string(input, &aliases))
 }
 
-fn cmd_associate(
+fn cmd_search(
     &CommandContext {
-        state_mutex,
-        args,
-        user_name,
-        ..
+        state_mutex, args, ..
     }: &CommandContext,
 ) -> Result<Response, Error> {
-    if args.len() == 0 {
-        let state = state_mutex.lock()?;
-        let associations = state.all_associations()?;
+    let state = state_mutex.lock()?;
+    let open_order = state.demand_open_order()?;
 
-        Ok(Response::Associations { associations })
-    } else {
-        let split = args.split_whitespace().collect::<Vec<_>>();
-        let (slack_name, sharebill_account) = match split.len() {
-            1 => (user_name, split[0]),
-            2 => (split[0], split[1]),
-            _ => return Err(Error::InputError),
-        };
+    let query = interpret_query(&state, &open_order, &args)?;
+    let mut matches = state.query_menu(open_order.menu, &query)?;
+    matches.truncate(search::SHORTLIST_LENGTH);
 
-        let state = state_mutex.lock()?;
-        state.set_association(slack_name, sharebill_account)?;
+    Ok(Response::SearchResults {
+        query: args.to_string(),
+        matches,
+    })
+}
 
-        Ok(Response::NewAssociation {
-            user_name: slack_name.to_string(),
-            sharebill_account: sharebill_account.to_string(),
-        })
+/// Splits a note for the restaurant off the end of an order, as in
+/// `order 513 (no wasabi)`
+fn split_note(args: &str) -> (&str, Option<&str>) {
+    let args = args.trim();
+    if args.ends_with(')') {
+        if let Some(start) = args.rfind('(') {
+            let note = args[start + 1..args.len() - 1].trim();
+            if note.len() > 0 {
+                return (args[..start].trim(), Some(note));
+            }
+        }
     }
+    (args, None)
 }
 
-fn generate_bill(state: &state::State) -> Result<HashMap<String, Rational>, Error> {
-    use num::Zero;
+/// The most of one item that can be ordered at once, as in `513 x20`
+const MAX_QUANTITY: usize = 20;
 
-    let open_order = state.demand_open_order()?;
-    let items = state.items_in_order(open_order.id)?;
+/// One item in an order like `513, 605 x2 (no wasabi), avo salmon`
+struct OrderEntry<'a> {
+    search_string: &'a str,
+    quantity: usize,
+    note: Option<&'a str>,
+}
 
-    let associations = state
-        .all_associations()?
-        .into_iter()
-        .map(|x| (x.slack_name, x.sharebill_account))
-        .collect::<HashMap<_, _>>();
+/// Splits an order into its comma separated entries, leaving commas in
+/// notes alone. Each entry may end with a quantity like `x2` and a note
+fn parse_order_entries(args: &str) -> Result<Vec<OrderEntry>, Error> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&args[start..index]);
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+    parts.push(&args[start..]);
 
-    let persons = Rational::from(
-        items
-            .iter()
-            .group_by(|&&(_, ref order_item)| order_item.person_name.clone())
-            .into_iter()
-            .count(),
-    );
+    let mut entries = vec![];
+    for part in parts {
+        let (rest, note) = split_note(part);
+        if rest.len() == 0 {
+            continue;
+        }
 
-    let overhead = Rational::from_cents(open_order.overhead_in_cents);
-    let overhead_per_person = overhead / persons;
+        let quantity = rest.rfind(' ').and_then(|split_at| {
+            let word = rest[split_at + 1..].to_lowercase();
+            if word.starts_with('x') {
+                word[1..].parse::<usize>().ok().map(|x| (split_at, x))
+            } else {
+                None
+            }
+        });
+        let (search_string, quantity) = match quantity {
+            Some((split_at, quantity)) => (rest[..split_at].trim(), quantity),
+            None => (rest, 1),
+        };
+        if quantity == 0 || quantity > MAX_QUANTITY {
+            return Err(Error::InputError);
+        }
 
-    let slack_debits = items
-        .into_iter()
-        .group_by(|&(_, ref order_item)| order_item.person_name.clone())
-        .into_iter()
-        .map(|(person_name, items)| {
-            let food = items
-                .into_iter()
-                .map(|(menu_item, _)| Rational::from_cents(menu_item.price_in_cents))
-                .fold(Rational::zero(), |acc, x| acc + x);
+        entries.push(OrderEntry {
+            search_string,
+            quantity,
+            note,
+        });
+    }
 
-            (person_name, food + &overhead_per_person)
-        })
-        .collect::<Vec<_>>();
+    Ok(entries)
+}
 
-    // Associations are deliberately used to bill orders by different
-    // people to the same accuont. This is handled below:
-    let mut debits = HashMap::<String, Rational>::new();
-    for (slack_name, value) in slack_debits {
-        let account = associations
-            .get(&slack_name)
-            .ok_or(Error::MissingAssociation(slack_name))?;
-        let entry = debits.entry(account.clone()).or_insert_with(Rational::zero);
-        *entry = &*entry + value;
+/// Finds the menu item `search_string` refers to. When it is not clear,
+/// returns the best candidates instead, if any
+fn resolve_item(
+    state: &state::State,
+    order: &Order,
+    search_string: &str,
+) -> Result<Result<MenuItem, Vec<search::Match>>, Error> {
+    let query = interpret_query(state, order, search_string)?;
+    let mut matches = state.query_menu(order.menu, &query)?;
+
+    if search::is_confident(&matches) {
+        Ok(Ok(matches.swap_remove(0).menu_item))
+    } else {
+        matches.truncate(search::SHORTLIST_LENGTH);
+        Ok(Err(matches))
     }
-
-    Ok(debits)
 }
 
-fn cmd_sharebill(
+fn cmd_order(
     &CommandContext {
         state_mutex,
         args,
         user_name,
-        env:
-            &web::Env {
-                ref maybe_sharebill_url,
-                ref sharebill_cookies,
-                ..
-            },
+        env,
         ..
     }: &CommandContext,
 ) -> Result<Response, Error> {
-    use num::Zero;
-
-    let sharebill_url = maybe_sharebill_url
-        .as_ref()
-        .ok_or(Error::MissingConfig("web.sharebill_url"))?;
+    let entries = parse_order_entries(args)?;
 
     let state = state_mutex.lock()?;
     let open_order = state.demand_open_order()?;
 
-    let description = format!(
-        "{}",
-        state
-            .restaurant(
-                state
-                    .menu_object(open_order.menu)?
-                    .ok_or(Error::NotFound)?
-                    .restaurant
-            )?
-            .ok_or(Error::NotFound)?
-            .name
-    );
-
-    let associations = state
-        .all_associations()?
-        .into_iter()
-        .map(|x| (x.slack_name, x.sharebill_account))
-        .collect::<HashMap<_, _>>();
-
-    let debits = generate_bill(&state)?;
-
-    let credit_account = match args.len() {
-        0 => associations.get(user_name).map(|x| x as &str),
-        _ => Some(args),
+    let mut items = vec![];
+    let mut failures = vec![];
+    for entry in entries.iter() {
+        match resolve_item(&state, &open_order, entry.search_string)? {
+            Ok(menu_item) => {
+                for _ in 0..entry.quantity {
+                    items.push((menu_item.clone(), entry.note));
+                }
+            }
+            Err(matches) => failures.push(OrderFailure {
+                search_string: entry.search_string.to_owned(),
+                matches,
+            }),
+        }
     }
-    .ok_or(Error::MissingAssociation(user_name.to_owned()))?;
 
-    let total = debits.values().fold(Rational::zero(), |acc, x| acc + x);
+    // A single item can be asked about, and the answer given with `pick`
+    if entries.len() <= 1 && failures.len() == 1 {
+        let OrderFailure {
+            search_string,
+            matches,
+        } = failures.pop().unwrap();
 
-    let mut credits = HashMap::<String, Rational>::new();
-    credits.insert(credit_account.to_owned(), total);
-
-    let post = sharebill::models::Post {
-        meta: sharebill::models::Meta {
-            description: description,
-            timestamp: time::now_utc(),
-        },
-        transaction: sharebill::models::Transaction {
-            debits: debits,
-            credits: credits,
-        },
-    };
-
-    let target_url = format!("{}post/{}", &sharebill_url, &uuid::Uuid::new_v4());
-
-    let res = reqwest::Client::new()
-        .request(reqwest::Method::PUT, &target_url)
-        .header(reqwest::header::COOKIE, sharebill_cookies.join(", "))
-        .json(&post)
-        .send()?;
+        if matches.is_empty() {
+            return Ok(Response::OrderNoMatch { search_string });
+        }
 
-    if res.status() != reqwest::StatusCode::CREATED {
-        return Err(Error::UnexpectedStatus(res.status().clone()));
+        let numbers = matches.iter().map(|x| x.menu_item.number).collect::<Vec<_>>();
+        let entry = &entries[0];
+        state.set_pending_choice(
+            open_order.id,
+            user_name,
+            &numbers,
+            entry.note,
+            entry.quantity as i32,
+        )?;
+
+        return Ok(Response::OrderAmbiguous {
+            search_string,
+            matches,
+        });
     }
 
-    state.close_current_order()?;
+    // Either all the items are ordered or none of them
+    if !failures.is_empty() {
+        return Ok(Response::OrderFailed { failures });
+    }
+    if items.is_empty() {
+        return Err(Error::MissingArgument("query"));
+    }
 
-    Ok(Response::Sharebill { url: target_url })
+    place_items(&state, env, &open_order, user_name, items)
 }
 
-fn cmd_suggest(
+/// How long, in seconds, the shortlist from an ambiguous order can be
+/// picked from
+const PENDING_CHOICE_LIFETIME: i32 = 5 * 60;
+
+fn cmd_pick(
     &CommandContext {
         state_mutex,
-        env:
-            &web::Env {
-                ref maybe_sharebill_url,
-                ref sharebill_cookies,
-                ..
-            },
+        args,
+        user_name,
+        env,
         ..
     }: &CommandContext,
 ) -> Result<Response, Error> {
-    #[derive(Deserialize, Debug)]
-    struct Row {
-        pub key: String,
-        pub value: Rational,
+    let state = state_mutex.lock()?;
+    let open_order = state.demand_open_order()?;
+
+    let choice = match state.pending_choice(user_name, PENDING_CHOICE_LIFETIME)? {
+        Some(choice) => choice,
+        None => return Err(Error::NoPendingChoice),
+    };
+    if choice.order != open_order.id {
+        return Err(Error::NoPendingChoice);
     }
 
-    #[derive(Deserialize)]
-    struct Balances {
-        pub rows: Vec<Row>,
+    let numbers = choice.numbers();
+    let index = args.trim().parse::<usize>()?;
+    if index < 1 || index > numbers.len() {
+        return Err(Error::InvalidChoice(numbers.len()));
+    }
+
+    let query = state::Query::ExactInteger(numbers[index - 1]);
+    let menu_item = state
+        .query_menu(open_order.menu, &query)?
+        .into_iter()
+        .next()
+        .ok_or(Error::NotFound)?
+        .menu_item;
+
+    let note = choice.note.as_ref().map(|x| x.as_str());
+    let items = (0..choice.quantity).map(|_| (menu_item.clone(), note)).collect();
+
+    // The shortlist can be picked from again until an order succeeds
+    state.transaction(|| -> Result<Response, Error> {
+        let response = place_items(&state, env, &open_order, user_name, items)?;
+        if let Response::PlacedOrder { .. } = response {
+            state.clear_pending_choice(user_name)?;
+        }
+        Ok(response)
+    })
+}
+
+/// The items `person_name` has in the order
+fn person_items(
+    state: &state::State,
+    order: &Order,
+    person_name: &str,
+) -> Result<Vec<(MenuItem, OrderItem)>, Error> {
+    Ok(state
+        .items_in_order(order.id)?
+        .into_iter()
+        .filter(|x| x.1.person_name.to_lowercase() == person_name.to_lowercase())
+        .collect())
+}
+
+fn cmd_fav(
+    &CommandContext {
+        state_mutex,
+        args,
+        user_name,
+        env,
+        ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    let state = state_mutex.lock()?;
+    let open_order = state.demand_open_order()?;
+    let restaurant = order_restaurant(&state, &open_order)?;
+
+    let mut split = args.trim().splitn(2, ' ');
+    let first = split.next().unwrap();
+    let name = split.next().unwrap_or("").trim();
+
+    if first != "" && name.len() == 0 {
+        return Err(Error::MissingArgument("name"));
     }
 
-    let sharebill_url = maybe_sharebill_url
+    match first {
+        "save" => {
+            let items = person_items(&state, &open_order, user_name)?;
+            if items.is_empty() {
+                return Err(Error::EmptyOrder);
+            }
+
+            let items = items
+                .iter()
+                .map(|x| (x.0.number, x.1.note.as_ref().map(|x| x.as_str())))
+                .collect::<Vec<_>>();
+            state.save_favorite(user_name, restaurant.id, name, &items)?;
+
+            Ok(Response::FavoriteSaved {
+                name: name.to_owned(),
+                item_count: items.len(),
+            })
+        }
+        "order" => {
+            let favorites = state
+                .favorites(user_name, restaurant.id)?
+                .into_iter()
+                .filter(|x| x.name.to_lowercase() == name.to_lowercase())
+                .collect::<Vec<_>>();
+            if favorites.is_empty() {
+                return Err(Error::NotFound);
+            }
+
+            // Like other orders, either all the items are ordered or none
+            let mut items = vec![];
+            let mut failures = vec![];
+            for favorite in favorites.iter() {
+                let query = state::Query::ExactInteger(favorite.number);
+                let note = favorite.note.as_ref().map(|x| x.as_str());
+                match state.query_menu(open_order.menu, &query)?.into_iter().next() {
+                    Some(x) => items.push((x.menu_item, note)),
+                    None => failures.push(OrderFailure {
+                        search_string: favorite.number.to_string(),
+                        matches: vec![],
+                    }),
+                }
+            }
+
+            if !failures.is_empty() {
+                return Ok(Response::OrderFailed { failures });
+            }
+
+            place_items(&state, env, &open_order, user_name, items)
+        }
+        "remove" => {
+            if !state.remove_favorite(user_name, restaurant.id, name)? {
+                return Err(Error::NotFound);
+            }
+            Ok(Response::FavoriteRemoved {
+                name: name.to_owned(),
+            })
+        }
+        "" => {
+            let mut favorites: Vec<(String, Vec<(i32, Option<String>)>)> = vec![];
+            for favorite in state.favorites(user_name, restaurant.id)? {
+                let new_name = match favorites.last() {
+                    Some(&(ref name, _)) => name != &favorite.name,
+                    None => true,
+                };
+                if new_name {
+                    favorites.push((favorite.name.clone(), vec![]));
+                }
+                favorites
+                    .last_mut()
+                    .unwrap()
+                    .1
+                    .push((favorite.number, favorite.note));
+            }
+
+            Ok(Response::Favorites {
+                restaurant_name: restaurant.name,
+                favorites,
+                url: format!("{}favorites", env.base_url),
+            })
+        }
+        _ => Err(Error::InputError),
+    }
+}
+
+fn cmd_clear(
+    &CommandContext {
+        state_mutex,
+        user_name,
+        ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    let state = state_mutex.lock()?;
+    let open_order = state.demand_open_order()?;
+
+    let cleared = person_items(&state, &open_order, user_name)?
+        .into_iter()
+        .map(|x| (i32::from(x.1.menu_item), x.1.note))
+        .collect::<Vec<_>>();
+
+    state.transaction(|| -> Result<(), Error> {
+        state.clear_orders_for_person(open_order.id, user_name)?;
+        if !cleared.is_empty() {
+            state.record_undo(open_order.id, user_name, &Undo::RestoreOrderItems(cleared))?;
+        }
+        Ok(())
+    })?;
+
+    Ok(Response::Clear)
+}
+
+/// Undoes the most recent change the user made to the current order, or
+/// closing the most recent order
+fn cmd_undo(
+    &CommandContext {
+        state_mutex,
+        user_name,
+        env,
+        ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    let state = state_mutex.lock()?;
+
+    let journal_entry = state
+        .last_journal_entry(user_name)?
+        .ok_or(Error::NothingToUndo)?;
+
+    let order = match journal_entry.undo() {
+        Undo::Reopen => {
+            let last_closed = state.closed_orders(1)?.pop();
+            match last_closed {
+                Some(ref order) if order.id == journal_entry.order => (),
+                _ => return Err(Error::NothingToUndo),
+            }
+            last_closed.unwrap()
+        }
+        _ => {
+            let open_order = state.demand_open_order()?;
+            if open_order.id != journal_entry.order {
+                return Err(Error::NothingToUndo);
+            }
+            open_order
+        }
+    };
+
+    // Putting cleared items back is held to the budget like ordering them
+    let undone = state.transaction(|| -> Result<Undo, Error> {
+        let undo = state.undo(&journal_entry)?;
+        if let Undo::RestoreOrderItems(_) = undo {
+            if env.enforce_budget {
+                if let Some(over_budget) = over_budget(&state, env, &order, user_name)? {
+                    return Err(Error::OverBudget(over_budget));
+                }
+            }
+        }
+        Ok(undo)
+    });
+
+    let undo = match undone {
+        Err(Error::OverBudget(over_budget)) => {
+            return Ok(Response::UndoRefused { over_budget })
+        }
+        undone => undone?,
+    };
+
+    Ok(Response::Undone {
+        currency: restaurant_currency(env, &order_restaurant(&state, &order)?),
+        undo,
+    })
+}
+
+fn cmd_summary(
+    &CommandContext {
+        state_mutex, env, ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    let state = state_mutex.lock()?;
+    let open_order = state.demand_open_order()?;
+    let items = state.items_in_order(open_order.id)?;
+
+    let minimum = match open_order.minimum_order_in_cents {
+        Some(minimum) => Some(MinimumOrder {
+            currency: restaurant_currency(env, &order_restaurant(&state, &open_order)?),
+            total: items
+                .iter()
+                .map(|&(ref menu_item, _)| Rational::from_cents(menu_item.price_in_cents))
+                .sum(),
+            minimum: Rational::from_cents(minimum),
+        }),
+        None => None,
+    };
+
+    Ok(Response::Summary {
+        minimum,
+        orders: items
+            .into_iter()
+            .group_by(|&(_, ref order_item)| order_item.person_name.clone())
+            .into_iter()
+            .map(|(person_name, items)| {
+                (
+                    person_name,
+                    items.map(|(menu_item, _order_item)| menu_item).collect(),
+                )
+            })
+            .collect(),
+    })
+}
+
+fn restaurant_currency(env: &web::Env, restaurant: &Restaurant) -> Currency {
+    restaurant
+        .currency
         .as_ref()
-        .ok_or(Error::MissingConfig("web.sharebill_url"))?;
+        .and_then(|code| Currency::from_code(code))
+        .unwrap_or_else(|| env.currency.clone())
+}
+
+fn order_restaurant(state: &state::State, order: &Order) -> Result<Restaurant, Error> {
+    let menu = state.menu_object(order.menu)?.ok_or(Error::NotFound)?;
+    Ok(state.restaurant(menu.restaurant)?.ok_or(Error::NotFound)?)
+}
+
+/// Finds the rate for converting from `currency` to the home currency,
+/// preferring one entered for the order over the configured ones.
+/// Returns `None` when no conversion is needed
+fn exchange_rate(
+    env: &web::Env,
+    order: &Order,
+    currency: &Currency,
+) -> Result<Option<Rational>, Error> {
+    if *currency == env.currency {
+        return Ok(None);
+    }
+
+    if let Some(ref rate) = order.exchange_rate {
+        return Ok(Some(rate.parse()?));
+    }
+
+    env.exchange_rates
+        .iter()
+        .find(|&(code, _)| code.eq_ignore_ascii_case(&currency.code))
+        .map(|(_, rate)| Some(rate.clone()))
+        .ok_or_else(|| Error::MissingExchangeRate(currency.code.clone()))
+}
+
+struct OrderTotals {
+    overhead: Rational,
+    overhead_per_person: Rational,
+
+    /// Each adjustment, with its effect on the order as a whole
+    adjustments: Vec<(Adjustment, Rational)>,
+
+    /// What each person owes, with what they ordered
+    persons: Vec<(String, Rational, Vec<MenuItem>)>,
+}
+
+fn load_adjustments(state: &state::State, order: &Order) -> Result<Vec<Adjustment>, Error> {
+    state
+        .order_adjustments(order.id)?
+        .into_iter()
+        .map(|x| -> Result<_, Error> {
+            Ok(Adjustment {
+                id: x.id,
+                kind: x.adjustment_kind(),
+                amount: x.amount.parse()?,
+                description: x.description,
+            })
+        })
+        .collect()
+}
+
+/// Works out what everyone owes in the currency of the restaurant.
+/// Overhead and fixed adjustments are split evenly, percentages apply
+/// to what each person ordered and per person amounts are added as is
+fn order_totals(state: &state::State, order: &Order) -> Result<OrderTotals, Error> {
+    use num::Zero;
+
+    let items = state.items_in_order(order.id)?;
+    let adjustments = load_adjustments(state, order)?;
+
+    let orders = items
+        .into_iter()
+        .group_by(|&(_, ref order_item)| order_item.person_name.clone())
+        .into_iter()
+        .map(|(person_name, items)| {
+            let items = items.map(|(menu_item, _)| menu_item).collect::<Vec<_>>();
+            (person_name, items)
+        })
+        .collect::<Vec<_>>();
+
+    let nobody = orders.is_empty();
+    let person_count = Rational::from(orders.len());
+    let share = |amount: &Rational| {
+        if nobody {
+            Rational::zero()
+        } else {
+            amount / &person_count
+        }
+    };
+    let food = |items: &[MenuItem]| {
+        items
+            .iter()
+            .map(|menu_item| Rational::from_cents(menu_item.price_in_cents))
+            .sum::<Rational>()
+    };
+
+    let overhead = Rational::from_cents(order.overhead_in_cents);
+    let overhead_per_person = share(&overhead);
+
+    let total_food = orders.iter().map(|&(_, ref items)| food(items)).sum::<Rational>();
+    let adjustments = adjustments
+        .into_iter()
+        .map(|adjustment| {
+            let total = match adjustment.kind {
+                AdjustmentKind::Fixed => adjustment.amount.clone(),
+                AdjustmentKind::Percentage => total_food.percent(&adjustment.amount),
+                AdjustmentKind::PerPerson => &adjustment.amount * &person_count,
+            };
+            (adjustment, total)
+        })
+        .collect::<Vec<_>>();
+
+    let persons = orders
+        .into_iter()
+        .map(|(person_name, items)| {
+            let food = food(&items);
+            let adjusted = adjustments
+                .iter()
+                .map(|&(ref adjustment, _)| match adjustment.kind {
+                    AdjustmentKind::Fixed => share(&adjustment.amount),
+                    AdjustmentKind::Percentage => food.percent(&adjustment.amount),
+                    AdjustmentKind::PerPerson => adjustment.amount.clone(),
+                })
+                .sum::<Rational>();
+            let total = food + &overhead_per_person + adjusted;
+            (person_name, total, items)
+        })
+        .collect();
+
+    Ok(OrderTotals {
+        overhead,
+        overhead_per_person,
+        adjustments,
+        persons,
+    })
+}
+
+fn cmd_price(
+    &CommandContext {
+        state_mutex, env, ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    let state = state_mutex.lock()?;
+    let open_order = state.demand_open_order()?;
+    let currency = restaurant_currency(env, &order_restaurant(&state, &open_order)?);
+    let totals = order_totals(&state, &open_order)?;
+
+    Ok(Response::Price {
+        currency,
+        overhead: totals.overhead,
+        overhead_per_person: totals.overhead_per_person,
+        adjustments: totals.adjustments,
+        summary: totals.persons,
+    })
+}
+
+fn cmd_associate(
+    &CommandContext {
+        state_mutex,
+        args,
+        user_name,
+        ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    if args.len() == 0 {
+        let state = state_mutex.lock()?;
+        let associations = state.all_associations()?;
+
+        Ok(Response::Associations { associations })
+    } else {
+        let split = args.split_whitespace().collect::<Vec<_>>();
+        let (slack_name, sharebill_account) = match split.len() {
+            1 => (user_name, split[0]),
+            2 => (split[0], split[1]),
+            _ => return Err(Error::InputError),
+        };
+
+        let state = state_mutex.lock()?;
+        state.set_association(slack_name, sharebill_account)?;
+
+        Ok(Response::NewAssociation {
+            user_name: slack_name.to_string(),
+            sharebill_account: sharebill_account.to_string(),
+        })
+    }
+}
+
+/// Sums up what everyone owes for the open order, in the home currency
+fn generate_bill(
+    state: &state::State,
+    env: &web::Env,
+) -> Result<HashMap<String, Rational>, Error> {
+    use num::Zero;
+
+    let open_order = state.demand_open_order()?;
+
+    let associations = state
+        .all_associations()?
+        .into_iter()
+        .map(|x| (x.slack_name, x.sharebill_account))
+        .collect::<HashMap<_, _>>();
+
+    let slack_debits = order_totals(state, &open_order)?
+        .persons
+        .into_iter()
+        .map(|(person_name, total, _)| (person_name, total))
+        .collect::<Vec<_>>();
+
+    // Associations are deliberately used to bill orders by different
+    // people to the same accuont. This is handled below:
+    let mut debits = HashMap::<String, Rational>::new();
+    let mut missing = Vec::<String>::new();
+    for (slack_name, value) in slack_debits {
+        match associations.get(&slack_name) {
+            Some(account) => {
+                let entry = debits.entry(account.clone()).or_insert_with(Rational::zero);
+                *entry += value;
+            }
+            None => missing.push(slack_name),
+        }
+    }
+
+    if !missing.is_empty() {
+        return Err(Error::MissingAssociations(missing));
+    }
+
+    let currency = restaurant_currency(env, &order_restaurant(state, &open_order)?);
+    match exchange_rate(env, &open_order, &currency)? {
+        Some(rate) => Ok(debits
+            .into_iter()
+            .map(|(account, amount)| (account, amount * &rate))
+            .collect()),
+        None => Ok(debits),
+    }
+}
+
+fn build_bill(
+    state: &state::State,
+    env: &web::Env,
+    open_order: &Order,
+    user_name: &str,
+    credit_account: &str,
+) -> Result<settlement::Bill, Error> {
+    let restaurant = order_restaurant(state, open_order)?;
+    let currency = restaurant_currency(env, &restaurant);
+
+    let associations = state
+        .all_associations()?
+        .into_iter()
+        .map(|x| (x.slack_name, x.sharebill_account))
+        .collect::<HashMap<_, _>>();
+
+    let credit_account = match credit_account.len() {
+        0 => associations.get(user_name).cloned(),
+        _ => Some(credit_account.to_owned()),
+    };
+
+    // Report every missing association at once, including the one for
+    // the credit account, so they can all be fixed in one go
+    let (debits, credit_account) = match (generate_bill(state, env), credit_account) {
+        (Ok(debits), Some(credit_account)) => (debits, credit_account),
+        (Ok(_), None) => return Err(Error::MissingAssociations(vec![user_name.to_owned()])),
+        (Err(Error::MissingAssociations(mut missing)), None) => {
+            if !missing.iter().any(|x| x == user_name) {
+                missing.push(user_name.to_owned());
+            }
+            return Err(Error::MissingAssociations(missing));
+        }
+        (Err(err), _) => return Err(err),
+    };
+
+    let total: Rational = debits.values().sum();
+
+    // Keep a record of the original amount for bills in other currencies
+    let mut description = match exchange_rate(env, open_order, &currency)? {
+        Some(rate) => format!(
+            "{} ({} at {:#.4} {}/{})",
+            restaurant.name,
+            currency.format(&(&total / &rate)),
+            rate,
+            env.currency.code,
+            currency.code
+        ),
+        None => restaurant.name,
+    };
+    for (adjustment, total) in order_totals(state, open_order)?.adjustments {
+        description.push_str("\n");
+        description.push_str(&adjustment.describe(&total, &currency));
+    }
+
+    let mut credits = HashMap::<String, Rational>::new();
+    credits.insert(credit_account, total);
+
+    Ok(settlement::Bill {
+        description: description,
+        timestamp: time::now_utc(),
+        debits: debits,
+        credits: credits,
+    })
+}
+
+fn cmd_settle(
+    &CommandContext {
+        state_mutex,
+        args,
+        user_name,
+        env,
+        ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    let mut split = args.splitn(2, ' ');
+    let (preview, args) = match split.next() {
+        Some("preview") => (true, split.next().unwrap_or("")),
+        _ => (false, args),
+    };
+
+    let backend = settlement::from_env(env)?;
+
+    let state = state_mutex.lock()?;
+    let open_order = state.demand_open_order()?;
+
+    let bill = match build_bill(&state, env, &open_order, user_name, args) {
+        Ok(bill) => bill,
+        Err(Error::MissingAssociations(slack_names)) => {
+            return Ok(Response::MissingAssociations { slack_names })
+        }
+        Err(err) => return Err(err),
+    };
+
+    if preview {
+        let settlement::Preview { payload, pending } = backend.preview(&open_order, &bill)?;
+
+        return Ok(Response::SettlementPreview {
+            backend: backend.name(),
+            payload,
+            pending,
+        });
+    }
+
+    backend.prepare(&state, &open_order, &bill)?;
+    let open_order = state.demand_open_order()?;
+
+    let receipt = state.transaction(|| -> Result<settlement::Receipt, Error> {
+        let receipt = backend.settle(&state, &open_order, bill)?;
+        state.close_current_order()?;
+        Ok(receipt)
+    })?;
+
+    Ok(Response::Settled {
+        backend: backend.name(),
+        receipt,
+    })
+}
+
+fn cmd_log(
+    &CommandContext {
+        state_mutex,
+        args,
+        env,
+        ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    let count = match args.len() {
+        0 => 10,
+        _ => args.parse::<i64>()?,
+    };
+
+    let state = state_mutex.lock()?;
+
+    // Everyone is usually interested in the current order only
+    let order = state.current_open_order()?.map(|x| x.id);
+    let entries = state.audit_log(order, count)?;
+
+    let url = match order {
+        Some(order) => format!("{}log?order={}", env.base_url, order),
+        None => format!("{}log", env.base_url),
+    };
+
+    Ok(Response::Log { entries, url })
+}
+
+fn cmd_history(
+    &CommandContext {
+        state_mutex, args, ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    let count = match args.len() {
+        0 => 5,
+        _ => args.parse::<i64>()?,
+    };
+
+    let state = state_mutex.lock()?;
+
+    let orders = state
+        .closed_orders(count)?
+        .into_iter()
+        .map(|order| -> Result<_, Error> {
+            let menu = state.menu_object(order.menu)?.ok_or(Error::NotFound)?;
+            let restaurant = state.restaurant(menu.restaurant)?.ok_or(Error::NotFound)?;
+            Ok((restaurant.name, order))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(Response::History { orders })
+}
+
+fn cmd_suggest(
+    &CommandContext {
+        state_mutex, env, ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    let backend = settlement::from_env(env)?;
+
+    let state = state_mutex.lock()?;
+    let debits = generate_bill(&state, env)?;
+
+    let mut balances = backend
+        .balances(&state)?
+        .into_iter()
+        .filter(|&(ref account, _)| debits.contains_key(account))
+        .map(|(account, balance)| {
+            let this_meal = debits
+                .get(&account)
+                .expect("Guaranteed by filter on the line above");
+            let new_balance = &balance - this_meal;
+            (account, balance, new_balance)
+        })
+        .collect::<Vec<_>>();
+
+    balances.sort_by(|a, b| a.2.cmp(&b.2));
+    let balances = balances.into_iter().take(3).collect();
+
+    Ok(Response::Suggest {
+        backend: backend.name(),
+        currency: env.currency.clone(),
+        balances,
+    })
+}
+
+/// Accounts are found through the associations when possible, but
+/// anything else is taken to be an account name as is
+fn account_for(state: &state::State, name: &str) -> Result<String, Error> {
+    Ok(state
+        .all_associations()?
+        .into_iter()
+        .find(|x| x.slack_name.to_lowercase() == name.to_lowercase())
+        .map(|x| x.sharebill_account)
+        .unwrap_or_else(|| name.to_owned()))
+}
+
+fn cmd_balance(
+    &CommandContext {
+        state_mutex,
+        args,
+        user_name,
+        env,
+        ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    use num::Zero;
+
+    let backend = settlement::from_env(env)?;
+
+    let state = state_mutex.lock()?;
+    let account = account_for(
+        &state,
+        match args.len() {
+            0 => user_name,
+            _ => args,
+        },
+    )?;
+
+    let balance = backend
+        .balances(&state)?
+        .remove(&account)
+        .unwrap_or_else(Rational::zero);
+
+    Ok(Response::Balance {
+        account,
+        balance,
+        currency: env.currency.clone(),
+    })
+}
+
+fn cmd_balances(&CommandContext { state_mutex, env, .. }: &CommandContext) -> Result<Response, Error> {
+    use num::Zero;
+
+    let backend = settlement::from_env(env)?;
+
+    let state = state_mutex.lock()?;
+    let mut balances = backend
+        .balances(&state)?
+        .into_iter()
+        .filter(|&(_, ref balance)| !balance.is_zero())
+        .collect::<Vec<_>>();
+    balances.sort_by(|a, b| a.1.cmp(&b.1));
+
+    Ok(Response::Balances {
+        backend: backend.name(),
+        currency: env.currency.clone(),
+        balances,
+    })
+}
+
+fn cmd_pay(
+    &CommandContext {
+        state_mutex,
+        args,
+        user_name,
+        env,
+        ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    use num::Zero;
+
+    // The amount may contain spaces, as in `12 1/2` or `kr 12,-`
+    let mut split = args.trim().splitn(2, ' ');
+    let to = split.next().filter(|x| !x.is_empty()).ok_or(Error::MissingArgument("NAME"))?;
+    let amount = split.next().ok_or(Error::MissingArgument("AMOUNT"))?;
+
+    let amount = Rational::parse_amount(amount)?;
+    if amount <= Rational::zero() {
+        return Err(Error::InputError);
+    }
+
+    let backend = settlement::from_env(env)?;
+
+    let state = state_mutex.lock()?;
+    let from = account_for(&state, user_name)?;
+    let to = account_for(&state, to)?;
+    if from.to_lowercase() == to.to_lowercase() {
+        return Err(Error::InputError);
+    }
+
+    backend.pay(&state, &from, &to, &amount)?;
+
+    Ok(Response::Paid {
+        from,
+        to,
+        amount,
+        currency: env.currency.clone(),
+    })
+}
+
+fn cmd_overhead(
+    &CommandContext {
+        state_mutex,
+        args,
+        user_name,
+        env,
+        ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    let state = state_mutex.lock()?;
+    let open_order = state.demand_open_order()?;
+    let currency = restaurant_currency(env, &order_restaurant(&state, &open_order)?);
+
+    if args.len() == 0 {
+        Ok(Response::Overhead {
+            currency,
+            overhead_in_cents: open_order.overhead_in_cents,
+        })
+    } else {
+        let prev_overhead_in_cents = open_order.overhead_in_cents;
+
+        let new_overhead_in_cents = parse_cents(args)?;
+
+        state.transaction(|| -> Result<(), Error> {
+            state.set_overhead(open_order.id, new_overhead_in_cents)?;
+            state.record_undo(
+                open_order.id,
+                user_name,
+                &Undo::SetOverhead(prev_overhead_in_cents),
+            )?;
+            Ok(())
+        })?;
+
+        Ok(Response::OverheadSet {
+            currency,
+            prev_overhead_in_cents,
+            new_overhead_in_cents,
+        })
+    }
+}
+
+fn cmd_currency(
+    &CommandContext {
+        state_mutex,
+        args,
+        env,
+        ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    let state = state_mutex.lock()?;
+    let open_order = state.demand_open_order()?;
+    let restaurant = order_restaurant(&state, &open_order)?;
+
+    if args.len() == 0 {
+        return Ok(Response::Currency {
+            currency: restaurant_currency(env, &restaurant),
+            restaurant_name: restaurant.name,
+        });
+    }
+
+    let currency = Currency::from_code(args.trim())
+        .ok_or_else(|| Error::InvalidCurrency(args.to_owned()))?;
+    state.set_restaurant_currency(restaurant.id, Some(currency.code.as_str()))?;
+
+    Ok(Response::CurrencySet {
+        restaurant_name: restaurant.name,
+        currency,
+    })
+}
+
+fn cmd_contact(&CommandContext { state_mutex, args, .. }: &CommandContext) -> Result<Response, Error> {
+    let state = state_mutex.lock()?;
+
+    let mut split = args.trim().splitn(2, ' ');
+    let first = split.next().unwrap();
+    let contact = split.next().unwrap_or("").trim();
+
+    // Contacts are added to and removed from the restaurant of the current
+    // order. Anything with an @ is an email address
+    if first == "add" || first == "remove" {
+        if contact.len() == 0 {
+            return Err(Error::MissingArgument("contact"));
+        }
+
+        let restaurant = order_restaurant(&state, &state.demand_open_order()?)?;
+        let is_email = contact.contains('@');
+
+        if first == "add" {
+            if is_email && !mail::is_valid_address(contact) {
+                return Err(Error::InvalidEmailAddress(contact.to_owned()));
+            }
+            if is_email {
+                state.add_email_address(restaurant.id, contact)?;
+            } else {
+                state.add_phone_number(restaurant.id, contact)?;
+            }
+            return Ok(Response::ContactAdded {
+                restaurant_name: restaurant.name,
+                contact: contact.to_owned(),
+            });
+        }
+
+        let removed = if is_email {
+            state.remove_email_address(restaurant.id, contact)?
+        } else {
+            state.remove_phone_number(restaurant.id, contact)?
+        };
+        if !removed {
+            return Err(Error::NotFound);
+        }
+        return Ok(Response::ContactRemoved {
+            restaurant_name: restaurant.name,
+            contact: contact.to_owned(),
+        });
+    }
+
+    let restaurant = if args.trim().len() == 0 {
+        order_restaurant(&state, &state.demand_open_order()?)?
+    } else {
+        let retry = |x: &str| format!("contact {}", x);
+        match find_restaurant(&state, args.trim(), &retry)? {
+            Ok(restaurant) => restaurant,
+            Err(response) => return Ok(response),
+        }
+    };
+
+    Ok(Response::Contacts {
+        phone_numbers: state
+            .phone_numbers(restaurant.id)?
+            .into_iter()
+            .map(|x| x.number)
+            .collect(),
+        email_addresses: state
+            .email_addresses(restaurant.id)?
+            .into_iter()
+            .map(|x| x.email_address)
+            .collect(),
+        restaurant_name: restaurant.name,
+    })
+}
+
+fn cmd_alias(&CommandContext { state_mutex, args, .. }: &CommandContext) -> Result<Response, Error> {
+    let state = state_mutex.lock()?;
+    let open_order = state.demand_open_order()?;
+    let restaurant = order_restaurant(&state, &open_order)?;
+
+    let mut split = args.trim().splitn(2, ' ');
+    let first = split.next().unwrap();
+    let rest = split.next().unwrap_or("").trim();
+
+    match first {
+        "add" => {
+            // The number comes last, as the alias may be several words
+            let split_at = rest.rfind(' ').ok_or(Error::MissingArgument("number"))?;
+            let alias = rest[..split_at].trim();
+            let number = rest[split_at + 1..].trim_start_matches('#').parse::<i32>()?;
+
+            let query = state::Query::ExactInteger(number);
+            let menu_item = state
+                .query_menu(open_order.menu, &query)?
+                .into_iter()
+                .next()
+                .ok_or(Error::NotFound)?
+                .menu_item;
+
+            state.add_item_alias(restaurant.id, alias, number)?;
+
+            Ok(Response::ItemAliasAdded {
+                restaurant_name: restaurant.name,
+                alias: alias.to_owned(),
+                menu_item,
+            })
+        }
+        "remove" => {
+            if rest.len() == 0 {
+                return Err(Error::MissingArgument("alias"));
+            }
+            if !state.remove_item_alias(restaurant.id, rest)? {
+                return Err(Error::NotFound);
+            }
+
+            Ok(Response::ItemAliasRemoved {
+                restaurant_name: restaurant.name,
+                alias: rest.to_owned(),
+            })
+        }
+        "" => {
+            let menu = state.menu(open_order.menu)?;
+            let aliases = state
+                .item_aliases(restaurant.id)?
+                .into_iter()
+                .map(|x| {
+                    let name = menu
+                        .iter()
+                        .find(|item| item.number == x.number)
+                        .map(|item| item.name.clone());
+                    (x.alias, x.number, name)
+                })
+                .collect();
+
+            Ok(Response::ItemAliases {
+                restaurant_name: restaurant.name,
+                aliases,
+            })
+        }
+        _ => Err(Error::InputError),
+    }
+}
+
+fn order_email(
+    email: &config::EmailConfig,
+    lines: &[ordersheet::Line],
+    note: &str,
+) -> Result<String, Error> {
+    use std::fmt::Write;
+
+    let mut body = String::new();
+
+    writeln!(&mut body, "Hello!\n\nWe would like to order:\n")?;
+    for line in lines {
+        writeln!(
+            &mut body,
+            "  {} x {}. {}",
+            line.quantity, line.menu_item.number, line.menu_item.name
+        )?;
+        for item_note in &line.notes {
+            writeln!(&mut body, "      - {}", item_note)?;
+        }
+    }
+
+    if note.len() > 0 {
+        writeln!(&mut body, "\nNote: {}", note)?;
+    }
+
+    writeln!(&mut body, "\nDelivery address:\n{}", email.delivery_address)?;
+
+    write!(&mut body, "\nContact: {}", email.contact_name)?;
+    if let Some(ref phone) = email.contact_phone {
+        write!(&mut body, ", {}", phone)?;
+    }
+    writeln!(&mut body, "\n\nThank you!")?;
+
+    Ok(body)
+}
+
+fn cmd_send(
+    &CommandContext {
+        state_mutex,
+        args,
+        env,
+        ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    let email = env.email.as_ref().ok_or(Error::EmailNotConfigured)?;
+
+    // `send again` is needed to send an order that has already been sent
+    let args = args.trim();
+    let (again, note) = if args == "again" || args.starts_with("again ") {
+        (true, args["again".len()..].trim())
+    } else {
+        (false, args)
+    };
+
+    let (open_order, restaurant, email_address, lines, body) = {
+        let state = state_mutex.lock()?;
+        let open_order = state.demand_open_order()?;
+        let restaurant = order_restaurant(&state, &open_order)?;
+
+        if open_order.sent.is_some() && !again {
+            return Err(Error::OrderAlreadySent);
+        }
+
+        let email_address = match state.email_addresses(restaurant.id)?.into_iter().next() {
+            Some(x) => x.email_address,
+            None => return Err(Error::MissingEmailAddress(restaurant.name)),
+        };
+
+        let lines = ordersheet::by_menu_item(state.items_in_order(open_order.id)?);
+        if lines.is_empty() {
+            return Err(Error::EmptyOrder);
+        }
+        let body = order_email(email, &lines, note)?;
+
+        // Marked as sent up front, so a second send cannot get past the
+        // check above while this one is still mailing
+        state.set_order_sent(open_order.id)?;
+
+        (open_order, restaurant, email_address, lines, body)
+    };
+
+    // The mail server may take its time, so everyone else can go on
+    // using the state in the meantime
+    let credentials = email.smtp_username.as_ref().map(|username| {
+        let password = env.smtp_password.as_ref().map(|x| x.as_str());
+        (username.as_str(), password.unwrap_or(""))
+    });
+    let sent = mail::send(
+        &mail::Server {
+            address: &email.smtp_server,
+            starttls: email.smtp_starttls,
+            credentials,
+        },
+        &mail::Message {
+            from: &email.from,
+            to: &email_address,
+            subject: &format!("Order from {}", email.contact_name),
+            body: &body,
+        },
+    );
+    if let Err(err) = sent {
+        state_mutex.lock()?.reset_order_sent(open_order.id, open_order.sent)?;
+        return Err(err.into());
+    }
+
+    Ok(Response::OrderSent {
+        restaurant_name: restaurant.name,
+        email_address,
+        item_count: lines.iter().map(|x| x.quantity).sum(),
+    })
+}
+
+fn cmd_ordersheet(
+    &CommandContext {
+        state_mutex,
+        args,
+        env,
+        ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
+    let state = state_mutex.lock()?;
+    let open_order = state.demand_open_order()?;
+    let items = state.items_in_order(open_order.id)?;
+
+    let url = format!("{}order/{}", env.base_url, i32::from(open_order.id));
+
+    match args.trim() {
+        "" => {
+            let lines = ordersheet::by_menu_item(items);
+            Ok(Response::OrderSheet {
+                currency: restaurant_currency(env, &order_restaurant(&state, &open_order)?),
+                total: ordersheet::total(&lines),
+                lines,
+                url,
+            })
+        }
+        "packing" => Ok(Response::PackingList {
+            persons: ordersheet::by_person(items),
+            url,
+        }),
+        _ => Err(Error::InputError),
+    }
+}
 
+fn cmd_rate(
+    &CommandContext {
+        state_mutex,
+        args,
+        env,
+        ..
+    }: &CommandContext,
+) -> Result<Response, Error> {
     let state = state_mutex.lock()?;
-    let debits = generate_bill(&state)?;
+    let open_order = state.demand_open_order()?;
+    let currency = restaurant_currency(env, &order_restaurant(&state, &open_order)?);
 
-    let mut res = reqwest::Client::new()
-        .request(reqwest::Method::GET, &format!("{}balances", &sharebill_url))
-        .header(reqwest::header::COOKIE, sharebill_cookies.join(", "))
-        .send()?;
+    if args.len() == 0 {
+        let rate = match exchange_rate(env, &open_order, &currency) {
+            Ok(rate) => rate,
+            Err(Error::MissingExchangeRate(_)) => None,
+            Err(err) => return Err(err),
+        };
 
-    if !res.status().is_success() {
-        return Err(Error::UnexpectedStatus(res.status().clone()));
+        return Ok(Response::ExchangeRate {
+            from: currency,
+            to: env.currency.clone(),
+            rate,
+        });
     }
-    let balances: Balances = res.json()?;
 
-    let mut balances = balances
-        .rows
-        .into_iter()
-        .filter(|row| debits.contains_key(&row.key))
-        .map(|row| {
-            let this_meal = debits
-                .get(&row.key)
-                .expect("Guaranteed by filter on the line above");
-            let new_balance = &row.value - this_meal;
-            (row.key, row.value, new_balance)
-        })
-        .collect::<Vec<_>>();
+    let rate = args.parse::<Rational>()?;
+    if rate <= Rational::from(0) {
+        return Err(Error::InputError);
+    }
+    state.set_exchange_rate(open_order.id, Some(&rate))?;
 
-    balances.sort_by(|a, b| a.2.cmp(&b.2));
-    let balances = balances.into_iter().take(3).collect();
+    Ok(Response::ExchangeRateSet {
+        from: currency,
+        to: env.currency.clone(),
+        rate,
+    })
+}
+
+/// Parses amounts like `79`, `-20%` and `15/person`
+fn parse_adjustment(spec: &str) -> Result<(AdjustmentKind, Rational), Error> {
+    if spec.ends_with('%') {
+        return Ok((AdjustmentKind::Percentage, spec[..spec.len() - 1].parse()?));
+    }
+
+    for suffix in &["/person", "pp"] {
+        if spec.ends_with(suffix) {
+            let amount = Rational::parse_amount(&spec[..spec.len() - suffix.len()])?;
+            return Ok((AdjustmentKind::PerPerson, amount));
+        }
+    }
 
-    Ok(Response::Suggest { balances })
+    Ok((AdjustmentKind::Fixed, Rational::parse_amount(spec)?))
 }
 
-fn cmd_overhead(
+fn cmd_adjust(
     &CommandContext {
-        state_mutex, args, ..
+        state_mutex,
+        args,
+        env,
+        ..
     }: &CommandContext,
 ) -> Result<Response, Error> {
     let state = state_mutex.lock()?;
     let open_order = state.demand_open_order()?;
+    let currency = restaurant_currency(env, &order_restaurant(&state, &open_order)?);
 
+    let args = args.trim();
     if args.len() == 0 {
-        Ok(Response::Overhead {
-            overhead_in_cents: open_order.overhead_in_cents,
-        })
-    } else {
-        let prev_overhead_in_cents = open_order.overhead_in_cents;
-
-        let overhead = args.parse::<f64>()?;
-        let new_overhead_in_cents = (overhead * 100.0).round() as i32;
+        return Ok(Response::Adjustments {
+            currency,
+            adjustments: order_totals(&state, &open_order)?.adjustments,
+        });
+    }
 
-        state.set_overhead(open_order.id, new_overhead_in_cents)?;
+    let mut split = args.splitn(2, ' ');
+    let first = split.next().unwrap();
+    let rest = split.next().unwrap_or("").trim();
 
-        Ok(Response::OverheadSet {
-            prev_overhead_in_cents,
-            new_overhead_in_cents,
-        })
+    if first == "remove" {
+        let id = OrderAdjustmentId::from(rest.parse::<i32>()?);
+        if !state.remove_order_adjustment(open_order.id, id)? {
+            return Err(Error::NotFound);
+        }
+        return Ok(Response::AdjustmentRemoved { id });
     }
+
+    // The amount can be given either first or last, as in
+    // `adjust 79 delivery` or `adjust tip 10%`
+    let mut rsplit = args.rsplitn(2, ' ');
+    let last = rsplit.next().unwrap();
+    let init = rsplit.next().unwrap_or("").trim();
+
+    let ((kind, amount), description) = match parse_adjustment(first) {
+        Ok(adjustment) => (adjustment, rest),
+        Err(err) => match parse_adjustment(last) {
+            Ok(adjustment) => (adjustment, init),
+            Err(_) => return Err(err),
+        },
+    };
+    let description = match description {
+        "" => "adjustment",
+        x => x,
+    };
+
+    let id = state.add_order_adjustment(open_order.id, kind, &amount, description)?;
+
+    Ok(Response::AdjustmentAdded {
+        currency,
+        adjustment: Adjustment {
+            id,
+            kind,
+            amount,
+            description: description.to_owned(),
+        },
+    })
 }
 
 fn cmd_sudo(cmd_ctx: &CommandContext) -> Result<Response, Error> {
@@ -489,27 +2176,1083 @@ type CommandHandler = dyn Fn(&CommandContext) -> Result<Response, Error> + Sync;
 lazy_static! {
     pub static ref COMMAND_MAP: HashMap<&'static str, &'static CommandHandler> = {
         let mut m: HashMap<&'static str, &'static CommandHandler> = HashMap::new();
+        m.insert("adjust", &cmd_adjust);
+        m.insert("alias", &cmd_alias);
         m.insert("associate", &cmd_associate);
+        m.insert("balance", &cmd_balance);
+        m.insert("balances", &cmd_balances);
         m.insert("clear", &cmd_clear);
         m.insert("cancel", &cmd_clear);
         m.insert("reset", &cmd_clear);
         m.insert("closeorder", &cmd_closeorder);
+        m.insert("contact", &cmd_contact);
+        m.insert("currency", &cmd_currency);
+        m.insert("fav", &cmd_fav);
         m.insert("help", &cmd_help);
+        m.insert("history", &cmd_history);
+        m.insert("limits", &cmd_limits);
+        m.insert("log", &cmd_log);
         m.insert("openorder", &cmd_openorder);
         m.insert("open", &cmd_openorder);
         m.insert("order", &cmd_order);
+        m.insert("ordersheet", &cmd_ordersheet);
         m.insert("overhead", &cmd_overhead);
+        m.insert("pay", &cmd_pay);
+        m.insert("pick", &cmd_pick);
+        m.insert("poll", &cmd_poll);
         m.insert("tips", &cmd_overhead);
         m.insert("price", &cmd_price);
+        m.insert("rate", &cmd_rate);
         m.insert("repeat", &cmd_repeat);
         m.insert("reorder", &cmd_repeat);
         m.insert("retweet", &cmd_repeat);
+        m.insert("restaurant", &cmd_restaurant);
         m.insert("restaurants", &cmd_restaurants);
         m.insert("search", &cmd_search);
-        m.insert("sharebill", &cmd_sharebill);
+        m.insert("send", &cmd_send);
+        m.insert("settle", &cmd_settle);
+        m.insert("sharebill", &cmd_settle);
         m.insert("sudo", &cmd_sudo);
         m.insert("suggest", &cmd_suggest);
         m.insert("summary", &cmd_summary);
+        m.insert("undo", &cmd_undo);
+        m.insert("vote", &cmd_vote);
         m
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::super::exec_cmd;
+    use super::*;
+    use db;
+    use mail::sink::SmtpSink;
+    use models::Frontend;
+    use settlement::Receipt;
+    use sharebill::client::Auth;
+    use sharebill::mock::MockSharebill;
+    use sharebill::models::{Meta, Post, Transaction};
+    use std::net::TcpListener;
+    use std::sync::Mutex;
+    use takedown;
+    use time;
+
+    fn fabricate_state() -> Mutex<state::State> {
+        let state = state::State::new(db::connect_database(":memory:", true));
+
+        let restaurant = state.create_restaurant("Sushi Bar").unwrap();
+        let menu: takedown::Menu = serde_json::from_str(include_str!("../../take.json")).unwrap();
+        state.ingest_menu(restaurant, &menu).unwrap();
+
+        let menu = state.current_menu_for_restaurant(restaurant).unwrap();
+        state.create_order(menu.id, None, None).unwrap();
+
+        state.set_association("alice", "Alice").unwrap();
+        state.set_association("bob", "Bob").unwrap();
+
+        Mutex::new(state)
+    }
+
+    fn fabricate_env(mock: &MockSharebill) -> web::Env {
+        web::Env {
+            base_url: "http://localhost:3000/".to_owned(),
+            maybe_sharebill_url: Some(mock.url().to_owned()),
+            sharebill_auth: Auth::None,
+            settlement: None,
+            currency: Currency::from_code("NOK").unwrap(),
+            exchange_rates: BTreeMap::new(),
+            enforce_budget: false,
+            email: None,
+            smtp_password: None,
+        }
+    }
+
+    fn exec(
+        state_mutex: &Mutex<state::State>,
+        env: &web::Env,
+        user_name: &str,
+        cmd: &str,
+        args: &str,
+    ) -> Result<Response, Error> {
+        exec_cmd(
+            cmd,
+            &CommandContext {
+                state_mutex,
+                args,
+                user_name,
+                env,
+                frontend: Frontend::Slack,
+                real_user_name: user_name,
+            },
+        )
+    }
+
+    fn place_orders(state_mutex: &Mutex<state::State>, env: &web::Env) {
+        exec(state_mutex, env, "alice", "order", "513").unwrap();
+        exec(state_mutex, env, "bob", "order", "515").unwrap();
+    }
+
+    #[test]
+    fn sharebill_posts_and_closes_order() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+        place_orders(&state_mutex, &env);
+
+        match exec(&state_mutex, &env, "alice", "sharebill", "").unwrap() {
+            Response::Settled {
+                receipt: Receipt::Posted { url },
+                ..
+            } => assert!(url.starts_with(mock.url())),
+            _ => panic!("Expected a Sharebill post"),
+        }
+
+        let posts = mock.posts();
+        assert_eq!(1, posts.len());
+        let post = posts.values().next().unwrap();
+        assert_eq!("Sushi Bar", &post.meta.description);
+        assert_eq!(Some(&Rational::from(59)), post.transaction.debits.get("Alice"));
+        assert_eq!(Some(&Rational::from(69)), post.transaction.debits.get("Bob"));
+        assert_eq!(Some(&Rational::from(128)), post.transaction.credits.get("Alice"));
+
+        assert!(state_mutex.lock().unwrap().current_open_order().unwrap().is_none());
+    }
+
+    #[test]
+    fn settle_previews_without_settling() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+        place_orders(&state_mutex, &env);
+
+        for args in &["preview", "preview Bob"] {
+            match exec(&state_mutex, &env, "alice", "sharebill", args).unwrap() {
+                Response::SettlementPreview { .. } => (),
+                _ => panic!("Expected a preview for sharebill {}", args),
+            }
+        }
+
+        assert!(!mock.requests().iter().any(|x| x.method == "PUT"));
+        assert!(state_mutex.lock().unwrap().current_open_order().unwrap().is_some());
+    }
+
+    #[test]
+    fn sharebill_converts_foreign_currency() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+        place_orders(&state_mutex, &env);
+
+        exec(&state_mutex, &env, "alice", "currency", "eur").unwrap();
+        match exec(&state_mutex, &env, "alice", "sharebill", "") {
+            Err(Error::MissingExchangeRate(ref code)) if code == "EUR" => (),
+            _ => panic!("Expected a missing exchange rate"),
+        }
+
+        exec(&state_mutex, &env, "alice", "rate", "11.5").unwrap();
+        exec(&state_mutex, &env, "alice", "sharebill", "").unwrap();
+
+        let posts = mock.posts();
+        let post = posts.values().next().unwrap();
+        assert_eq!("Sushi Bar (€128.00 at 11.5000 NOK/EUR)", &post.meta.description);
+        let debit = "678.5".parse::<Rational>().unwrap();
+        assert_eq!(Some(&debit), post.transaction.debits.get("Alice"));
+        assert_eq!(Some(&Rational::from(1472)), post.transaction.credits.get("Alice"));
+    }
+
+    #[test]
+    fn sharebill_retry_does_not_bill_twice() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+        place_orders(&state_mutex, &env);
+
+        mock.drop_next_response();
+        assert!(exec(&state_mutex, &env, "alice", "sharebill", "").is_err());
+        assert!(state_mutex.lock().unwrap().current_open_order().unwrap().is_some());
+
+        exec(&state_mutex, &env, "alice", "sharebill", "").unwrap();
+
+        assert_eq!(1, mock.posts().len());
+        let requests = mock.requests();
+        assert_eq!(2, requests.len());
+        assert_eq!(requests[0].path, requests[1].path);
+        assert!(state_mutex.lock().unwrap().current_open_order().unwrap().is_none());
+    }
+
+    #[test]
+    fn sharebill_retry_notices_changes() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+        place_orders(&state_mutex, &env);
+
+        // A post that never arrived is replaced by the current bill
+        {
+            let lost = Post {
+                meta: Meta {
+                    description: "Sushi Bar".to_owned(),
+                    timestamp: time::now(),
+                },
+                transaction: Transaction {
+                    debits: HashMap::new(),
+                    credits: HashMap::new(),
+                },
+            };
+            let state = state_mutex.lock().unwrap();
+            let open_order = state.demand_open_order().unwrap();
+            let payload = serde_json::to_string(&lost).unwrap();
+            state.set_sharebill_post(open_order.id, "lost", &payload).unwrap();
+        }
+        exec(&state_mutex, &env, "alice", "sharebill", "").unwrap();
+        let posts = mock.posts();
+        assert_eq!(1, posts.len());
+        assert!(!posts.contains_key("lost"));
+        assert_eq!(2, posts.values().next().unwrap().transaction.debits.len());
+
+        // A post that did arrive is not replaced
+        exec(&state_mutex, &env, "alice", "openorder", "Sushi Bar").unwrap();
+        place_orders(&state_mutex, &env);
+        mock.drop_next_response();
+        assert!(exec(&state_mutex, &env, "alice", "sharebill", "").is_err());
+
+        match exec(&state_mutex, &env, "alice", "sharebill", "Bob") {
+            Err(Error::SettlementError(settlement::Error::PostChanged(_))) => (),
+            _ => panic!("Expected the changed bill to be refused"),
+        }
+        assert_eq!(2, mock.posts().len());
+        assert!(state_mutex.lock().unwrap().current_open_order().unwrap().is_some());
+    }
+
+    #[test]
+    fn adjustments_are_itemized_and_shared() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+        place_orders(&state_mutex, &env);
+
+        exec(&state_mutex, &env, "alice", "adjust", "10 delivery").unwrap();
+        exec(&state_mutex, &env, "alice", "adjust", "discount -10%").unwrap();
+        exec(&state_mutex, &env, "alice", "adjust", "1/person bags").unwrap();
+
+        match exec(&state_mutex, &env, "alice", "price", "").unwrap() {
+            Response::Price {
+                summary,
+                adjustments,
+                ..
+            } => {
+                let totals = adjustments.iter().map(|x| format!("{:#}", x.1)).collect::<Vec<_>>();
+                assert_eq!(vec!["10.00", "-12.80", "2.00"], totals);
+
+                let summary = summary.iter().map(|x| format!("{:#}", x.1)).collect::<Vec<_>>();
+                assert_eq!(vec!["59.10", "68.10"], summary);
+            }
+            _ => panic!("Expected a price summary"),
+        }
+
+        exec(&state_mutex, &env, "alice", "sharebill", "").unwrap();
+
+        let posts = mock.posts();
+        let post = posts.values().next().unwrap();
+        assert_eq!(
+            "Sushi Bar\n\
+             delivery: 10.00 kr\n\
+             discount (-10%): -12.80 kr\n\
+             bags (1.00 kr per person): 2.00 kr",
+            &post.meta.description
+        );
+    }
+
+    #[test]
+    fn negative_adjustments_can_be_read_back() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+        place_orders(&state_mutex, &env);
+
+        exec(&state_mutex, &env, "alice", "adjust", "-12.50 coupon").unwrap();
+
+        match exec(&state_mutex, &env, "alice", "price", "").unwrap() {
+            Response::Price { adjustments, .. } => {
+                let totals = adjustments.iter().map(|x| format!("{:#}", x.1)).collect::<Vec<_>>();
+                assert_eq!(vec!["-12.50"], totals);
+            }
+            _ => panic!("Expected a price summary"),
+        }
+        exec(&state_mutex, &env, "alice", "summary", "").unwrap();
+        exec(&state_mutex, &env, "alice", "sharebill", "").unwrap();
+    }
+
+    #[test]
+    fn pay_refuses_odd_payments() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+
+        for args in &["bob 0", "bob -12.50", "Alice 12.50"] {
+            match exec(&state_mutex, &env, "alice", "pay", args) {
+                Err(Error::InputError) => (),
+                _ => panic!("Expected pay {} to be refused", args),
+            }
+        }
+    }
+
+    #[test]
+    fn overhead_refuses_huge_amounts() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+
+        for args in &["1e30", "1e12"] {
+            match exec(&state_mutex, &env, "alice", "overhead", args) {
+                Err(Error::InputError) => (),
+                _ => panic!("Expected overhead {} to be refused", args),
+            }
+        }
+    }
+
+    #[test]
+    fn limits_refuse_huge_amounts() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+
+        for args in &["min=1e30", "budget=1e12"] {
+            match exec(&state_mutex, &env, "alice", "limits", args) {
+                Err(Error::InputError) => (),
+                _ => panic!("Expected limits {} to be refused", args),
+            }
+        }
+    }
+
+    #[test]
+    fn enforced_budget_refuses_orders() {
+        let mock = MockSharebill::start();
+        let env = web::Env {
+            enforce_budget: true,
+            ..fabricate_env(&mock)
+        };
+        let state_mutex = fabricate_state();
+
+        exec(&state_mutex, &env, "alice", "limits", "budget=70").unwrap();
+        place_orders(&state_mutex, &env);
+
+        match exec(&state_mutex, &env, "alice", "order", "513").unwrap() {
+            Response::OrderRefused { over_budget, .. } => {
+                assert_eq!(Rational::from(118), over_budget.total);
+                assert_eq!(Rational::from(70), over_budget.budget);
+            }
+            _ => panic!("Expected the order to be refused"),
+        }
+
+        match exec(&state_mutex, &env, "alice", "price", "").unwrap() {
+            Response::Price { summary, .. } => {
+                let summary = summary.iter().map(|x| format!("{:#}", x.1)).collect::<Vec<_>>();
+                assert_eq!(vec!["59.00", "69.00"], summary);
+            }
+            _ => panic!("Expected a price summary"),
+        }
+    }
+
+    #[test]
+    fn enforced_budget_refuses_undoing_clear() {
+        let mock = MockSharebill::start();
+        let env = web::Env {
+            enforce_budget: true,
+            ..fabricate_env(&mock)
+        };
+        let state_mutex = fabricate_state();
+
+        place_orders(&state_mutex, &env);
+        exec(&state_mutex, &env, "alice", "clear", "").unwrap();
+        exec(&state_mutex, &env, "alice", "limits", "budget=50").unwrap();
+
+        match exec(&state_mutex, &env, "alice", "undo", "").unwrap() {
+            Response::UndoRefused { over_budget } => {
+                assert_eq!(Rational::from(59), over_budget.total);
+                assert_eq!(Rational::from(50), over_budget.budget);
+            }
+            _ => panic!("Expected the undo to be refused"),
+        }
+
+        match exec(&state_mutex, &env, "alice", "price", "").unwrap() {
+            Response::Price { summary, .. } => assert_eq!(1, summary.len()),
+            _ => panic!("Expected a price summary"),
+        }
+
+        exec(&state_mutex, &env, "alice", "limits", "budget=70").unwrap();
+        match exec(&state_mutex, &env, "alice", "undo", "").unwrap() {
+            Response::Undone { .. } => (),
+            _ => panic!("Expected the cleared items to be put back"),
+        }
+    }
+
+    #[test]
+    fn refused_pick_keeps_the_shortlist() {
+        let mock = MockSharebill::start();
+        let env = web::Env {
+            enforce_budget: true,
+            ..fabricate_env(&mock)
+        };
+        let state_mutex = fabricate_state();
+
+        exec(&state_mutex, &env, "alice", "limits", "budget=70").unwrap();
+        place_orders(&state_mutex, &env);
+        exec(&state_mutex, &env, "alice", "order", "salmon x5").unwrap();
+
+        for _ in 0..2 {
+            match exec(&state_mutex, &env, "alice", "pick", "1").unwrap() {
+                Response::OrderRefused { .. } => (),
+                _ => panic!("Expected the order to be refused"),
+            }
+        }
+    }
+
+    #[test]
+    fn contacts_are_kept_per_restaurant() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+
+        exec(&state_mutex, &env, "alice", "contact", "add +47 22 33 44 55").unwrap();
+        exec(&state_mutex, &env, "alice", "contact", "add orders@sushi.example").unwrap();
+
+        match exec(&state_mutex, &env, "alice", "contact", "Sushi Bar").unwrap() {
+            Response::Contacts {
+                phone_numbers,
+                email_addresses,
+                ..
+            } => {
+                assert_eq!(vec!["+47 22 33 44 55"], phone_numbers);
+                assert_eq!(vec!["orders@sushi.example"], email_addresses);
+            }
+            _ => panic!("Expected contacts"),
+        }
+
+        match exec(&state_mutex, &env, "alice", "contact", "sus").unwrap() {
+            Response::Contacts { restaurant_name, .. } => assert_eq!("Sushi Bar", restaurant_name),
+            _ => panic!("Expected contacts"),
+        }
+        match exec(&state_mutex, &env, "alice", "contact", "Sushi Baar").unwrap() {
+            Response::RestaurantSuggestion { command, .. } => {
+                assert_eq!("contact Sushi Bar", command)
+            }
+            _ => panic!("Expected a suggestion"),
+        }
+
+        exec(&state_mutex, &env, "alice", "closeorder", "").unwrap();
+        match exec(&state_mutex, &env, "alice", "openorder", "Sushi Bar").unwrap() {
+            Response::OpenedOrder { phone_numbers, .. } => {
+                assert_eq!(vec!["+47 22 33 44 55"], phone_numbers)
+            }
+            _ => panic!("Expected an opened order"),
+        }
+    }
+
+    #[test]
+    fn send_emails_order_to_restaurant() {
+        let mock = MockSharebill::start();
+        let sink = SmtpSink::start();
+        let env = web::Env {
+            email: Some(config::EmailConfig {
+                smtp_server: sink.address().to_owned(),
+                smtp_starttls: false,
+                smtp_username: None,
+                smtp_password: None,
+                from: "fishsticks@example.com".to_owned(),
+                contact_name: "Alice".to_owned(),
+                contact_phone: Some("+47 99 88 77 66".to_owned()),
+                delivery_address: "Storgata 1, Oslo".to_owned(),
+            }),
+            ..fabricate_env(&mock)
+        };
+        let state_mutex = fabricate_state();
+        place_orders(&state_mutex, &env);
+        exec(&state_mutex, &env, "alice", "order", "513").unwrap();
+        match exec(&state_mutex, &env, "alice", "contact", "add a@b.example\r\nDATA") {
+            Err(Error::InvalidEmailAddress(_)) => (),
+            _ => panic!("Expected the address to be refused"),
+        }
+        exec(&state_mutex, &env, "alice", "contact", "add orders@sushi.example").unwrap();
+
+        match exec(&state_mutex, &env, "alice", "send", "ring the bell").unwrap() {
+            Response::OrderSent { item_count, .. } => assert_eq!(3, item_count),
+            _ => panic!("Expected the order to be sent"),
+        }
+
+        let received = sink.received();
+        assert_eq!(1, received.len());
+        assert_eq!(vec!["orders@sushi.example"], received[0].to);
+        assert!(received[0].data.contains("  2 x 513. "));
+        assert!(received[0].data.contains("Note: ring the bell"));
+        assert!(received[0].data.contains("Contact: Alice, +47 99 88 77 66"));
+
+        match exec(&state_mutex, &env, "alice", "send", "") {
+            Err(Error::OrderAlreadySent) => (),
+            _ => panic!("Expected the order to only be sent once"),
+        }
+        exec(&state_mutex, &env, "alice", "send", "again").unwrap();
+        assert_eq!(2, sink.received().len());
+    }
+
+    #[test]
+    fn failed_send_can_be_retried() {
+        let mock = MockSharebill::start();
+        let smtp_server = {
+            // A port nobody is listening on
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        let env = web::Env {
+            email: Some(config::EmailConfig {
+                smtp_server,
+                smtp_starttls: false,
+                smtp_username: None,
+                smtp_password: None,
+                from: "fishsticks@example.com".to_owned(),
+                contact_name: "Alice".to_owned(),
+                contact_phone: None,
+                delivery_address: "Storgata 1, Oslo".to_owned(),
+            }),
+            ..fabricate_env(&mock)
+        };
+        let state_mutex = fabricate_state();
+        place_orders(&state_mutex, &env);
+        exec(&state_mutex, &env, "alice", "contact", "add orders@sushi.example").unwrap();
+
+        for _ in 0..2 {
+            match exec(&state_mutex, &env, "alice", "send", "") {
+                Err(Error::MailError(_)) => (),
+                _ => panic!("Expected the mail server to be unreachable"),
+            }
+        }
+
+        let state = state_mutex.lock().unwrap();
+        assert_eq!(None, state.demand_open_order().unwrap().sent);
+    }
+
+    #[test]
+    fn ordersheet_counts_items_with_notes() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+        place_orders(&state_mutex, &env);
+        exec(&state_mutex, &env, "bob", "order", "513 (no wasabi)").unwrap();
+
+        match exec(&state_mutex, &env, "alice", "ordersheet", "").unwrap() {
+            Response::OrderSheet { lines, total, .. } => {
+                let lines = lines
+                    .iter()
+                    .map(|x| (x.quantity, x.menu_item.number, x.notes.clone()))
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    vec![(2, 513, vec!["no wasabi".to_owned()]), (1, 515, vec![])],
+                    lines
+                );
+                assert_eq!(Rational::from(59 + 69 + 59), total);
+            }
+            _ => panic!("Expected an order sheet"),
+        }
+    }
+
+    #[test]
+    fn order_asks_when_search_is_vague() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+
+        match exec(&state_mutex, &env, "alice", "order", "salmon").unwrap() {
+            Response::OrderAmbiguous { matches, .. } => {
+                assert!(matches.len() > 1);
+                assert!(matches.len() <= search::SHORTLIST_LENGTH);
+            }
+            _ => panic!("Expected to be asked which salmon"),
+        }
+
+        match exec(&state_mutex, &env, "alice", "order", "salmon avo").unwrap() {
+            Response::PlacedOrder { menu_items, .. } => assert_eq!(605, menu_items[0].number),
+            _ => panic!("Expected an order for 605"),
+        }
+    }
+
+    #[test]
+    fn pick_answers_ambiguous_order() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+
+        match exec(&state_mutex, &env, "bob", "pick", "1") {
+            Err(Error::NoPendingChoice) => (),
+            _ => panic!("Expected nothing to pick from"),
+        }
+
+        let order = exec(&state_mutex, &env, "alice", "order", "salmon (no wasabi)").unwrap();
+        let second = match order {
+            Response::OrderAmbiguous { matches, .. } => matches[1].menu_item.number,
+            _ => panic!("Expected to be asked which salmon"),
+        };
+
+        match exec(&state_mutex, &env, "alice", "pick", "9") {
+            Err(Error::InvalidChoice(_)) => (),
+            _ => panic!("Expected the choice to be out of range"),
+        }
+        match exec(&state_mutex, &env, "alice", "pick", "2").unwrap() {
+            Response::PlacedOrder { menu_items, .. } => assert_eq!(second, menu_items[0].number),
+            _ => panic!("Expected the second match to be ordered"),
+        }
+        match exec(&state_mutex, &env, "alice", "pick", "2") {
+            Err(Error::NoPendingChoice) => (),
+            _ => panic!("Expected the choice to be used up"),
+        }
+
+        let state = state_mutex.lock().unwrap();
+        let open_order = state.demand_open_order().unwrap();
+        let items = state.items_in_order(open_order.id).unwrap();
+        assert_eq!(Some("no wasabi".to_owned()), items[0].1.note);
+    }
+
+    #[test]
+    fn pick_keeps_the_quantity() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+
+        match exec(&state_mutex, &env, "alice", "order", "513 x1000000000") {
+            Err(Error::InputError) => (),
+            _ => panic!("Expected the quantity to be too large"),
+        }
+
+        exec(&state_mutex, &env, "alice", "order", "salmon x2").unwrap();
+        match exec(&state_mutex, &env, "alice", "pick", "1").unwrap() {
+            Response::PlacedOrder { menu_items, .. } => assert_eq!(2, menu_items.len()),
+            _ => panic!("Expected two of the first match to be ordered"),
+        }
+    }
+
+    #[test]
+    fn order_takes_several_items_or_none() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+
+        match exec(&state_mutex, &env, "alice", "order", "513, pizza, salmon x2").unwrap() {
+            Response::OrderFailed { failures } => {
+                let failures = failures
+                    .iter()
+                    .map(|x| &x.search_string as &str)
+                    .collect::<Vec<_>>();
+                assert_eq!(vec!["pizza", "salmon"], failures);
+            }
+            _ => panic!("Expected the order to fail"),
+        }
+
+        let order = "513, 605 x2 (no wasabi, please), avo salmon";
+        match exec(&state_mutex, &env, "alice", "order", order).unwrap() {
+            Response::PlacedOrder { menu_items, .. } => {
+                let numbers = menu_items.iter().map(|x| x.number).collect::<Vec<_>>();
+                assert_eq!(vec![513, 605, 605, 605], numbers);
+            }
+            _ => panic!("Expected four items to be ordered"),
+        }
+
+        let state = state_mutex.lock().unwrap();
+        let open_order = state.demand_open_order().unwrap();
+        let notes = state
+            .items_in_order(open_order.id)
+            .unwrap()
+            .into_iter()
+            .filter_map(|x| x.1.note)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["no wasabi, please"; 2], notes);
+    }
+
+    #[test]
+    fn undo_reverts_changes_in_reverse_order() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+        place_orders(&state_mutex, &env);
+
+        let item_count = || {
+            let state = state_mutex.lock().unwrap();
+            let open_order = state.demand_open_order().unwrap();
+            state.items_in_order(open_order.id).unwrap().len()
+        };
+
+        exec(&state_mutex, &env, "alice", "order", "605, 806").unwrap();
+        exec(&state_mutex, &env, "alice", "overhead", "30").unwrap();
+        exec(&state_mutex, &env, "alice", "clear", "").unwrap();
+        exec(&state_mutex, &env, "alice", "closeorder", "").unwrap();
+        assert!(state_mutex.lock().unwrap().current_open_order().unwrap().is_none());
+
+        exec(&state_mutex, &env, "alice", "undo", "").unwrap();
+        assert_eq!(1, item_count());
+        exec(&state_mutex, &env, "alice", "undo", "").unwrap();
+        assert_eq!(4, item_count());
+        exec(&state_mutex, &env, "alice", "undo", "").unwrap();
+        let overhead = state_mutex.lock().unwrap().demand_open_order().unwrap().overhead_in_cents;
+        assert_eq!(0, overhead);
+        exec(&state_mutex, &env, "alice", "undo", "").unwrap();
+        assert_eq!(2, item_count());
+
+        // Bob's order is his own to undo
+        exec(&state_mutex, &env, "alice", "undo", "").unwrap();
+        assert_eq!(1, item_count());
+        match exec(&state_mutex, &env, "alice", "undo", "") {
+            Err(Error::NothingToUndo) => (),
+            _ => panic!("Expected nothing left to undo"),
+        }
+        exec(&state_mutex, &env, "bob", "undo", "").unwrap();
+        assert_eq!(0, item_count());
+    }
+
+    #[test]
+    fn undo_after_restoring_takes_off_the_right_items() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+        place_orders(&state_mutex, &env);
+
+        exec(&state_mutex, &env, "alice", "order", "605, 806").unwrap();
+        exec(&state_mutex, &env, "alice", "clear", "").unwrap();
+        exec(&state_mutex, &env, "alice", "undo", "").unwrap();
+        exec(&state_mutex, &env, "alice", "undo", "").unwrap();
+
+        let state = state_mutex.lock().unwrap();
+        let open_order = state.demand_open_order().unwrap();
+        let mut numbers = state
+            .items_in_order(open_order.id)
+            .unwrap()
+            .into_iter()
+            .map(|x| (x.1.person_name, x.0.number))
+            .collect::<Vec<_>>();
+        numbers.sort();
+        assert_eq!(
+            vec![("alice".to_owned(), 513), ("bob".to_owned(), 515)],
+            numbers
+        );
+    }
+
+    #[test]
+    fn log_records_who_did_what() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+
+        exec(&state_mutex, &env, "alice", "order", "513").unwrap();
+        exec(&state_mutex, &env, "alice", "sudo", "bob order 515").unwrap();
+        exec(&state_mutex, &env, "bob", "undo", "again").unwrap();
+        exec(&state_mutex, &env, "bob", "undo", "").unwrap_err();
+        exec(&state_mutex, &env, "alice", "sudo", "bob").unwrap_err();
+
+        match exec(&state_mutex, &env, "alice", "log", "").unwrap() {
+            Response::Log { entries, .. } => {
+                let entries = entries
+                    .iter()
+                    .map(|x| {
+                        (
+                            x.real_user.as_str(),
+                            x.effective_user.as_str(),
+                            x.command.as_str(),
+                            x.outcome == "ok",
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    vec![
+                        ("alice", "alice", "sudo", false),
+                        ("bob", "bob", "undo", false),
+                        ("bob", "bob", "undo", true),
+                        ("alice", "bob", "order", true),
+                        ("alice", "alice", "order", true),
+                    ],
+                    entries
+                );
+            }
+            _ => panic!("Expected the log"),
+        }
+    }
+
+    #[test]
+    fn favorites_are_ordered_by_number_with_notes() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+
+        exec(&state_mutex, &env, "alice", "order", "513 (no wasabi), 605").unwrap();
+        exec(&state_mutex, &env, "alice", "fav", "save Lunch").unwrap();
+        exec(&state_mutex, &env, "alice", "closeorder", "").unwrap();
+
+        {
+            let state = state_mutex.lock().unwrap();
+            let restaurant = state.restaurant_by_name("Sushi Bar").unwrap().unwrap();
+            let menu: takedown::Menu = serde_json::from_str(include_str!("../../take.json")).unwrap();
+            state.ingest_menu(restaurant.id, &menu).unwrap();
+        }
+        exec(&state_mutex, &env, "alice", "openorder", "Sushi Bar").unwrap();
+
+        match exec(&state_mutex, &env, "bob", "fav", "order lunch") {
+            Err(Error::NotFound) => (),
+            _ => panic!("Expected favorites to be personal"),
+        }
+        exec(&state_mutex, &env, "alice", "fav", "order lunch").unwrap();
+
+        let state = state_mutex.lock().unwrap();
+        let open_order = state.demand_open_order().unwrap();
+        let items = state
+            .items_in_order(open_order.id)
+            .unwrap()
+            .into_iter()
+            .map(|x| (x.0.number, x.1.note))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(513, Some("no wasabi".to_owned())), (605, None)], items);
+    }
+
+    #[test]
+    fn repeat_chooses_from_recent_orders_and_notices_changes() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+
+        for item in &["513", "605", "605"] {
+            exec(&state_mutex, &env, "alice", "order", item).unwrap();
+            exec(&state_mutex, &env, "alice", "closeorder", "").unwrap();
+            exec(&state_mutex, &env, "alice", "openorder", "Sushi Bar").unwrap();
+        }
+
+        match exec(&state_mutex, &env, "alice", "repeat", "list").unwrap() {
+            Response::PreviousOrders { orders } => {
+                let numbers = orders
+                    .iter()
+                    .map(|x| x.menu_items.iter().map(|x| x.number).collect::<Vec<_>>())
+                    .collect::<Vec<_>>();
+                assert_eq!(vec![vec![605], vec![513]], numbers);
+            }
+            _ => panic!("Expected a list of previous orders"),
+        }
+
+        match exec(&state_mutex, &env, "alice", "repeat", "2").unwrap() {
+            Response::PlacedOrder { menu_items, .. } => assert_eq!(513, menu_items[0].number),
+            _ => panic!("Expected 513 to be ordered again"),
+        }
+        exec(&state_mutex, &env, "alice", "closeorder", "").unwrap();
+
+        // The salmon gets a new number
+        {
+            let state = state_mutex.lock().unwrap();
+            let restaurant = state.restaurant_by_name("Sushi Bar").unwrap().unwrap();
+            let mut menu: takedown::Menu =
+                serde_json::from_str(include_str!("../../take.json")).unwrap();
+            menu[0].entries[0].number = 520;
+            state.ingest_menu(restaurant.id, &menu).unwrap();
+        }
+        exec(&state_mutex, &env, "alice", "openorder", "Sushi Bar").unwrap();
+
+        match exec(&state_mutex, &env, "alice", "repeat", "").unwrap() {
+            Response::RepeatChanged { misses, .. } => {
+                assert_eq!(513, misses[0].previous.number);
+                assert_eq!(Some(520), misses[0].replacement.as_ref().map(|x| x.number));
+            }
+            _ => panic!("Expected the change to be noticed"),
+        }
+    }
+
+    #[test]
+    fn poll_picks_the_restaurant_with_most_votes() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+
+        state_mutex.lock().unwrap().create_restaurant("Pizza Place").unwrap();
+
+        exec(&state_mutex, &env, "alice", "poll", "start Sushi Bar, Pizza Place").unwrap();
+        exec(&state_mutex, &env, "alice", "vote", "1").unwrap();
+        exec(&state_mutex, &env, "alice", "vote", "Sushi Bar").unwrap();
+        exec(&state_mutex, &env, "bob", "vote", "sushi").unwrap();
+        exec(&state_mutex, &env, "carol", "vote", "Pizza Place").unwrap();
+
+        match exec(&state_mutex, &env, "alice", "poll", "close open") {
+            Err(Error::StateError(state::Error::OrderAlreadyOpen(_))) => (),
+            _ => panic!("Expected the open order to get in the way"),
+        }
+
+        exec(&state_mutex, &env, "alice", "closeorder", "").unwrap();
+        match exec(&state_mutex, &env, "alice", "poll", "close open").unwrap() {
+            Response::PollClosed {
+                tally,
+                winner,
+                opened,
+            } => {
+                let votes = tally
+                    .into_iter()
+                    .map(|x| (x.restaurant_name, x.voters))
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    vec![
+                        ("Pizza Place".to_owned(), vec!["carol".to_owned()]),
+                        ("Sushi Bar".to_owned(), vec!["alice".to_owned(), "bob".to_owned()]),
+                    ],
+                    votes
+                );
+                assert_eq!(Some("Sushi Bar".to_owned()), winner);
+                assert!(opened.is_some());
+            }
+            _ => panic!("Expected the poll to close"),
+        }
+
+        match exec(&state_mutex, &env, "alice", "vote", "Sushi Bar") {
+            Err(Error::StateError(state::Error::NoOpenPoll)) => (),
+            _ => panic!("Expected the poll to be over"),
+        }
+        assert!(state_mutex.lock().unwrap().current_open_order().unwrap().is_some());
+    }
+
+    #[test]
+    fn poll_opens_the_winner_after_a_rename() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+
+        state_mutex.lock().unwrap().create_restaurant("Pizza Place").unwrap();
+
+        exec(&state_mutex, &env, "alice", "poll", "start Sushi Bar, Pizza Place").unwrap();
+        exec(&state_mutex, &env, "alice", "vote", "Sushi Bar").unwrap();
+        exec(&state_mutex, &env, "alice", "restaurant", "rename Sushi Bar = Sushi Place").unwrap();
+        exec(&state_mutex, &env, "alice", "restaurant", "archive Sushi Place").unwrap();
+        exec(&state_mutex, &env, "alice", "closeorder", "").unwrap();
+
+        match exec(&state_mutex, &env, "alice", "poll", "close open").unwrap() {
+            Response::PollClosed { winner, opened, .. } => {
+                assert_eq!(Some("Sushi Place".to_owned()), winner);
+                assert!(opened.is_some());
+            }
+            _ => panic!("Expected the poll to close"),
+        }
+    }
+
+    #[test]
+    fn item_aliases_survive_menu_imports() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+
+        exec(&state_mutex, &env, "alice", "alias", "add The Usual 605").unwrap();
+        exec(&state_mutex, &env, "alice", "closeorder", "").unwrap();
+
+        {
+            let state = state_mutex.lock().unwrap();
+            let restaurant = state.restaurant_by_name("Sushi Bar").unwrap().unwrap();
+            let menu: takedown::Menu = serde_json::from_str(include_str!("../../take.json")).unwrap();
+            state.ingest_menu(restaurant.id, &menu).unwrap();
+        }
+        exec(&state_mutex, &env, "alice", "openorder", "Sushi Bar").unwrap();
+
+        match exec(&state_mutex, &env, "alice", "order", "the usual").unwrap() {
+            Response::PlacedOrder { menu_items, .. } => assert_eq!(605, menu_items[0].number),
+            _ => panic!("Expected an order for 605"),
+        }
+        match exec(&state_mutex, &env, "bob", "order", "#513").unwrap() {
+            Response::PlacedOrder { menu_items, .. } => assert_eq!(513, menu_items[0].number),
+            _ => panic!("Expected an order for 513"),
+        }
+
+        exec(&state_mutex, &env, "alice", "alias", "remove the usual").unwrap();
+        match exec(&state_mutex, &env, "alice", "alias", "").unwrap() {
+            Response::ItemAliases { aliases, .. } => assert!(aliases.is_empty()),
+            _ => panic!("Expected a list of aliases"),
+        }
+    }
+
+    #[test]
+    fn archived_restaurants_cannot_be_ordered_from() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+        exec(&state_mutex, &env, "alice", "closeorder", "").unwrap();
+
+        exec(&state_mutex, &env, "alice", "restaurant", "rename sushi bar = Sushi Place").unwrap();
+        exec(&state_mutex, &env, "alice", "restaurant", "archive Sushi Place").unwrap();
+
+        match exec(&state_mutex, &env, "alice", "openorder", "Sushi Place").unwrap() {
+            Response::RestaurantsNoMatch { restaurants } => assert!(restaurants.is_empty()),
+            _ => panic!("Expected archived restaurant to be hidden"),
+        }
+
+        exec(&state_mutex, &env, "alice", "restaurant", "unarchive Sushi Place").unwrap();
+        match exec(&state_mutex, &env, "alice", "openorder", "Sushi Place").unwrap() {
+            Response::OpenedOrder {
+                restaurant_name, ..
+            } => assert_eq!("Sushi Place", restaurant_name),
+            _ => panic!("Expected an opened order"),
+        }
+    }
+
+    #[test]
+    fn openorder_finds_restaurants_loosely() {
+        let mock = MockSharebill::start();
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+        exec(&state_mutex, &env, "alice", "closeorder", "").unwrap();
+
+        let open = |query| match exec(&state_mutex, &env, "alice", "openorder", query).unwrap() {
+            Response::OpenedOrder {
+                restaurant_name, ..
+            } => {
+                exec(&state_mutex, &env, "alice", "closeorder", "").unwrap();
+                restaurant_name
+            }
+            Response::RestaurantSuggestion { restaurant_name, .. } => format!("{}?", restaurant_name),
+            _ => "no match".to_owned(),
+        };
+
+        assert_eq!("Sushi Bar", open("sushi bar"));
+        assert_eq!("Sushi Bar", open("sus"));
+        assert_eq!("Sushi Bar?", open("Sushi Baar"));
+        assert_eq!("no match", open("Pizza Palace"));
+
+        exec(&state_mutex, &env, "alice", "restaurant", "alias Sushi Bar = raw fish").unwrap();
+        assert_eq!("Sushi Bar", open("Raw Fish"));
+
+        match exec(&state_mutex, &env, "alice", "openorder", "Sushi Baar budget=100").unwrap() {
+            Response::RestaurantSuggestion { command, .. } => {
+                assert_eq!("openorder Sushi Bar budget=100", command)
+            }
+            _ => panic!("Expected a suggestion"),
+        }
+    }
+
+    #[test]
+    fn restaurants_can_be_named_by_numbers() {
+        let state = state::State::new(db::connect_database(":memory:", true));
+        let sushi_bar = state.create_restaurant("Sushi Bar").unwrap();
+        state.create_restaurant("1881").unwrap();
+
+        let find = |query: &str| match state.find_restaurant(query).unwrap() {
+            state::RestaurantMatch::Found(restaurant) => restaurant.name,
+            _ => "no match".to_owned(),
+        };
+
+        assert_eq!("Sushi Bar", find(&i32::from(sushi_bar).to_string()));
+        assert_eq!("1881", find("1881"));
+    }
+
+    #[test]
+    fn suggest_picks_the_poorest() {
+        let mock = MockSharebill::start();
+        mock.set_balance("Alice", Rational::from(100));
+        mock.set_balance("Bob", Rational::from(-50));
+        mock.set_balance("Carol", Rational::from(-500));
+        let env = fabricate_env(&mock);
+        let state_mutex = fabricate_state();
+        place_orders(&state_mutex, &env);
+
+        match exec(&state_mutex, &env, "alice", "suggest", "").unwrap() {
+            Response::Suggest { balances, .. } => {
+                let accounts = balances.iter().map(|x| &x.0 as &str).collect::<Vec<_>>();
+                assert_eq!(vec!["Bob", "Alice"], accounts);
+                assert_eq!(Rational::from(-119), balances[0].2);
+            }
+            _ => panic!("Expected a suggestion"),
+        }
+    }
+}
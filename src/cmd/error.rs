@@ -1,22 +1,46 @@
+use diesel;
+use mail;
+use settlement;
+use sharebill::rational::ParseRationalError;
 use state;
 use std;
 
+use super::response::OverBudget;
+
 quick_error! {
     #[derive(Debug)]
     pub enum Error {
         StateError(err: state::Error) { from() }
+        SettlementError(err: settlement::Error) { from() }
         UrlDecodingError(err: urlencoded::UrlDecodingError) { from() }
         PoisonError
-        InputError { from(std::num::ParseFloatError) }
+        InputError { from(std::num::ParseFloatError) from(std::num::ParseIntError) }
+        InvalidAmount(err: ParseRationalError) { from() }
         InvalidSlackToken
-        MissingAssociation(slack_name: String)
+        MissingAssociations(slack_names: Vec<String>)
         SerdeJson(err: serde_json::Error) { from() }
-        UnexpectedStatus(status: reqwest::StatusCode)
         NotFound
-        MissingConfig(config_path: &'static str)
         FormatError(err: std::fmt::Error) { from() }
-        ReqwestError(err: reqwest::Error) { from() }
         MissingArgument(arg: &'static str)
+        InvalidCurrency(code: String)
+        MissingExchangeRate(currency: String)
+        MailError(err: mail::Error) { from() }
+        EmailNotConfigured
+        MissingEmailAddress(restaurant_name: String)
+        InvalidEmailAddress(address: String)
+        OrderAlreadySent
+        EmptyOrder
+        NoPendingChoice
+        InvalidChoice(count: usize)
+        NothingToUndo
+        OverBudget(over_budget: OverBudget)
+        NotInPoll(restaurant_name: String)
+    }
+}
+
+impl std::convert::From<diesel::result::Error> for Error {
+    fn from(err: diesel::result::Error) -> Self {
+        Error::StateError(state::Error::Diesel(err))
     }
 }
 
@@ -8,7 +8,10 @@ use self::commands::COMMAND_MAP;
 pub use self::error::Error;
 pub use self::response::*;
 
-pub fn exec_cmd(cmd: &str, cmd_ctx: &CommandContext) -> Result<Response, Error> {
+use models::OrderId;
+use state;
+
+fn dispatch(cmd: &str, cmd_ctx: &CommandContext) -> Result<Response, Error> {
     match COMMAND_MAP.get(cmd) {
         Some(cmd) => cmd(cmd_ctx),
         _ => Ok(Response::UnknownCommand {
@@ -17,3 +20,54 @@ pub fn exec_cmd(cmd: &str, cmd_ctx: &CommandContext) -> Result<Response, Error>
         }),
     }
 }
+
+fn audit(
+    cmd: &str,
+    cmd_ctx: &CommandContext,
+    order_before: Option<OrderId>,
+    result: &Result<Response, Error>,
+) -> Result<(), Error> {
+    let outcome = match *result {
+        Ok(_) => "ok".to_owned(),
+        Err(ref err) => format!("{:?}", err),
+    };
+
+    // Commands like openorder have no order until they have run
+    let state = cmd_ctx.state_mutex.lock()?;
+    let order = match order_before {
+        Some(order) => Some(order),
+        None => state.current_open_order()?.map(|x| x.id),
+    };
+
+    state.record_audit(&state::Audit {
+        frontend: cmd_ctx.frontend,
+        real_user: cmd_ctx.real_user_name,
+        effective_user: cmd_ctx.user_name,
+        order,
+        command: cmd,
+        args: cmd_ctx.args,
+        outcome: &outcome,
+    })?;
+
+    Ok(())
+}
+
+/// Runs the command and records it in the audit log
+pub fn exec_cmd(cmd: &str, cmd_ctx: &CommandContext) -> Result<Response, Error> {
+    // sudo is recorded as the command it runs, if it gets as far as
+    // running one
+    if cmd == "sudo" && cmd_ctx.args.splitn(3, ' ').nth(1).is_some() {
+        return dispatch(cmd, cmd_ctx);
+    }
+
+    let order_before = cmd_ctx.state_mutex.lock()?.current_open_order()?.map(|x| x.id);
+    let result = dispatch(cmd, cmd_ctx);
+
+    // The command has had its effect by now, so failing to record it
+    // should not fail the command
+    if let Err(err) = audit(cmd, cmd_ctx, order_before, &result) {
+        eprintln!("Unable to record {:?} in the audit log: {:?}", cmd, err);
+    }
+
+    result
+}
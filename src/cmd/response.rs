@@ -1,21 +1,118 @@
+use currency::Currency;
 use models::*;
+use ordersheet;
+use search;
+use settlement::Receipt;
 use sharebill::Rational;
 
+pub struct Adjustment {
+    pub id: OrderAdjustmentId,
+    pub kind: AdjustmentKind,
+    pub amount: Rational,
+    pub description: String,
+}
+
+impl Adjustment {
+    /// Describes the adjustment along with `total`, its effect on the
+    /// order as a whole, as in `tip (10%): 12.80 kr`
+    pub fn describe(&self, total: &Rational, currency: &Currency) -> String {
+        match self.kind {
+            AdjustmentKind::Fixed => format!("{}: {}", self.description, currency.format(total)),
+            AdjustmentKind::Percentage => format!(
+                "{} ({}%): {}",
+                self.description,
+                self.amount,
+                currency.format(total)
+            ),
+            AdjustmentKind::PerPerson => format!(
+                "{} ({} per person): {}",
+                self.description,
+                currency.format(&self.amount),
+                currency.format(total)
+            ),
+        }
+    }
+}
+
+/// An item in an order that could not be made out, along with the best
+/// candidates, if any
+pub struct OrderFailure {
+    pub search_string: String,
+    pub matches: Vec<search::Match>,
+}
+
+/// One of the orders `repeat` can repeat
+pub struct PreviousOrder {
+    pub closed: i32,
+    pub menu_items: Vec<MenuItem>,
+}
+
+/// An item from an earlier order that is gone from the menu or has changed
+/// price, along with what it might have become
+pub struct RepeatMiss {
+    pub previous: MenuItem,
+    pub replacement: Option<MenuItem>,
+}
+
+/// A restaurant in a poll and the people who voted for it
+pub struct PollChoice {
+    pub restaurant_id: RestaurantId,
+    pub restaurant_name: String,
+    pub voters: Vec<String>,
+}
+
+/// Someone's total for the order, when it is over the budget
+#[derive(Debug)]
+pub struct OverBudget {
+    pub currency: Currency,
+    pub total: Rational,
+    pub budget: Rational,
+}
+
+/// How far the order has come towards the minimum order value
+pub struct MinimumOrder {
+    pub currency: Currency,
+    pub total: Rational,
+    pub minimum: Rational,
+}
+
 pub enum Response {
     UnknownCommand {
         cmd: String,
         args: String,
     },
     RepeatNoMatch,
+    PreviousOrders {
+        orders: Vec<PreviousOrder>,
+    },
+    RepeatChanged {
+        currency: Currency,
+
+        /// The items that are still on the menu as they were
+        unchanged: Vec<MenuItem>,
+        misses: Vec<RepeatMiss>,
+    },
     OrderNoMatch {
         search_string: String,
     },
+    OrderAmbiguous {
+        search_string: String,
+        matches: Vec<search::Match>,
+    },
+    OrderFailed {
+        failures: Vec<OrderFailure>,
+    },
     PlacedOrder {
         menu_items: Vec<MenuItem>,
+        over_budget: Option<OverBudget>,
+    },
+    OrderRefused {
+        menu_items: Vec<MenuItem>,
+        over_budget: OverBudget,
     },
     SearchResults {
         query: String,
-        items: Vec<MenuItem>,
+        matches: Vec<search::Match>,
     },
     Restaurants {
         restaurants: Vec<Restaurant>,
@@ -23,12 +120,102 @@ pub enum Response {
     RestaurantsNoMatch {
         restaurants: Vec<Restaurant>,
     },
+    RestaurantSuggestion {
+        restaurant_name: String,
+        /// The command to run for the suggestion, without the leading slash
+        command: String,
+    },
+    RestaurantAmbiguous {
+        query: String,
+        restaurants: Vec<Restaurant>,
+    },
+    RestaurantDetails {
+        restaurant: Restaurant,
+        aliases: Vec<String>,
+    },
+    RestaurantAliasAdded {
+        restaurant_name: String,
+        alias: String,
+    },
+    RestaurantAliasRemoved {
+        alias: String,
+    },
+    RestaurantRenamed {
+        old_name: String,
+        new_name: String,
+    },
+    RestaurantArchived {
+        restaurant_name: String,
+        archived: bool,
+    },
     OpenedOrder {
         menu_url: String,
         restaurant_name: String,
+        currency: Currency,
+        minimum_order_in_cents: Option<i32>,
+        budget_per_person_in_cents: Option<i32>,
+        phone_numbers: Vec<String>,
     },
     ClosedOrder,
+    PollStarted {
+        started_by: String,
+
+        /// In the order they are numbered for `vote`
+        choices: Vec<String>,
+    },
+    Poll {
+        started_by: String,
+        tally: Vec<PollChoice>,
+    },
+    Voted {
+        restaurant_name: String,
+    },
+    PollClosed {
+        tally: Vec<PollChoice>,
+
+        /// `None` when nobody voted or it is a tie
+        winner: Option<String>,
+
+        /// The response to opening an order at the winner, when asked to
+        opened: Option<Box<Response>>,
+    },
+    OrderSheet {
+        currency: Currency,
+        lines: Vec<ordersheet::Line>,
+        total: Rational,
+        url: String,
+    },
+    PackingList {
+        persons: Vec<(String, Vec<ordersheet::Line>)>,
+        url: String,
+    },
+    OrderSent {
+        restaurant_name: String,
+        email_address: String,
+        item_count: usize,
+    },
     Clear,
+    Favorites {
+        restaurant_name: String,
+
+        /// The name of each favorite with its menu numbers and notes
+        favorites: Vec<(String, Vec<(i32, Option<String>)>)>,
+        url: String,
+    },
+    FavoriteSaved {
+        name: String,
+        item_count: usize,
+    },
+    FavoriteRemoved {
+        name: String,
+    },
+    Undone {
+        currency: Currency,
+        undo: Undo,
+    },
+    UndoRefused {
+        over_budget: OverBudget,
+    },
     Associations {
         associations: Vec<SharebillAssociation>,
     },
@@ -36,26 +223,134 @@ pub enum Response {
         user_name: String,
         sharebill_account: String,
     },
-    Sharebill {
+    Settled {
+        backend: &'static str,
+        receipt: Receipt,
+    },
+    SettlementPreview {
+        backend: &'static str,
+        payload: String,
+        pending: bool,
+    },
+    MissingAssociations {
+        slack_names: Vec<String>,
+    },
+    History {
+        orders: Vec<(String, Order)>,
+    },
+    Log {
+        entries: Vec<AuditEntry>,
         url: String,
     },
     Overhead {
+        currency: Currency,
         overhead_in_cents: i32,
     },
     OverheadSet {
+        currency: Currency,
         prev_overhead_in_cents: i32,
         new_overhead_in_cents: i32,
     },
     Summary {
         orders: Vec<(String, Vec<MenuItem>)>,
+        minimum: Option<MinimumOrder>,
     },
     Price {
+        currency: Currency,
         overhead: Rational,
         overhead_per_person: Rational,
-        summary: Vec<(String, f64, Vec<MenuItem>)>,
+        adjustments: Vec<(Adjustment, Rational)>,
+        summary: Vec<(String, Rational, Vec<MenuItem>)>,
     },
     Suggest {
+        backend: &'static str,
+        currency: Currency,
         balances: Vec<(String, Rational, Rational)>,
     },
+    Balance {
+        account: String,
+        balance: Rational,
+        currency: Currency,
+    },
+    Balances {
+        backend: &'static str,
+        currency: Currency,
+        balances: Vec<(String, Rational)>,
+    },
+    Paid {
+        from: String,
+        to: String,
+        amount: Rational,
+        currency: Currency,
+    },
+    Currency {
+        restaurant_name: String,
+        currency: Currency,
+    },
+    CurrencySet {
+        restaurant_name: String,
+        currency: Currency,
+    },
+    Contacts {
+        restaurant_name: String,
+        phone_numbers: Vec<String>,
+        email_addresses: Vec<String>,
+    },
+    ItemAliases {
+        restaurant_name: String,
+
+        /// The alias, the item number and the name of that item on the
+        /// current menu, if it is still there
+        aliases: Vec<(String, i32, Option<String>)>,
+    },
+    ItemAliasAdded {
+        restaurant_name: String,
+        alias: String,
+        menu_item: MenuItem,
+    },
+    ItemAliasRemoved {
+        restaurant_name: String,
+        alias: String,
+    },
+    ContactAdded {
+        restaurant_name: String,
+        contact: String,
+    },
+    ContactRemoved {
+        restaurant_name: String,
+        contact: String,
+    },
+    ExchangeRate {
+        from: Currency,
+        to: Currency,
+        rate: Option<Rational>,
+    },
+    ExchangeRateSet {
+        from: Currency,
+        to: Currency,
+        rate: Rational,
+    },
+    Adjustments {
+        currency: Currency,
+        adjustments: Vec<(Adjustment, Rational)>,
+    },
+    AdjustmentAdded {
+        currency: Currency,
+        adjustment: Adjustment,
+    },
+    AdjustmentRemoved {
+        id: OrderAdjustmentId,
+    },
+    Limits {
+        currency: Currency,
+        minimum_order_in_cents: Option<i32>,
+        budget_per_person_in_cents: Option<i32>,
+    },
+    LimitsSet {
+        restaurant_name: String,
+        currency: Currency,
+        minimum_order_in_cents: Option<i32>,
+        budget_per_person_in_cents: Option<i32>,
+    },
     Help,
 }
@@ -1,6 +1,10 @@
 extern crate getopts;
 extern crate serde_json;
 
+use currency::Currency;
+use sharebill::client::Auth;
+use sharebill::Rational;
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
 use std::io;
@@ -11,9 +15,59 @@ quick_error! {
         Getopts(err: getopts::Fail) { from() }
         IoError(err: io::Error) { from() }
         SerdeJsonError(err: serde_json::Error) { from() }
+        MissingEnvironmentVariable(name: String)
+        InvalidCurrency(code: String)
     }
 }
 
+/// A secret can be given inline, but is better kept out of the
+/// configuration file by reading it from a file or environment variable
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Secret {
+    Inline(String),
+    File { file: String },
+    Env { env: String },
+}
+
+impl Secret {
+    pub fn resolve(&self) -> Result<String, Error> {
+        match *self {
+            Secret::Inline(ref value) => Ok(value.clone()),
+            Secret::File { ref file } => Ok(fs::read_to_string(file)?.trim().to_owned()),
+            Secret::Env { ref env } => {
+                env::var(env).map_err(|_| Error::MissingEnvironmentVariable(env.clone()))
+            }
+        }
+    }
+}
+
+// Written by hand to keep inline secrets out of logs
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Secret::Inline(_) => write!(f, "Inline(..)"),
+            Secret::File { ref file } => write!(f, "File {{ file: {:?} }}", file),
+            Secret::Env { ref env } => write!(f, "Env {{ env: {:?} }}", env),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SharebillAuthConfig {
+    Bearer {
+        token: Secret,
+    },
+    Basic {
+        user: String,
+        password: Option<Secret>,
+    },
+    Cookies {
+        cookies: BTreeMap<String, Secret>,
+    },
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct DbConfig {
     #[serde(default = "default_connection_string")]
@@ -47,8 +101,11 @@ pub struct WebConfig {
 
     pub sharebill_url: Option<String>,
 
+    /// Raw cookie strings. Superseded by sharebill_auth
     #[serde(default = "default_cookies")]
     pub sharebill_cookies: Vec<String>,
+
+    pub sharebill_auth: Option<SharebillAuthConfig>,
 }
 fn default_bind() -> String {
     "localhost:3000".to_owned()
@@ -68,8 +125,33 @@ impl WebConfig {
             slack_token: None,
             sharebill_url: None,
             sharebill_cookies: vec![],
+            sharebill_auth: None,
         }
     }
+
+    pub fn sharebill_auth(&self) -> Result<Auth, Error> {
+        Ok(match self.sharebill_auth {
+            Some(SharebillAuthConfig::Bearer { ref token }) => Auth::Bearer(token.resolve()?),
+            Some(SharebillAuthConfig::Basic {
+                ref user,
+                ref password,
+            }) => Auth::Basic {
+                user: user.clone(),
+                password: match *password {
+                    Some(ref password) => Some(password.resolve()?),
+                    None => None,
+                },
+            },
+            Some(SharebillAuthConfig::Cookies { ref cookies }) => Auth::Cookies(
+                cookies
+                    .iter()
+                    .map(|(name, value)| Ok(format!("{}={}", name, value.resolve()?)))
+                    .collect::<Result<_, Error>>()?,
+            ),
+            None if self.sharebill_cookies.is_empty() => Auth::None,
+            None => Auth::Cookies(self.sharebill_cookies.clone()),
+        })
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -87,6 +169,60 @@ pub struct MatrixConfig {
     pub reminder: Option<MatrixReminderConfig>,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum SettlementConfig {
+    Sharebill,
+    Ledger,
+    SplitwiseCsv {
+        directory: String,
+
+        /// Defaults to the home currency
+        currency: Option<String>,
+    },
+    Journal {
+        path: String,
+
+        #[serde(default = "default_account_prefix")]
+        account_prefix: String,
+    },
+}
+
+/// Orders are emailed to restaurants through an SMTP server, which can be
+/// a local MTA or, with STARTTLS and a login, a mail provider
+#[derive(Deserialize, Debug, Clone)]
+pub struct EmailConfig {
+    #[serde(default = "default_smtp_server")]
+    pub smtp_server: String,
+
+    #[serde(default)]
+    pub smtp_starttls: bool,
+
+    /// Requires `smtp_starttls`, so the password is not sent in the clear
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<Secret>,
+
+    pub from: String,
+
+    /// Who the restaurant should get in touch with about the order
+    pub contact_name: String,
+
+    pub contact_phone: Option<String>,
+
+    pub delivery_address: String,
+}
+
+fn default_smtp_server() -> String {
+    "localhost:25".to_owned()
+}
+
+fn default_currency() -> String {
+    "NOK".to_owned()
+}
+fn default_account_prefix() -> String {
+    "fishsticks:".to_owned()
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     #[serde(default = "DbConfig::new")]
@@ -96,6 +232,24 @@ pub struct Config {
     pub web: WebConfig,
 
     pub matrix: Option<MatrixConfig>,
+
+    pub settlement: Option<SettlementConfig>,
+
+    pub email: Option<EmailConfig>,
+
+    /// The home currency, which the ledger and Sharebill are kept in
+    #[serde(default = "default_currency")]
+    pub currency: String,
+
+    /// Exchange rates from other currencies into the home currency, as
+    /// the number of home currency units per unit of the other currency
+    #[serde(default)]
+    pub exchange_rates: BTreeMap<String, Rational>,
+
+    /// Refuse orders that go over the per-person budget, instead of
+    /// just warning about them
+    #[serde(default)]
+    pub enforce_budget: bool,
 }
 
 impl Config {
@@ -111,8 +265,26 @@ impl Config {
                 slack_token: None,
                 sharebill_url: None,
                 sharebill_cookies: vec![],
+                sharebill_auth: None,
             },
             matrix: None,
+            settlement: None,
+            email: None,
+            currency: default_currency(),
+            exchange_rates: BTreeMap::new(),
+            enforce_budget: false,
+        }
+    }
+
+    pub fn currency(&self) -> Result<Currency, Error> {
+        Currency::from_code(&self.currency)
+            .ok_or_else(|| Error::InvalidCurrency(self.currency.clone()))
+    }
+
+    pub fn smtp_password(&self) -> Result<Option<String>, Error> {
+        match self.email.as_ref().and_then(|x| x.smtp_password.as_ref()) {
+            Some(password) => Ok(Some(password.resolve()?)),
+            None => Ok(None),
         }
     }
 }
@@ -191,7 +363,13 @@ pub fn read_config() -> ConfigResult {
             slack_token: cfg.web.slack_token,
             sharebill_url: cfg.web.sharebill_url,
             sharebill_cookies: cfg.web.sharebill_cookies,
+            sharebill_auth: cfg.web.sharebill_auth,
         },
         matrix: cfg.matrix,
+        settlement: cfg.settlement,
+        email: cfg.email,
+        currency: cfg.currency,
+        exchange_rates: cfg.exchange_rates,
+        enforce_budget: cfg.enforce_budget,
     })
 }
@@ -0,0 +1,107 @@
+use sharebill::rational::Rounding;
+use sharebill::Rational;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    Before,
+    After,
+}
+
+/// Symbol, number of decimals and symbol placement for the currencies
+/// we are likely to run into. Others are formatted with their code
+const KNOWN_CURRENCIES: &[(&str, &str, usize, Position)] = &[
+    ("NOK", "kr", 2, Position::After),
+    ("SEK", "kr", 2, Position::After),
+    ("DKK", "kr", 2, Position::After),
+    ("ISK", "kr", 0, Position::After),
+    ("EUR", "€", 2, Position::Before),
+    ("USD", "$", 2, Position::Before),
+    ("GBP", "£", 2, Position::Before),
+    ("CHF", "CHF", 2, Position::Before),
+    ("PLN", "zł", 2, Position::After),
+    ("CZK", "Kč", 2, Position::After),
+    ("JPY", "¥", 0, Position::Before),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Currency {
+    pub code: String,
+    symbol: String,
+    decimals: usize,
+    position: Position,
+}
+
+impl Currency {
+    /// Returns `None` unless `code` looks like an ISO 4217 code, that is
+    /// three letters
+    pub fn from_code(code: &str) -> Option<Currency> {
+        if code.len() != 3 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        let code = code.to_uppercase();
+
+        let known = KNOWN_CURRENCIES.iter().find(|x| x.0 == code);
+        Some(match known {
+            Some(&(_, symbol, decimals, position)) => Currency {
+                code,
+                symbol: symbol.to_owned(),
+                decimals,
+                position,
+            },
+            None => Currency {
+                symbol: code.clone(),
+                code,
+                decimals: 2,
+                position: Position::After,
+            },
+        })
+    }
+
+    pub fn format(&self, amount: &Rational) -> String {
+        let number = amount.to_decimal_string(self.decimals, Rounding::HalfUp);
+        let (sign, number) = if number.starts_with('-') {
+            ("-", &number[1..])
+        } else {
+            ("", &number[..])
+        };
+
+        // Letters need some space to not run into the digits
+        let space = if self.symbol.chars().all(char::is_alphabetic) {
+            " "
+        } else {
+            ""
+        };
+
+        match self.position {
+            Position::Before => format!("{}{}{}{}", sign, self.symbol, space, number),
+            Position::After => format!("{}{}{}{}", sign, number, space, self.symbol),
+        }
+    }
+
+    pub fn format_cents(&self, cents: i32) -> String {
+        self.format(&Rational::from_cents(cents))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats_known_currencies() {
+        let amount = Rational::from_cents(-1250);
+        let format = |code| Currency::from_code(code).unwrap().format(&amount);
+
+        assert_eq!("-12.50 kr", format("nok"));
+        assert_eq!("-€12.50", format("EUR"));
+        assert_eq!("-CHF 12.50", format("CHF"));
+        assert_eq!("-¥13", format("JPY"));
+        assert_eq!("-12.50 XYZ", format("XYZ"));
+    }
+
+    #[test]
+    fn rejects_invalid_codes() {
+        assert_eq!(None, Currency::from_code("kroner"));
+        assert_eq!(None, Currency::from_code("€"));
+    }
+}
@@ -26,6 +26,7 @@ struct NewMenuItem<'a> {
     number: i32,
     name: &'a str,
     price_in_cents: i32,
+    category: &'a str,
 }
 
 pub fn menu(
@@ -51,12 +52,13 @@ pub fn menu(
 
     let menu_items_to_insert =
         menu.iter()
-            .flat_map(|ref category| &category.entries)
-            .map(|ref item| NewMenuItem {
+            .flat_map(|category| category.entries.iter().map(move |item| (category, item)))
+            .map(|(category, item)| NewMenuItem {
                 menu: i32::from(menu_id),
                 number: item.number,
                 name: &item.name,
                 price_in_cents: (item.price * 100.0) as i32,
+                category: &category.category,
             });
 
     /* Bah, Diesel does not support batch inserts for sqlite,
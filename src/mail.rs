@@ -0,0 +1,558 @@
+//! Just enough SMTP to hand a plain text message over to a mail server,
+//! optionally upgrading the connection with STARTTLS and logging in with
+//! AUTH PLAIN.
+
+use native_tls::{self, HandshakeError, TlsConnector};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use time;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum Error {
+        Io(err: io::Error) { from() }
+        Tls(err: native_tls::Error) { from() }
+        Rejected(reply: String) { }
+        InvalidAddress(address: String) { }
+        AuthWithoutTls
+    }
+}
+
+/// How long to wait for the server to connect or answer
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where and how to hand over mail
+pub struct Server<'a> {
+    /// As in `localhost:25`
+    pub address: &'a str,
+
+    /// Whether to upgrade the connection with STARTTLS before sending
+    /// anything else
+    pub starttls: bool,
+
+    /// The username and password for AUTH PLAIN, which is only sent over
+    /// TLS
+    pub credentials: Option<(&'a str, &'a str)>,
+}
+
+pub struct Message<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub subject: &'a str,
+    pub body: &'a str,
+}
+
+/// Whether the address is safe to put in SMTP commands and headers, which
+/// rules out whitespace, control characters and angle brackets. There must
+/// be exactly one `@`, with something on either side of it
+pub fn is_valid_address(address: &str) -> bool {
+    let mut parts = address.split('@');
+    let valid_part = |part: Option<&str>| match part {
+        Some(part) => part.len() > 0,
+        None => false,
+    };
+
+    valid_part(parts.next())
+        && valid_part(parts.next())
+        && parts.next().is_none()
+        && !address
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == '<' || c == '>')
+}
+
+/// Encodes as base64, as needed for AUTH PLAIN
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let byte = |i: usize| u32::from(chunk.get(i).cloned().unwrap_or(0));
+        let bits = byte(0) << 16 | byte(1) << 8 | byte(2);
+
+        // A chunk of n bytes fills n + 1 characters, and the rest is padding
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Encodes non-ASCII header text as an RFC 2047 encoded word
+fn encode_header(text: &str) -> String {
+    if text.chars().all(|c| c.is_ascii() && !c.is_ascii_control()) {
+        return text.to_owned();
+    }
+
+    let mut encoded = "=?utf-8?Q?".to_owned();
+    for byte in text.bytes() {
+        match byte {
+            b' ' => encoded.push('_'),
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("={:02X}", byte)),
+        }
+    }
+    encoded.push_str("?=");
+    encoded
+}
+
+struct Connection<S: Read + Write> {
+    stream: BufReader<S>,
+}
+
+impl<S: Read + Write> Connection<S> {
+    fn new(stream: S) -> Connection<S> {
+        Connection {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    /// Reads a possibly multi-line reply and fails unless its code is
+    /// the expected one
+    fn expect(&mut self, code: &str) -> Result<(), Error> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line)? == 0 {
+                return Err(Error::Rejected(reply));
+            }
+            reply.push_str(&line);
+
+            // The last line of a reply has a space after the code
+            if line.len() < 4 || &line[3..4] != "-" {
+                break;
+            }
+        }
+
+        if reply.starts_with(code) {
+            Ok(())
+        } else {
+            Err(Error::Rejected(reply.trim_end().to_owned()))
+        }
+    }
+
+    fn command(&mut self, command: &str, code: &str) -> Result<(), Error> {
+        write!(self.stream.get_mut(), "{}\r\n", command)?;
+        self.expect(code)
+    }
+}
+
+/// Connects to the first of the addresses the server name resolves to that
+/// answers in time
+fn connect(address: &str) -> Result<TcpStream, Error> {
+    let mut last_err = io::Error::new(io::ErrorKind::NotFound, "No address for the SMTP server");
+
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, TIMEOUT) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(err) => last_err = err,
+        }
+    }
+
+    Err(Error::Io(last_err))
+}
+
+pub fn send(server: &Server, message: &Message) -> Result<(), Error> {
+    for address in &[message.from, message.to] {
+        if !is_valid_address(address) {
+            return Err(Error::InvalidAddress(address.to_string()));
+        }
+    }
+    if server.credentials.is_some() && !server.starttls {
+        return Err(Error::AuthWithoutTls);
+    }
+
+    let mut connection = Connection::new(connect(server.address)?);
+    connection.expect("220")?;
+    connection.command("EHLO fishsticks", "250")?;
+
+    if !server.starttls {
+        return deliver(connection, message);
+    }
+
+    connection.command("STARTTLS", "220")?;
+
+    // The certificate is checked against the host part of the address
+    let host = server.address.rsplitn(2, ':').last().unwrap();
+    let stream = TlsConnector::new()?
+        .connect(host, connection.stream.into_inner())
+        .map_err(|err| match err {
+            HandshakeError::Failure(err) => Error::Tls(err),
+            HandshakeError::WouldBlock(_) => Error::Io(io::ErrorKind::WouldBlock.into()),
+        })?;
+
+    let mut connection = Connection::new(stream);
+    connection.command("EHLO fishsticks", "250")?;
+
+    if let Some((username, password)) = server.credentials {
+        let token = base64(format!("\0{}\0{}", username, password).as_bytes());
+        connection.command(&format!("AUTH PLAIN {}", token), "235")?;
+    }
+
+    deliver(connection, message)
+}
+
+fn deliver<S: Read + Write>(
+    mut connection: Connection<S>,
+    message: &Message,
+) -> Result<(), Error> {
+    connection.command(&format!("MAIL FROM:<{}>", message.from), "250")?;
+    connection.command(&format!("RCPT TO:<{}>", message.to), "250")?;
+    connection.command("DATA", "354")?;
+
+    let mut data = String::new();
+    data.push_str(&format!("From: {}\r\n", message.from));
+    data.push_str(&format!("To: {}\r\n", message.to));
+    data.push_str(&format!("Subject: {}\r\n", encode_header(message.subject)));
+    data.push_str(&format!("Date: {}\r\n", time::now().rfc822z()));
+    data.push_str("MIME-Version: 1.0\r\n");
+    data.push_str("Content-Type: text/plain; charset=utf-8\r\n");
+    data.push_str("Content-Transfer-Encoding: 8bit\r\n");
+    data.push_str("\r\n");
+    for line in message.body.lines() {
+        // Lines starting with a dot get another one, so they are not
+        // mistaken for the end of the message
+        if line.starts_with('.') {
+            data.push('.');
+        }
+        data.push_str(line);
+        data.push_str("\r\n");
+    }
+    data.push_str(".");
+
+    connection.command(&data, "250")?;
+    connection.command("QUIT", "221")?;
+
+    Ok(())
+}
+
+/// An in-process SMTP server that accepts everything, so code sending
+/// mail can be tested without a mail server
+#[cfg(test)]
+pub mod sink {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    #[derive(Debug, Clone)]
+    pub struct Received {
+        pub from: String,
+        pub to: Vec<String>,
+        pub data: String,
+    }
+
+    pub struct SmtpSink {
+        address: String,
+        received: Arc<Mutex<Vec<Received>>>,
+    }
+
+    impl SmtpSink {
+        pub fn start() -> SmtpSink {
+            let listener = TcpListener::bind("127.0.0.1:0").expect("Unable to bind SMTP sink");
+            let address = listener.local_addr().unwrap().to_string();
+
+            let received = Arc::new(Mutex::new(vec![]));
+            let server_received = received.clone();
+
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if let Ok(stream) = stream {
+                        let _ignore_failure = handle(stream, &server_received);
+                    }
+                }
+            });
+
+            SmtpSink { address, received }
+        }
+
+        pub fn address(&self) -> &str {
+            &self.address
+        }
+
+        pub fn received(&self) -> Vec<Received> {
+            self.received.lock().unwrap().clone()
+        }
+    }
+
+    fn handle(stream: TcpStream, received: &Mutex<Vec<Received>>) -> ::std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        let mut message = Received {
+            from: String::new(),
+            to: vec![],
+            data: String::new(),
+        };
+
+        write!(writer, "220 sink\r\n")?;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let line = line.trim_end();
+
+            if line.starts_with("MAIL FROM:") {
+                message.from = line["MAIL FROM:".len()..].trim_matches(&['<', '>'][..]).to_owned();
+            } else if line.starts_with("RCPT TO:") {
+                message.to.push(line["RCPT TO:".len()..].trim_matches(&['<', '>'][..]).to_owned());
+            } else if line == "DATA" {
+                write!(writer, "354 go ahead\r\n")?;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line)? == 0 {
+                        return Ok(());
+                    }
+                    if line == ".\r\n" {
+                        break;
+                    }
+                    message.data.push_str(&line);
+                }
+                received.lock().unwrap().push(message.clone());
+            } else if line == "QUIT" {
+                write!(writer, "221 bye\r\n")?;
+                return Ok(());
+            }
+
+            write!(writer, "250 ok\r\n")?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Stands in for a connection to a server that gives these replies,
+    /// keeping what is written to it
+    struct Script {
+        replies: io::Cursor<Vec<u8>>,
+        sent: Vec<u8>,
+    }
+
+    impl Script {
+        fn new(replies: &str) -> Script {
+            Script {
+                replies: io::Cursor::new(replies.as_bytes().to_vec()),
+                sent: vec![],
+            }
+        }
+    }
+
+    impl Read for Script {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for Script {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.sent.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Starts a server that greets with the first reply and answers each
+    /// line it gets with the next one. Once out of replies it reads once
+    /// more and hangs up, giving back everything it got
+    fn scripted_server(replies: Vec<&'static str>) -> (String, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut received = vec![];
+
+            let mut replies = replies.into_iter();
+            write!(writer, "{}\r\n", replies.next().unwrap()).unwrap();
+            for reply in replies {
+                reader.read_until(b'\n', &mut received).unwrap();
+                write!(writer, "{}\r\n", reply).unwrap();
+            }
+
+            let mut buf = [0; 512];
+            let count = reader.read(&mut buf).unwrap_or(0);
+            received.extend_from_slice(&buf[..count]);
+            received
+        });
+
+        (address, handle)
+    }
+
+    fn order_message() -> Message<'static> {
+        Message {
+            from: "fishsticks@example.com",
+            to: "orders@example.com",
+            subject: "Order",
+            body: "1 x 513. Salmon",
+        }
+    }
+
+    #[test]
+    fn reads_multi_line_replies() {
+        let mut connection = Connection::new(Script::new(
+            "250-sink\r\n250-STARTTLS\r\n250 AUTH PLAIN\r\n354 go ahead\r\n",
+        ));
+
+        connection.command("EHLO fishsticks", "250").unwrap();
+        connection.command("DATA", "354").unwrap();
+        assert_eq!(
+            "EHLO fishsticks\r\nDATA\r\n",
+            String::from_utf8_lossy(&connection.stream.get_ref().sent)
+        );
+    }
+
+    #[test]
+    fn reports_whole_rejections() {
+        let mut connection =
+            Connection::new(Script::new("550-No such user\r\n550 Try another\r\n"));
+
+        match connection.command("RCPT TO:<nobody@example.com>", "250") {
+            Err(Error::Rejected(reply)) => assert_eq!("550-No such user\r\n550 Try another", reply),
+            _ => panic!("Expected the recipient to be rejected"),
+        }
+    }
+
+    #[test]
+    fn fails_on_unfinished_replies() {
+        let mut connection = Connection::new(Script::new("250-sink\r\n"));
+
+        match connection.command("EHLO fishsticks", "250") {
+            Err(Error::Rejected(_)) => (),
+            _ => panic!("Expected the cut off reply to fail"),
+        }
+    }
+
+    #[test]
+    fn starts_tls_before_anything_else() {
+        let (address, server) =
+            scripted_server(vec!["220 hi", "250-hi\r\n250 STARTTLS", "220 go ahead"]);
+
+        let result = send(
+            &Server {
+                address: &address,
+                starttls: true,
+                credentials: Some(("user", "pass")),
+            },
+            &order_message(),
+        );
+        assert!(result.is_err());
+
+        // Right after STARTTLS comes a TLS handshake record
+        let received = server.join().unwrap();
+        let plain = b"EHLO fishsticks\r\nSTARTTLS\r\n";
+        assert_eq!(&plain[..], &received[..plain.len()]);
+        assert_eq!(Some(&0x16), received.get(plain.len()));
+    }
+
+    #[test]
+    fn stops_when_starttls_is_refused() {
+        let (address, server) = scripted_server(vec!["220 hi", "250 hi", "454 TLS not available"]);
+
+        match send(
+            &Server {
+                address: &address,
+                starttls: true,
+                credentials: Some(("user", "pass")),
+            },
+            &order_message(),
+        ) {
+            Err(Error::Rejected(reply)) => assert!(reply.starts_with("454")),
+            _ => panic!("Expected STARTTLS to be refused"),
+        }
+
+        let received = server.join().unwrap();
+        assert_eq!(
+            "EHLO fishsticks\r\nSTARTTLS\r\n",
+            String::from_utf8_lossy(&received)
+        );
+    }
+
+    #[test]
+    fn encodes_non_ascii_headers() {
+        assert_eq!("Order for Sushi Bar", encode_header("Order for Sushi Bar"));
+        assert_eq!("=?utf-8?Q?Order_for_K=C3=B8ket?=", encode_header("Order for Køket"));
+    }
+
+    #[test]
+    fn validates_addresses() {
+        assert!(is_valid_address("orders@example.com"));
+        assert!(!is_valid_address("orders@example.com\r\nRCPT TO:<evil@example.com>"));
+        assert!(!is_valid_address("orders @example.com"));
+        assert!(!is_valid_address("orders@@example.com"));
+        assert!(!is_valid_address("orders@example@com"));
+        assert!(!is_valid_address("@example.com"));
+        assert!(!is_valid_address("orders"));
+    }
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!("AHVzZXIAcGFzcw==", base64(b"\0user\0pass"));
+        assert_eq!("YWI=", base64(b"ab"));
+        assert_eq!("YWJj", base64(b"abc"));
+    }
+
+    #[test]
+    fn refuses_auth_without_tls() {
+        let server = Server {
+            address: "localhost:25",
+            starttls: false,
+            credentials: Some(("user", "pass")),
+        };
+        let message = Message {
+            from: "fishsticks@example.com",
+            to: "orders@example.com",
+            subject: "Order",
+            body: "",
+        };
+
+        match send(&server, &message) {
+            Err(Error::AuthWithoutTls) => (),
+            _ => panic!("Expected the password to stay off the plain connection"),
+        }
+    }
+
+    #[test]
+    fn sends_dot_stuffed_message() {
+        let sink = sink::SmtpSink::start();
+
+        send(
+            &Server {
+                address: sink.address(),
+                starttls: false,
+                credentials: None,
+            },
+            &Message {
+                from: "fishsticks@example.com",
+                to: "orders@example.com",
+                subject: "Order",
+                body: "1 x 513. Salmon\n.hidden\n.\nEnd",
+            },
+        )
+        .unwrap();
+
+        let received = sink.received();
+        assert_eq!(1, received.len());
+        assert_eq!("fishsticks@example.com", &received[0].from);
+        assert_eq!(vec!["orders@example.com"], received[0].to);
+        assert!(received[0]
+            .data
+            .ends_with("\r\n1 x 513. Salmon\r\n..hidden\r\n..\r\nEnd\r\n"));
+    }
+}
@@ -14,8 +14,8 @@ extern crate crossbeam;
 extern crate iron;
 extern crate itertools;
 extern crate matrix_bot_api;
+extern crate native_tls;
 extern crate num;
-extern crate reqwest;
 extern crate serde;
 extern crate serde_json;
 extern crate sharebill;
@@ -25,11 +25,16 @@ extern crate uuid;
 
 mod cmd;
 mod config;
+mod currency;
 mod db;
 mod ingest;
+mod mail;
 mod matrix;
 mod models;
+mod ordersheet;
 mod schema;
+mod search;
+mod settlement;
 mod slack;
 mod state;
 mod takedown;
@@ -51,6 +56,30 @@ fn main() {
         }
     };
 
+    let sharebill_auth = match config.web.sharebill_auth() {
+        Ok(sharebill_auth) => sharebill_auth,
+        Err(err) => {
+            println!("{:?}", &err);
+            panic!(err)
+        }
+    };
+
+    let smtp_password = match config.smtp_password() {
+        Ok(smtp_password) => smtp_password,
+        Err(err) => {
+            println!("{:?}", &err);
+            panic!(err)
+        }
+    };
+
+    let currency = match config.currency() {
+        Ok(currency) => currency,
+        Err(err) => {
+            println!("{:?}", &err);
+            panic!(err)
+        }
+    };
+
     let db_connection = db::connect_database(
         &config.database.connection_string,
         config.database.run_migrations,
@@ -61,6 +90,9 @@ fn main() {
         let web = {
             let state = state.clone();
             let config = config.clone();
+            let sharebill_auth = sharebill_auth.clone();
+            let currency = currency.clone();
+            let smtp_password = smtp_password.clone();
             scope.spawn(|| {
                 web::run(
                     state,
@@ -68,7 +100,13 @@ fn main() {
                     config.web.base,
                     config.web.slack_token,
                     config.web.sharebill_url,
-                    config.web.sharebill_cookies,
+                    sharebill_auth,
+                    config.settlement,
+                    currency,
+                    config.exchange_rates,
+                    config.enforce_budget,
+                    config.email,
+                    smtp_password,
                 )
             })
         };
@@ -76,7 +114,13 @@ fn main() {
         let env = web::Env {
             base_url: config.web.base,
             maybe_sharebill_url: config.web.sharebill_url,
-            sharebill_cookies: config.web.sharebill_cookies,
+            sharebill_auth: sharebill_auth,
+            settlement: config.settlement,
+            currency: currency,
+            exchange_rates: config.exchange_rates,
+            enforce_budget: config.enforce_budget,
+            email: config.email,
+            smtp_password: smtp_password,
         };
 
         let matrix = config.matrix.map(|matrix| {
@@ -6,7 +6,9 @@ use matrix_bot_api::{MatrixBot, MessageType, BKResponse};
 
 use cmd;
 use config;
-use slack::{ResponseType, SlackResponse};
+use models::Frontend;
+use settlement::Receipt;
+use slack::{describe_limits, ResponseType, SlackResponse};
 use state;
 use web;
 
@@ -36,17 +38,49 @@ impl From<cmd::Response> for MatrixResponse {
             OpenedOrder {
                 menu_url,
                 restaurant_name,
-            } => MatrixResponse {
-                text: format!(
+                currency,
+                minimum_order_in_cents,
+                budget_per_person_in_cents,
+                phone_numbers,
+            } => {
+                let mut text = format!(
                     "🔔 Now taking orders from the {} menu ({}) 📝",
                     restaurant_name, menu_url
-                ),
-                msg_type: MessageType::TextMessage,
-            },
-            Sharebill { url } => MatrixResponse {
-                text: format!("💸 Posted to Sharebill and closed order ✔️ {}", url),
+                );
+                let limits =
+                    describe_limits(&currency, minimum_order_in_cents, budget_per_person_in_cents);
+                if !limits.is_empty() {
+                    text.push_str(&format!(" ({})", limits));
+                }
+                if !phone_numbers.is_empty() {
+                    text.push_str(&format!("\n☎️ {}", phone_numbers.join(", ")));
+                }
+
+                MatrixResponse {
+                    text,
+                    msg_type: MessageType::TextMessage,
+                }
+            }
+            Settled {
+                receipt: Receipt::Posted { url },
+                backend,
+            } => MatrixResponse {
+                text: format!("💸 Posted to {} and closed order ✔️ {}", backend, url),
                 msg_type: MessageType::TextMessage,
             },
+            PollClosed {
+                tally,
+                winner,
+                opened: Some(opened),
+            } => {
+                let mut response = MatrixResponse::from(SlackResponse::from(PollClosed {
+                    tally,
+                    winner,
+                    opened: None,
+                }));
+                response.text.push_str(&format!("\n{}", MatrixResponse::from(*opened).text));
+                response
+            }
             x => SlackResponse::from(x).into(),
         }
     }
@@ -96,6 +130,8 @@ pub fn run(
                     args: args,
                     user_name: &message.sender,
                     env: &env,
+                    frontend: Frontend::Matrix,
+                    real_user_name: &message.sender,
                 },
             )
             .map(MatrixResponse::from)
@@ -1,6 +1,7 @@
 use diesel;
 use diesel::types::*;
 use schema::{menu_items, order_items};
+use serde_json;
 use std;
 
 macro_rules! generate_id_type {
@@ -50,11 +51,62 @@ generate_id_type!(MenuId);
 generate_id_type!(MenuItemId);
 generate_id_type!(OrderId);
 generate_id_type!(OrderItemId);
+generate_id_type!(LedgerTransactionId);
+generate_id_type!(LedgerEntryId);
+generate_id_type!(OrderAdjustmentId);
+generate_id_type!(JournalEntryId);
+generate_id_type!(AuditEntryId);
+generate_id_type!(FavoriteId);
+generate_id_type!(PollId);
 
 #[derive(Debug, Queryable, Serialize)]
 pub struct Restaurant {
     pub id: RestaurantId,
     pub name: String,
+    pub currency: Option<String>,
+    pub minimum_order_in_cents: Option<i32>,
+    pub budget_per_person_in_cents: Option<i32>,
+    pub archived: bool,
+    pub website: Option<String>,
+    pub address: Option<String>,
+    pub cuisine: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct RestaurantAlias {
+    pub alias: String,
+    pub restaurant: RestaurantId,
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct ItemAlias {
+    pub restaurant: RestaurantId,
+    pub alias: String,
+    pub number: i32,
+}
+
+/// One item of a favorite
+#[derive(Debug, Queryable, Serialize)]
+pub struct Favorite {
+    pub id: FavoriteId,
+    pub person_name: String,
+    pub restaurant: RestaurantId,
+    pub name: String,
+    pub number: i32,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct PhoneNumber {
+    pub restaurant: RestaurantId,
+    pub number: String,
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct EmailAddress {
+    pub restaurant: RestaurantId,
+    pub email_address: String,
 }
 
 #[derive(Debug, Queryable, Serialize)]
@@ -64,7 +116,7 @@ pub struct Menu {
     pub imported: i32,
 }
 
-#[derive(Debug, Queryable, Serialize, Identifiable, Associations)]
+#[derive(Debug, Clone, Queryable, Serialize, Identifiable, Associations)]
 #[has_many(order_items, foreign_key = "menu_item")]
 pub struct MenuItem {
     pub id: MenuItemId,
@@ -72,6 +124,7 @@ pub struct MenuItem {
     pub number: i32,
     pub name: String,
     pub price_in_cents: i32,
+    pub category: Option<String>,
 }
 
 #[derive(Debug, Queryable, Serialize)]
@@ -81,15 +134,23 @@ pub struct Order {
     pub overhead_in_cents: i32,
     pub opened: i32,
     pub closed: Option<i32>,
+    pub sharebill_post_id: Option<String>,
+    pub sharebill_payload: Option<String>,
+    pub billed_post_url: Option<String>,
+    pub exchange_rate: Option<String>,
+    pub minimum_order_in_cents: Option<i32>,
+    pub budget_per_person_in_cents: Option<i32>,
+    pub sent: Option<i32>,
 }
 
-#[derive(Debug, Queryable, Serialize, Identifiable, Associations)]
+#[derive(Debug, Clone, Queryable, Serialize, Identifiable, Associations)]
 #[belongs_to(MenuItem)]
 pub struct OrderItem {
     pub id: OrderItemId,
     pub order: OrderId,
     pub person_name: String,
     pub menu_item: MenuItemId,
+    pub note: Option<String>,
 }
 
 #[derive(Debug, Queryable, Serialize)]
@@ -97,3 +158,165 @@ pub struct SharebillAssociation {
     pub slack_name: String,
     pub sharebill_account: String,
 }
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct LedgerTransaction {
+    pub id: LedgerTransactionId,
+    pub order: Option<OrderId>,
+    pub description: String,
+    pub created: i32,
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct LedgerEntry {
+    pub id: LedgerEntryId,
+    pub transaction: LedgerTransactionId,
+    pub account: String,
+    pub amount: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdjustmentKind {
+    Fixed,
+    Percentage,
+    PerPerson,
+}
+
+impl AdjustmentKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AdjustmentKind::Fixed => "fixed",
+            AdjustmentKind::Percentage => "percentage",
+            AdjustmentKind::PerPerson => "per_person",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<AdjustmentKind> {
+        match kind {
+            "fixed" => Some(AdjustmentKind::Fixed),
+            "percentage" => Some(AdjustmentKind::Percentage),
+            "per_person" => Some(AdjustmentKind::PerPerson),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct OrderAdjustment {
+    pub id: OrderAdjustmentId,
+    pub order: OrderId,
+    pub kind: String,
+    pub amount: String,
+    pub description: String,
+}
+
+impl OrderAdjustment {
+    pub fn adjustment_kind(&self) -> AdjustmentKind {
+        AdjustmentKind::parse(&self.kind).expect("Database invariant")
+    }
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct PendingChoice {
+    pub person_name: String,
+    pub order: OrderId,
+    pub numbers: String,
+    pub note: Option<String>,
+    pub created: i32,
+    pub quantity: i32,
+}
+
+impl PendingChoice {
+    pub fn numbers(&self) -> Vec<i32> {
+        self.numbers
+            .split(',')
+            .map(|x| x.parse().expect("Database invariant"))
+            .collect()
+    }
+}
+
+/// How to undo a change someone made to an order
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Undo {
+    /// Take off one of each of these menu items, with their notes, which
+    /// were just ordered. Row ids are not kept, as putting cleared items
+    /// back gives them new ones
+    RemoveOrderItems(Vec<(i32, Option<String>)>),
+
+    /// Put back these menu items, with their notes, which were cleared
+    RestoreOrderItems(Vec<(i32, Option<String>)>),
+
+    /// Set the overhead back to this
+    SetOverhead(i32),
+
+    /// Open the order again after it was closed
+    Reopen,
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct JournalEntry {
+    pub id: JournalEntryId,
+    pub person_name: String,
+    pub order: OrderId,
+    pub created: i32,
+    pub undo: String,
+}
+
+impl JournalEntry {
+    pub fn undo(&self) -> Undo {
+        serde_json::from_str(&self.undo).expect("Database invariant")
+    }
+}
+
+/// Where a command came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frontend {
+    Slack,
+    Matrix,
+    Web,
+}
+
+impl Frontend {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Frontend::Slack => "slack",
+            Frontend::Matrix => "matrix",
+            Frontend::Web => "web",
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct AuditEntry {
+    pub id: AuditEntryId,
+    pub created: i32,
+    pub frontend: String,
+    pub real_user: String,
+    pub effective_user: String,
+    pub order: Option<OrderId>,
+    pub command: String,
+    pub args: String,
+    pub outcome: String,
+}
+
+/// A vote on where to order from
+#[derive(Debug, Queryable, Serialize)]
+pub struct Poll {
+    pub id: PollId,
+    pub started_by: String,
+    pub opened: i32,
+    pub closed: Option<i32>,
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct PollOption {
+    pub poll: PollId,
+    pub restaurant: RestaurantId,
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct PollVote {
+    pub poll: PollId,
+    pub person_name: String,
+    pub restaurant: RestaurantId,
+}
@@ -0,0 +1,131 @@
+//! The order the way the restaurant sees it: how many of each item to
+//! make, and which items go in whose bag
+
+use models::{MenuItem, OrderItem};
+use sharebill::Rational;
+
+pub struct Line {
+    pub quantity: usize,
+    pub menu_item: MenuItem,
+    pub notes: Vec<String>,
+}
+
+impl Line {
+    pub fn total(&self) -> Rational {
+        Rational::from_cents(self.menu_item.price_in_cents * self.quantity as i32)
+    }
+}
+
+pub fn total(lines: &[Line]) -> Rational {
+    lines.iter().map(Line::total).sum()
+}
+
+/// Counts how many there are of each menu item, in menu order
+pub fn by_menu_item(items: Vec<(MenuItem, OrderItem)>) -> Vec<Line> {
+    let mut lines: Vec<Line> = vec![];
+
+    for (menu_item, order_item) in items {
+        let index = match lines.iter().position(|x| x.menu_item.id == menu_item.id) {
+            Some(index) => index,
+            None => {
+                lines.push(Line {
+                    quantity: 0,
+                    menu_item,
+                    notes: vec![],
+                });
+                lines.len() - 1
+            }
+        };
+
+        lines[index].quantity += 1;
+        if let Some(note) = order_item.note {
+            lines[index].notes.push(note);
+        }
+    }
+
+    lines.sort_by_key(|x| x.menu_item.number);
+    lines
+}
+
+/// Groups the items by person, for packing. Expects the items sorted by
+/// person, as from `State::items_in_order`
+pub fn by_person(items: Vec<(MenuItem, OrderItem)>) -> Vec<(String, Vec<Line>)> {
+    let mut persons: Vec<(String, Vec<(MenuItem, OrderItem)>)> = vec![];
+
+    for (menu_item, order_item) in items {
+        let new_person = match persons.last() {
+            Some(&(ref person_name, _)) => person_name != &order_item.person_name,
+            None => true,
+        };
+        if new_person {
+            persons.push((order_item.person_name.clone(), vec![]));
+        }
+        persons.last_mut().unwrap().1.push((menu_item, order_item));
+    }
+
+    persons
+        .into_iter()
+        .map(|(person_name, items)| (person_name, by_menu_item(items)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn item(id: i32, number: i32, person_name: &str, note: Option<&str>) -> (MenuItem, OrderItem) {
+        (
+            MenuItem {
+                id: id.into(),
+                menu: 1.into(),
+                number,
+                name: format!("Item {}", number),
+                price_in_cents: 1000,
+                category: None,
+            },
+            OrderItem {
+                id: 1.into(),
+                order: 1.into(),
+                person_name: person_name.to_owned(),
+                menu_item: id.into(),
+                note: note.map(str::to_owned),
+            },
+        )
+    }
+
+    #[test]
+    fn counts_items_in_menu_order() {
+        let lines = by_menu_item(vec![
+            item(2, 605, "alice", None),
+            item(1, 513, "alice", Some("no wasabi")),
+            item(2, 605, "bob", None),
+            item(1, 513, "bob", None),
+            item(1, 513, "carol", None),
+        ]);
+
+        let summary = lines
+            .iter()
+            .map(|x| (x.quantity, x.menu_item.number, x.notes.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![(3, 513, vec!["no wasabi".to_owned()]), (2, 605, vec![])],
+            summary
+        );
+        assert_eq!(Rational::from(50), total(&lines));
+    }
+
+    #[test]
+    fn groups_by_person() {
+        let persons = by_person(vec![
+            item(1, 513, "alice", None),
+            item(1, 513, "alice", None),
+            item(2, 605, "bob", None),
+        ]);
+
+        let summary = persons
+            .iter()
+            .map(|x| (x.0.as_str(), x.1.len(), x.1[0].quantity))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("alice", 1, 2), ("bob", 1, 1)], summary);
+    }
+}
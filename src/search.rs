@@ -0,0 +1,238 @@
+//! Ranking of menu items against what people type, which is usually a
+//! few words from the name, sometimes misspelled, in any order
+
+extern crate strsim;
+
+use models::MenuItem;
+
+/// Matches below this score are not worth showing
+pub const MIN_SCORE: u32 = 40;
+
+/// The best match must score at least this to be ordered without asking
+pub const CONFIDENT_SCORE: u32 = 75;
+
+/// ...and beat the runner-up by at least this much
+pub const CONFIDENT_LEAD: u32 = 10;
+
+/// How many matches to show when listing them
+pub const SHORTLIST_LENGTH: usize = 5;
+
+pub struct Match {
+    /// From 0 to 100, where 100 is an exact match
+    pub score: u32,
+    pub menu_item: MenuItem,
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|x| x.len() > 0)
+        .map(|x| x.to_lowercase())
+        .collect()
+}
+
+/// How well a single query token matches a single token from the item,
+/// from 0 to 1
+fn token_score(query: &str, token: &str) -> f64 {
+    if query == token {
+        return 1.0;
+    }
+
+    let query_len = query.chars().count();
+    let token_len = token.chars().count();
+
+    // Partial words, as in "tem" for "tempura". Longer prefixes say more
+    if query_len >= 2 && token.starts_with(query) {
+        return 0.7 + 0.25 * query_len as f64 / token_len as f64;
+    }
+
+    if query_len >= 3 && token.contains(query) {
+        return 0.6 + 0.2 * query_len as f64 / token_len as f64;
+    }
+
+    // Typos
+    let similarity = strsim::jaro_winkler(query, token);
+    if similarity >= 0.85 {
+        similarity * 0.8
+    } else {
+        0.0
+    }
+}
+
+/// Scores `menu_item` against the query. Each query token counts as well
+/// as its best match in the name or the category, where the category
+/// counts less. Names with many words that were not asked for lose a
+/// little, so "edamame" prefers EDAMAME over SPICY EDAMAME
+pub fn score(query: &str, menu_item: &MenuItem) -> u32 {
+    const CATEGORY_WEIGHT: f64 = 0.8;
+    const COVERAGE_WEIGHT: f64 = 0.25;
+
+    let query_tokens = tokenize(query);
+    let name_tokens = tokenize(&menu_item.name);
+    let category_tokens = menu_item
+        .category
+        .as_ref()
+        .map(|x| tokenize(x))
+        .unwrap_or_default();
+
+    if query_tokens.is_empty() {
+        return 0;
+    }
+    if query_tokens == name_tokens {
+        return 100;
+    }
+
+    let mut name_tokens_used = vec![false; name_tokens.len()];
+    let mut total = 0.0;
+
+    for query_token in &query_tokens {
+        if query_token.parse::<i32>() == Ok(menu_item.number) {
+            total += 1.0;
+            continue;
+        }
+
+        let mut best = 0.0;
+        let mut best_name_token = None;
+        for (index, token) in name_tokens.iter().enumerate() {
+            let token_score = token_score(query_token, token);
+            if token_score > best {
+                best = token_score;
+                best_name_token = Some(index);
+            }
+        }
+        for token in &category_tokens {
+            let token_score = token_score(query_token, token) * CATEGORY_WEIGHT;
+            if token_score > best {
+                best = token_score;
+                best_name_token = None;
+            }
+        }
+
+        if let Some(index) = best_name_token {
+            name_tokens_used[index] = true;
+        }
+        total += best;
+    }
+
+    let average = total / query_tokens.len() as f64;
+    let coverage = if name_tokens.is_empty() {
+        0.0
+    } else {
+        name_tokens_used.iter().filter(|&&x| x).count() as f64 / name_tokens.len() as f64
+    };
+
+    let score = (1.0 - COVERAGE_WEIGHT) * average + COVERAGE_WEIGHT * coverage;
+    ((score * 100.0).round() as u32).min(99)
+}
+
+/// Scores and sorts the items, best first, dropping those below
+/// `MIN_SCORE`. Ties go to the lowest menu number
+pub fn rank(query: &str, menu_items: Vec<MenuItem>) -> Vec<Match> {
+    let mut matches = menu_items
+        .into_iter()
+        .map(|menu_item| Match {
+            score: score(query, &menu_item),
+            menu_item,
+        })
+        .filter(|x| x.score >= MIN_SCORE)
+        .collect::<Vec<_>>();
+
+    matches.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(a.menu_item.number.cmp(&b.menu_item.number))
+    });
+    matches
+}
+
+/// Whether the best of the ranked matches is good enough to act on
+/// without asking. The full name always is
+pub fn is_confident(matches: &[Match]) -> bool {
+    match matches.len() {
+        0 => false,
+        1 => matches[0].score >= CONFIDENT_SCORE,
+        _ if matches[0].score == 100 => true,
+        _ => {
+            matches[0].score >= CONFIDENT_SCORE
+                && matches[0].score - matches[1].score >= CONFIDENT_LEAD
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate serde_json;
+
+    use super::*;
+    use takedown;
+
+    fn fixture() -> Vec<MenuItem> {
+        let menu: takedown::Menu = serde_json::from_str(include_str!("../take.json")).unwrap();
+
+        let mut id = 0;
+        let mut menu_items = vec![];
+        for category in menu {
+            for item in category.entries {
+                id += 1;
+                menu_items.push(MenuItem {
+                    id: id.into(),
+                    menu: 1.into(),
+                    number: item.number,
+                    name: item.name,
+                    price_in_cents: (item.price * 100.0) as i32,
+                    category: Some(category.category.clone()),
+                });
+            }
+        }
+        menu_items
+    }
+
+    fn best(query: &str) -> Option<i32> {
+        let matches = rank(query, fixture());
+        if is_confident(&matches) {
+            Some(matches[0].menu_item.number)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn finds_by_words_in_any_order() {
+        assert_eq!(Some(605), best("avo salmon"));
+        assert_eq!(Some(605), best("salmon avo"));
+        assert_eq!(Some(513), best("salmon tempura nigiri"));
+        assert_eq!(Some(806), best("tempura mochi"));
+    }
+
+    #[test]
+    fn prefers_items_without_extra_words() {
+        assert_eq!(Some(101), best("edamame"));
+        assert_eq!(Some(102), best("spicy edamame"));
+    }
+
+    #[test]
+    fn handles_partial_words_and_typos() {
+        assert_eq!(Some(605), best("avo salm"));
+        assert_eq!(Some(101), best("edamme"));
+        assert_eq!(Some(803), best("matcha ice"));
+    }
+
+    #[test]
+    fn uses_category() {
+        assert_eq!(Some(603), best("burning vegetarian"));
+    }
+
+    #[test]
+    fn is_not_confident_about_vague_queries() {
+        assert_eq!(None, best("salmon"));
+        assert_eq!(None, best("spicy"));
+        assert_eq!(None, best("pizza"));
+    }
+
+    #[test]
+    fn ranks_without_showing_everything() {
+        let matches = rank("salmon", fixture());
+        assert!(matches.len() < fixture().len() / 2);
+        assert!(matches.iter().all(|x| x.menu_item.name.contains("SALMON")));
+        assert!(rank("pizza", fixture()).is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+use models::Order;
+use state;
+use std::fmt::Write;
+use std::fs;
+use std::io::Write as IoWrite;
+
+use super::{format_cents, to_cents, Backend, Bill, Error, Preview, Receipt};
+
+/// Appends a transaction per order to a plain text accounting journal,
+/// as read by ledger and hledger
+pub struct Journal {
+    path: String,
+    account_prefix: String,
+}
+
+impl Journal {
+    pub fn new(path: String, account_prefix: String) -> Journal {
+        Journal {
+            path,
+            account_prefix,
+        }
+    }
+
+    fn render(&self, bill: &Bill) -> String {
+        let mut buf = String::new();
+
+        writeln!(
+            &mut buf,
+            "{} * {}",
+            bill.timestamp.strftime("%Y-%m-%d").unwrap(),
+            bill.description.replace('\n', " ")
+        )
+        .unwrap();
+
+        let mut debits = bill.debits.iter().collect::<Vec<_>>();
+        debits.sort_by(|a, b| a.0.cmp(b.0));
+        for (account, amount) in debits {
+            writeln!(
+                &mut buf,
+                "    {}{}  {}",
+                self.account_prefix,
+                account,
+                format_cents(-to_cents(amount))
+            )
+            .unwrap();
+        }
+
+        // The last credit posting is left without an amount, to let the
+        // journal balance the rounded debits exactly
+        let mut credits = bill.credits.iter().collect::<Vec<_>>();
+        credits.sort_by(|a, b| a.0.cmp(b.0));
+        if let Some((last, init)) = credits.split_last() {
+            for &(account, amount) in init {
+                writeln!(
+                    &mut buf,
+                    "    {}{}  {}",
+                    self.account_prefix,
+                    account,
+                    format_cents(to_cents(amount))
+                )
+                .unwrap();
+            }
+            writeln!(&mut buf, "    {}{}", self.account_prefix, last.0).unwrap();
+        }
+
+        writeln!(&mut buf, "").unwrap();
+
+        buf
+    }
+}
+
+impl Backend for Journal {
+    fn name(&self) -> &'static str {
+        "journal"
+    }
+
+    fn preview(&self, _order: &Order, bill: &Bill) -> Result<Preview, Error> {
+        Ok(Preview {
+            payload: self.render(bill),
+            pending: false,
+        })
+    }
+
+    fn settle(&self, _state: &state::State, _order: &Order, bill: Bill) -> Result<Receipt, Error> {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(self.render(&bill).as_bytes())?;
+
+        Ok(Receipt::Written {
+            path: self.path.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::fabricate_bill;
+    use super::*;
+
+    #[test]
+    fn last_credit_balances_the_transaction() {
+        let journal = Journal::new("fishsticks.journal".to_owned(), "Friends:".to_owned());
+
+        let bill = fabricate_bill(
+            &[("Alice", "100/3"), ("Bob", "100/3"), ("Carol", "100/3")],
+            &[("Alice", "100")],
+        );
+        assert_eq!(
+            "2017-07-14 * Sushi Bar\n\
+             \x20   Friends:Alice  -33.33\n\
+             \x20   Friends:Bob  -33.33\n\
+             \x20   Friends:Carol  -33.33\n\
+             \x20   Friends:Alice\n\n",
+            journal.render(&bill)
+        );
+
+        let bill = fabricate_bill(&[("Carol", "100")], &[("Alice", "50"), ("Bob", "50")]);
+        assert_eq!(
+            "2017-07-14 * Sushi Bar\n\
+             \x20   Friends:Carol  -100.00\n\
+             \x20   Friends:Alice  50.00\n\
+             \x20   Friends:Bob\n\n",
+            journal.render(&bill)
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use models::Order;
+use num::Zero;
+use sharebill::Rational;
+use state;
+use std::collections::HashMap;
+use std::fmt::Write;
+use time;
+
+use super::{Backend, Bill, Error, Preview, Receipt};
+
+/// Records settlements in the fishsticks database itself, for teams
+/// that have no external service to keep track of who owes whom
+pub struct Ledger;
+
+/// Ledger amounts are signed: credits are positive and debits negative,
+/// so the balance of an account is the sum of its entries
+fn entries(bill: &Bill) -> Vec<(String, Rational)> {
+    let mut entries = bill
+        .credits
+        .iter()
+        .map(|(account, amount)| (account.clone(), amount.clone()))
+        .chain(
+            bill.debits
+                .iter()
+                .map(|(account, amount)| (account.clone(), -amount)),
+        )
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries
+}
+
+impl Backend for Ledger {
+    fn name(&self) -> &'static str {
+        "ledger"
+    }
+
+    fn preview(&self, _order: &Order, bill: &Bill) -> Result<Preview, Error> {
+        let mut payload = String::new();
+
+        writeln!(&mut payload, "{}", bill.description).unwrap();
+        for (account, amount) in entries(bill) {
+            writeln!(&mut payload, "    {}: {}", account, amount).unwrap();
+        }
+
+        Ok(Preview {
+            payload,
+            pending: false,
+        })
+    }
+
+    fn settle(&self, state: &state::State, order: &Order, bill: Bill) -> Result<Receipt, Error> {
+        state.record_ledger_transaction(
+            Some(order.id),
+            &bill.description,
+            bill.timestamp.to_timespec().sec as i32,
+            &entries(&bill),
+        )?;
+
+        Ok(Receipt::Recorded)
+    }
+
+    fn balances(&self, state: &state::State) -> Result<HashMap<String, Rational>, Error> {
+        // Accounts are compared without regard to case, like in the
+        // database, and named as they were first written
+        let mut balances = HashMap::<String, (String, Rational)>::new();
+
+        for entry in state.ledger_entries()? {
+            let amount = entry.amount.parse::<Rational>()?;
+            let balance = balances
+                .entry(entry.account.to_lowercase())
+                .or_insert_with(|| (entry.account.clone(), Rational::zero()));
+            balance.1 = &balance.1 + amount;
+        }
+
+        Ok(balances.into_iter().map(|(_, x)| x).collect())
+    }
+
+    fn pay(
+        &self,
+        state: &state::State,
+        from: &str,
+        to: &str,
+        amount: &Rational,
+    ) -> Result<(), Error> {
+        state.record_ledger_transaction(
+            None,
+            &format!("Payment from {} to {}", from, to),
+            time::now().to_timespec().sec as i32,
+            &[(from.to_owned(), amount.clone()), (to.to_owned(), -amount)],
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::fabricate_bill;
+    use super::*;
+    use db;
+    use serde_json;
+    use takedown;
+
+    fn fabricate_state() -> state::State {
+        let state = state::State::new(db::connect_database(":memory:", true));
+
+        let restaurant = state.create_restaurant("Sushi Bar").unwrap();
+        let menu: takedown::Menu = serde_json::from_str(include_str!("../../take.json")).unwrap();
+        state.ingest_menu(restaurant, &menu).unwrap();
+
+        let menu = state.current_menu_for_restaurant(restaurant).unwrap();
+        state.create_order(menu.id, None, None).unwrap();
+
+        state
+    }
+
+    fn rational(amount: &str) -> Rational {
+        amount.parse().unwrap()
+    }
+
+    #[test]
+    fn fractional_splits_can_be_read_back() {
+        let state = fabricate_state();
+        let order = state.demand_open_order().unwrap();
+
+        let bill = fabricate_bill(
+            &[("Alice", "100/3"), ("Bob", "100/3"), ("Carol", "100/3")],
+            &[("Alice", "100")],
+        );
+        Ledger.settle(&state, &order, bill).unwrap();
+
+        let balances = Ledger.balances(&state).unwrap();
+        assert_eq!(rational("66 2/3"), balances["Alice"]);
+        assert_eq!(rational("-33 1/3"), balances["Bob"]);
+        assert_eq!(rational("-33 1/3"), balances["Carol"]);
+    }
+
+    #[test]
+    fn payments_balance_accounts_regardless_of_case() {
+        let state = fabricate_state();
+
+        Ledger.pay(&state, "bob", "Alice", &rational("12.50")).unwrap();
+        Ledger.pay(&state, "alice", "Bob", &rational("2.50")).unwrap();
+
+        let balances = Ledger.balances(&state).unwrap();
+        assert_eq!(2, balances.len());
+        assert_eq!(rational("-10"), balances["Alice"]);
+        assert_eq!(rational("10"), balances["bob"]);
+    }
+}
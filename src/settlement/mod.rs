@@ -0,0 +1,146 @@
+mod journal;
+mod ledger;
+mod sharebill;
+mod splitwise_csv;
+
+use config::SettlementConfig;
+use models::Order;
+use sharebill::client;
+use sharebill::rational::{ParseRationalError, Rounding};
+use sharebill::Rational;
+use state;
+use std::collections::HashMap;
+use std::io;
+use time;
+use web;
+
+use self::journal::Journal;
+use self::ledger::Ledger;
+use self::sharebill::Sharebill;
+use self::splitwise_csv::SplitwiseCsv;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum Error {
+        StateError(err: state::Error) { from() }
+        Io(err: io::Error) { from() }
+        ParseRational(err: ParseRationalError) { from() }
+        SerdeJson(err: serde_json::Error) { from() }
+        Sharebill(err: client::Error) { from() }
+        MissingConfig(config_path: &'static str)
+        PostChanged(url: String)
+        Unsupported(backend: &'static str, operation: &'static str)
+    }
+}
+
+/// The outcome of settling an order, already mapped from people to accounts
+#[derive(Clone)]
+pub struct Bill {
+    pub description: String,
+    pub timestamp: time::Tm,
+    pub debits: HashMap<String, Rational>,
+    pub credits: HashMap<String, Rational>,
+}
+
+pub enum Receipt {
+    Posted { url: String },
+    Written { path: String },
+    Recorded,
+}
+
+pub struct Preview {
+    pub payload: String,
+
+    /// The payload is left over from an earlier attempt, which will be
+    /// completed rather than starting over
+    pub pending: bool,
+}
+
+pub trait Backend {
+    fn name(&self) -> &'static str;
+
+    fn preview(&self, order: &Order, bill: &Bill) -> Result<Preview, Error>;
+
+    /// Runs before `settle`, outside of the transaction that settles and
+    /// closes the order, to persist what must survive a failed attempt
+    fn prepare(&self, _state: &state::State, _order: &Order, _bill: &Bill) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn settle(&self, state: &state::State, order: &Order, bill: Bill) -> Result<Receipt, Error>;
+
+    fn balances(&self, _state: &state::State) -> Result<HashMap<String, Rational>, Error> {
+        Err(Error::Unsupported(self.name(), "balances"))
+    }
+
+    /// Records that `from` has paid `amount` to `to` outside of fishsticks
+    fn pay(
+        &self,
+        _state: &state::State,
+        _from: &str,
+        _to: &str,
+        _amount: &Rational,
+    ) -> Result<(), Error> {
+        Err(Error::Unsupported(self.name(), "pay"))
+    }
+}
+
+pub fn from_env(env: &web::Env) -> Result<Box<dyn Backend>, Error> {
+    match env.settlement {
+        // Without any configuration, fall back to the built-in ledger
+        // unless there is a Sharebill server to talk to
+        None if env.maybe_sharebill_url.is_none() => Ok(Box::new(Ledger)),
+        None | Some(SettlementConfig::Sharebill) => {
+            let sharebill_url = env
+                .maybe_sharebill_url
+                .as_ref()
+                .ok_or(Error::MissingConfig("web.sharebill_url"))?;
+
+            Ok(Box::new(Sharebill::new(
+                sharebill_url.clone(),
+                env.sharebill_auth.clone(),
+            )))
+        }
+        Some(SettlementConfig::Ledger) => Ok(Box::new(Ledger)),
+        Some(SettlementConfig::SplitwiseCsv {
+            ref directory,
+            ref currency,
+        }) => Ok(Box::new(SplitwiseCsv::new(
+            directory.clone(),
+            currency.clone().unwrap_or_else(|| env.currency.code.clone()),
+        ))),
+        Some(SettlementConfig::Journal {
+            ref path,
+            ref account_prefix,
+        }) => Ok(Box::new(Journal::new(path.clone(), account_prefix.clone()))),
+    }
+}
+
+/// Rounds to whole cents for the file formats, which cannot represent
+/// fractions like a third of the overhead
+fn to_cents(value: &Rational) -> i64 {
+    value.to_cents(Rounding::HalfEven)
+}
+
+fn format_cents(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, cents.abs() / 100, cents.abs() % 100)
+}
+
+/// A bill for a Sushi Bar order, with amounts written as for `Rational`
+#[cfg(test)]
+fn fabricate_bill(debits: &[(&str, &str)], credits: &[(&str, &str)]) -> Bill {
+    fn amounts(amounts: &[(&str, &str)]) -> HashMap<String, Rational> {
+        amounts
+            .iter()
+            .map(|&(account, amount)| (account.to_owned(), amount.parse().unwrap()))
+            .collect()
+    }
+
+    Bill {
+        description: "Sushi Bar".to_owned(),
+        timestamp: time::at_utc(time::Timespec::new(1_500_000_000, 0)),
+        debits: amounts(debits),
+        credits: amounts(credits),
+    }
+}
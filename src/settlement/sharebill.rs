@@ -0,0 +1,131 @@
+use models::Order;
+use sharebill::client::{self, Auth, Client};
+use sharebill::models::{Meta, Post, Transaction};
+use sharebill::Rational;
+use state;
+use std::collections::HashMap;
+
+use super::{Backend, Bill, Error, Preview, Receipt};
+
+pub struct Sharebill {
+    client: Client,
+}
+
+impl Sharebill {
+    pub fn new(url: String, auth: Auth) -> Sharebill {
+        Sharebill {
+            client: Client::new(&url, auth),
+        }
+    }
+}
+
+fn post(bill: Bill) -> Post {
+    Post {
+        meta: Meta {
+            description: bill.description,
+            timestamp: bill.timestamp,
+        },
+        transaction: Transaction {
+            debits: bill.debits,
+            credits: bill.credits,
+        },
+    }
+}
+
+/// Whether the posts bill the same accounts for the same thing, no matter
+/// when they were made
+fn same_bill(a: &Post, b: &Post) -> Result<bool, Error> {
+    Ok(a.meta.description == b.meta.description
+        && serde_json::to_value(&a.transaction)? == serde_json::to_value(&b.transaction)?)
+}
+
+/// The post left over from an earlier attempt to settle the order, if any
+fn pending_post(order: &Order) -> Result<Option<(&str, Post)>, Error> {
+    match (
+        order.sharebill_post_id.as_ref(),
+        order.sharebill_payload.as_ref(),
+    ) {
+        (Some(post_id), Some(payload)) => {
+            Ok(Some((post_id.as_str(), serde_json::from_str(payload)?)))
+        }
+        _ => Ok(None),
+    }
+}
+
+impl Backend for Sharebill {
+    fn name(&self) -> &'static str {
+        "Sharebill"
+    }
+
+    fn preview(&self, order: &Order, bill: &Bill) -> Result<Preview, Error> {
+        let fresh = post(bill.clone());
+
+        if let Some((_, pending)) = pending_post(order)? {
+            if same_bill(&pending, &fresh)? {
+                return Ok(Preview {
+                    payload: serde_json::to_string_pretty(&pending)?,
+                    pending: true,
+                });
+            }
+        }
+
+        Ok(Preview {
+            payload: serde_json::to_string_pretty(&fresh)?,
+            pending: false,
+        })
+    }
+
+    fn prepare(&self, state: &state::State, order: &Order, bill: &Bill) -> Result<(), Error> {
+        // The post id and payload are persisted before sending, so retrying
+        // after a lost response reuses them instead of billing everyone twice
+        let fresh = post(bill.clone());
+
+        if let Some((post_id, pending)) = pending_post(order)? {
+            if same_bill(&pending, &fresh)? {
+                return Ok(());
+            }
+
+            // The order or the credit account has changed since the last
+            // attempt, whose post can only be replaced if it never arrived
+            match self.client.post(post_id) {
+                Err(client::Error::NotFound) => (),
+                Ok(_) => return Err(Error::PostChanged(self.client.post_url(post_id))),
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let post_id = uuid::Uuid::new_v4().to_string();
+        state.set_sharebill_post(order.id, &post_id, &serde_json::to_string(&fresh)?)?;
+
+        Ok(())
+    }
+
+    fn settle(&self, state: &state::State, order: &Order, bill: Bill) -> Result<Receipt, Error> {
+        let pending = (
+            order.sharebill_post_id.clone(),
+            order.sharebill_payload.clone(),
+        );
+        let (post_id, post) = match pending {
+            (Some(post_id), Some(payload)) => (post_id, serde_json::from_str::<Post>(&payload)?),
+            _ => {
+                let post_id = uuid::Uuid::new_v4().to_string();
+                let post = post(bill);
+                state.set_sharebill_post(order.id, &post_id, &serde_json::to_string(&post)?)?;
+
+                (post_id, post)
+            }
+        };
+
+        // Either outcome means the post is in place
+        self.client.put_post(&post_id, &post)?;
+
+        let url = self.client.post_url(&post_id);
+        state.set_billed_post_url(order.id, &url)?;
+
+        Ok(Receipt::Posted { url })
+    }
+
+    fn balances(&self, _state: &state::State) -> Result<HashMap<String, Rational>, Error> {
+        Ok(self.client.balances()?)
+    }
+}
@@ -0,0 +1,143 @@
+use models::Order;
+use state;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use super::{format_cents, to_cents, Backend, Bill, Error, Preview, Receipt};
+
+/// Writes one CSV file per order in the layout Splitwise uses for its
+/// exports, with a column per person holding their net share
+pub struct SplitwiseCsv {
+    directory: String,
+    currency: String,
+}
+
+impl SplitwiseCsv {
+    pub fn new(directory: String, currency: String) -> SplitwiseCsv {
+        SplitwiseCsv {
+            directory,
+            currency,
+        }
+    }
+
+    fn render(&self, bill: &Bill) -> String {
+        let mut accounts = bill
+            .debits
+            .keys()
+            .chain(bill.credits.keys())
+            .cloned()
+            .collect::<Vec<_>>();
+        accounts.sort();
+        accounts.dedup();
+
+        let owed = |account: &str| bill.debits.get(account).map(to_cents).unwrap_or(0);
+        let cost = accounts.iter().map(|x| owed(x)).sum::<i64>();
+
+        // Everything owed is paid by the credit accounts, split the same
+        // way as in the bill. The last of them gets what is left after
+        // rounding the others, so the columns sum to zero
+        let credit_total = bill.credits.values().map(to_cents).sum::<i64>();
+        let mut credit_accounts = bill.credits.keys().collect::<Vec<_>>();
+        credit_accounts.sort();
+
+        let mut shares = HashMap::<&str, i64>::new();
+        let mut remaining = cost;
+        for (index, account) in credit_accounts.iter().enumerate() {
+            let share = if index + 1 == credit_accounts.len() {
+                remaining
+            } else if credit_total != 0 {
+                to_cents(&bill.credits[*account]) * cost / credit_total
+            } else {
+                0
+            };
+            remaining -= share;
+            shares.insert(account.as_str(), share);
+        }
+        let paid = |account: &str| shares.get(account).cloned().unwrap_or(0);
+
+        let mut buf = String::new();
+
+        write!(&mut buf, "Date,Description,Category,Cost,Currency").unwrap();
+        for account in &accounts {
+            write!(&mut buf, ",{}", quote(account)).unwrap();
+        }
+        writeln!(&mut buf, "").unwrap();
+
+        write!(
+            &mut buf,
+            "{},{},Dining out,{},{}",
+            bill.timestamp.strftime("%Y-%m-%d").unwrap(),
+            quote(&bill.description),
+            format_cents(cost),
+            self.currency
+        )
+        .unwrap();
+        for account in &accounts {
+            write!(&mut buf, ",{}", format_cents(paid(account) - owed(account))).unwrap();
+        }
+        writeln!(&mut buf, "").unwrap();
+
+        buf
+    }
+}
+
+fn quote(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+impl Backend for SplitwiseCsv {
+    fn name(&self) -> &'static str {
+        "Splitwise CSV"
+    }
+
+    fn preview(&self, _order: &Order, bill: &Bill) -> Result<Preview, Error> {
+        Ok(Preview {
+            payload: self.render(bill),
+            pending: false,
+        })
+    }
+
+    fn settle(&self, _state: &state::State, order: &Order, bill: Bill) -> Result<Receipt, Error> {
+        fs::create_dir_all(&self.directory)?;
+
+        let path = Path::new(&self.directory).join(format!("order-{}.csv", order.id));
+        fs::write(&path, self.render(&bill))?;
+
+        Ok(Receipt::Written {
+            path: path.to_string_lossy().into_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::fabricate_bill;
+    use super::*;
+
+    #[test]
+    fn columns_sum_to_zero_with_several_credits() {
+        let bill = fabricate_bill(
+            &[("Alice", "100/3"), ("Bob", "100/3"), ("Carol", "100/3")],
+            &[("Alice", "50"), ("Bob", "50")],
+        );
+        let csv = SplitwiseCsv::new("splitwise".to_owned(), "NOK".to_owned());
+
+        assert_eq!(
+            "Date,Description,Category,Cost,Currency,Alice,Bob,Carol\n\
+             2017-07-14,Sushi Bar,Dining out,99.99,NOK,16.66,16.67,-33.33\n",
+            csv.render(&bill)
+        );
+    }
+
+    #[test]
+    fn fields_are_quoted() {
+        assert_eq!("Sushi Bar", quote("Sushi Bar"));
+        assert_eq!("\"Sushi, \"\"the\"\" bar\"", quote("Sushi, \"the\" bar"));
+    }
+}
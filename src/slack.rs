@@ -1,6 +1,10 @@
 use cmd::{self, exec_cmd, CommandContext, Error};
+use currency::Currency;
+use models::{AdjustmentKind, Frontend, Undo};
 use num::Zero;
+use settlement::Receipt;
 use std::fmt::Write;
+use time;
 use web;
 use words::*;
 
@@ -32,6 +36,42 @@ pub struct SlackResponse {
     pub unfurl_links: bool,
 }
 
+/// Describes the minimum order value and budget per person, or an empty
+/// string if there are none
+pub fn describe_limits(
+    currency: &Currency,
+    minimum_order_in_cents: Option<i32>,
+    budget_per_person_in_cents: Option<i32>,
+) -> String {
+    let mut limits = vec![];
+    if let Some(minimum) = minimum_order_in_cents {
+        limits.push(format!("minimum order {}", currency.format_cents(minimum)));
+    }
+    if let Some(budget) = budget_per_person_in_cents {
+        limits.push(format!("budget {} per person", currency.format_cents(budget)));
+    }
+    limits.join(", ")
+}
+
+/// Lists the choices of a poll by number, with who voted for each
+fn describe_tally(tally: &[cmd::PollChoice]) -> String {
+    tally
+        .iter()
+        .enumerate()
+        .map(|(i, choice)| match choice.voters.len() {
+            0 => format!("{}. {}: no votes", i + 1, choice.restaurant_name),
+            count => format!(
+                "{}. {}: {} ({})",
+                i + 1,
+                choice.restaurant_name,
+                count,
+                choice.voters.join(", ")
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 impl From<cmd::Response> for SlackResponse {
     fn from(src: cmd::Response) -> Self {
         use cmd::Response::*;
@@ -48,12 +88,88 @@ impl From<cmd::Response> for SlackResponse {
                 text: format!("🙍 I found no matches for you"),
                 ..Default::default()
             },
+            PreviousOrders { orders } => {
+                let mut buf = String::new();
+
+                writeln!(&mut buf, "💁 Your most recent orders from here were:").unwrap();
+                for (index, order) in orders.into_iter().enumerate() {
+                    let closed = time::at(time::Timespec::new(order.closed.into(), 0));
+                    let items = order
+                        .menu_items
+                        .into_iter()
+                        .map(|x| format!("{}. {}", x.number, x.name))
+                        .collect::<Vec<_>>();
+                    writeln!(
+                        &mut buf,
+                        " {}) {} {}",
+                        index + 1,
+                        closed.strftime("%Y-%m-%d").unwrap(),
+                        items.join(", ")
+                    ).unwrap();
+                }
+                writeln!(&mut buf, "\nRepeat one with /ffs repeat 2 for the second, and so on").unwrap();
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
+            RepeatChanged {
+                currency,
+                unchanged,
+                misses,
+            } => {
+                let mut buf = String::new();
+
+                writeln!(
+                    &mut buf,
+                    "🤔 The menu has changed since then, so I did not order anything:"
+                ).unwrap();
+
+                let mut suggestion = unchanged
+                    .iter()
+                    .map(|x| x.number.to_string())
+                    .collect::<Vec<_>>();
+                for miss in misses {
+                    write!(
+                        &mut buf,
+                        " - {}. {} ({})",
+                        miss.previous.number,
+                        miss.previous.name,
+                        currency.format_cents(miss.previous.price_in_cents)
+                    ).unwrap();
+                    match miss.replacement {
+                        Some(replacement) => {
+                            writeln!(
+                                &mut buf,
+                                " might now be {}. {} ({})",
+                                replacement.number,
+                                replacement.name,
+                                currency.format_cents(replacement.price_in_cents)
+                            ).unwrap();
+                            suggestion.push(replacement.number.to_string());
+                        }
+                        None => writeln!(&mut buf, " is gone").unwrap(),
+                    }
+                }
+                if !suggestion.is_empty() {
+                    writeln!(&mut buf, "\nTry /ffs order {}", suggestion.join(", ")).unwrap();
+                }
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
             OrderNoMatch { search_string } => SlackResponse {
                 text: format!("🙍 I found no matches for {:?}", search_string),
                 ..Default::default()
             },
-            PlacedOrder { menu_items } => {
-                if menu_items.len() == 1 {
+            PlacedOrder {
+                menu_items,
+                over_budget,
+            } => {
+                let mut response = if menu_items.len() == 1 {
                     let menu_item = &menu_items[0];
 
                     SlackResponse {
@@ -85,9 +201,100 @@ impl From<cmd::Response> for SlackResponse {
                         ),
                         ..Default::default()
                     }
+                };
+
+                if let Some(over_budget) = over_budget {
+                    write!(
+                        &mut response.text,
+                        "\n💸 That puts you at {}, over the budget of {}",
+                        over_budget.currency.format(&over_budget.total),
+                        over_budget.currency.format(&over_budget.budget)
+                    ).unwrap();
                 }
+
+                response
             }
-            SearchResults { ref query, ref items } if items.len() > 1 => {
+            OrderRefused {
+                menu_items,
+                over_budget,
+            } => {
+                let summary = menu_items
+                    .into_iter()
+                    .map(|x| format!("{}. {}", x.number, x.name))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                SlackResponse {
+                    text: format!(
+                        "🙅 Not ordering {}: that would put you at {}, over the budget of {}",
+                        summary,
+                        over_budget.currency.format(&over_budget.total),
+                        over_budget.currency.format(&over_budget.budget)
+                    ),
+                    ..Default::default()
+                }
+            }
+            OrderFailed { failures } => {
+                let mut buf = String::new();
+
+                writeln!(&mut buf, "🙍 I did not order anything, as I could not make out:").unwrap();
+                for failure in failures {
+                    let candidates = failure
+                        .matches
+                        .iter()
+                        .map(|x| format!("{}. {}", x.menu_item.number, x.menu_item.name))
+                        .collect::<Vec<_>>();
+
+                    if candidates.is_empty() {
+                        writeln!(&mut buf, " - {:?}: no matches", failure.search_string).unwrap();
+                    } else {
+                        writeln!(
+                            &mut buf,
+                            " - {:?}: could be {}",
+                            failure.search_string,
+                            candidates.join(" or ")
+                        ).unwrap();
+                    }
+                }
+                writeln!(&mut buf, "\nTry again with menu numbers for those").unwrap();
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
+            OrderAmbiguous {
+                search_string,
+                matches,
+            } => {
+                let mut buf = String::new();
+
+                writeln!(
+                    &mut buf,
+                    "🤔 I am not sure what you mean by {:?}. Did you mean one of these?\n",
+                    search_string
+                ).unwrap();
+                for (index, x) in matches.iter().enumerate() {
+                    writeln!(
+                        &mut buf,
+                        " {}) {}. {}",
+                        index + 1,
+                        x.menu_item.number,
+                        x.menu_item.name
+                    ).unwrap();
+                }
+                writeln!(&mut buf, "\nAnswer with /ffs pick 1 to pick the first, and so on").unwrap();
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
+            SearchResults {
+                ref query,
+                ref matches,
+            } if matches.len() > 1 =>
+            {
                 let mut buf = String::new();
 
                 writeln!(
@@ -95,8 +302,12 @@ impl From<cmd::Response> for SlackResponse {
                     "💁 The best matches I found for {:?} are:\n",
                     query
                 ).unwrap();
-                for item in items[..4].iter() {
-                    writeln!(&mut buf, " - {}. {}", item.number, item.name).unwrap();
+                for x in matches {
+                    writeln!(
+                        &mut buf,
+                        " - {}. {} ({}%)",
+                        x.menu_item.number, x.menu_item.name, x.score
+                    ).unwrap();
                 }
 
                 SlackResponse {
@@ -104,8 +315,8 @@ impl From<cmd::Response> for SlackResponse {
                     ..Default::default()
                 }
             }
-            SearchResults { ref items, .. } if items.len() == 1 => {
-                let menu_item = &items[0];
+            SearchResults { ref matches, .. } if matches.len() == 1 => {
+                let menu_item = &matches[0].menu_item;
                 SlackResponse {
                     text: format!(
                         "💁 That query matches the {} \
@@ -150,27 +361,306 @@ impl From<cmd::Response> for SlackResponse {
                     ..Default::default()
                 }
             }
+            RestaurantSuggestion {
+                restaurant_name,
+                command,
+            } => SlackResponse {
+                text: format!("🤔 Did you mean {}? Then try /ffs {}", restaurant_name, command),
+                ..Default::default()
+            },
+            RestaurantAmbiguous { query, restaurants } => {
+                let restaurants = restaurants
+                    .into_iter()
+                    .map(|x| x.name)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                SlackResponse {
+                    text: format!("🤔 {:?} could be any of {}. Which one?", query, restaurants),
+                    ..Default::default()
+                }
+            }
+            RestaurantDetails {
+                restaurant,
+                aliases,
+            } => {
+                // writeln! cannot return Err when writing to a String. unwrap() below is Ok
+                let mut buf = String::new();
+
+                write!(&mut buf, "🍽️ {}", restaurant.name).unwrap();
+                if let Some(cuisine) = restaurant.cuisine {
+                    write!(&mut buf, " ({})", cuisine).unwrap();
+                }
+                if restaurant.archived {
+                    write!(&mut buf, ", archived").unwrap();
+                }
+                buf.push('\n');
+                if let Some(address) = restaurant.address {
+                    writeln!(&mut buf, "📍 {}", address).unwrap();
+                }
+                if let Some(website) = restaurant.website {
+                    writeln!(&mut buf, "🌐 {}", website).unwrap();
+                }
+                if let Some(notes) = restaurant.notes {
+                    writeln!(&mut buf, "📝 {}", notes).unwrap();
+                }
+                if !aliases.is_empty() {
+                    writeln!(&mut buf, "Also known as {}", aliases.join(", ")).unwrap();
+                }
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
+            RestaurantAliasAdded {
+                restaurant_name,
+                alias,
+            } => SlackResponse {
+                text: format!("💁 {} now refers to {}", alias, restaurant_name),
+                ..Default::default()
+            },
+            RestaurantAliasRemoved { alias } => SlackResponse {
+                text: format!("💁 {} no longer refers to any restaurant", alias),
+                ..Default::default()
+            },
+            RestaurantRenamed { old_name, new_name } => SlackResponse {
+                response_type: ResponseType::InChannel,
+                text: format!("💁 {} is now called {}", old_name, new_name),
+                ..Default::default()
+            },
+            RestaurantArchived {
+                restaurant_name,
+                archived,
+            } => SlackResponse {
+                response_type: ResponseType::InChannel,
+                text: if archived {
+                    format!(
+                        "🗄️ Archived {}. It is kept for the history, but no longer listed",
+                        restaurant_name
+                    )
+                } else {
+                    format!("💁 {} is back on the list of restaurants", restaurant_name)
+                },
+                ..Default::default()
+            },
             OpenedOrder {
                 menu_url,
                 restaurant_name,
+                currency,
+                minimum_order_in_cents,
+                budget_per_person_in_cents,
+                phone_numbers,
+            } => {
+                let mut text = format!(
+                    "🔔 Now taking orders from the <{}|{} menu> 📝",
+                    menu_url, restaurant_name
+                );
+                let limits =
+                    describe_limits(&currency, minimum_order_in_cents, budget_per_person_in_cents);
+                if !limits.is_empty() {
+                    write!(&mut text, " ({})", limits).unwrap();
+                }
+                if !phone_numbers.is_empty() {
+                    write!(&mut text, "\n☎️ {}", phone_numbers.join(", ")).unwrap();
+                }
+
+                SlackResponse {
+                    response_type: ResponseType::InChannel,
+                    text,
+                    ..Default::default()
+                }
+            }
+            OrderSent {
+                restaurant_name,
+                email_address,
+                item_count,
             } => SlackResponse {
                 response_type: ResponseType::InChannel,
                 text: format!(
-                    "🔔 Now taking orders from the <{}|{} menu> 📝",
-                    menu_url, restaurant_name
+                    "📧 Sent the order of {} items to {} at {}",
+                    item_count, restaurant_name, email_address
                 ),
                 ..Default::default()
             },
+            OrderSheet {
+                currency,
+                lines,
+                total,
+                url,
+            } => {
+                // writeln! cannot return Err when writing to a String. unwrap() below is Ok
+                let mut buf = String::new();
+
+                writeln!(&mut buf, "🧾 Order sheet (<{}|printable>):", url).unwrap();
+                for line in lines {
+                    writeln!(
+                        &mut buf,
+                        " {} x {}. {}: {}",
+                        line.quantity,
+                        line.menu_item.number,
+                        line.menu_item.name,
+                        currency.format(&line.total())
+                    ).unwrap();
+                    for note in line.notes {
+                        writeln!(&mut buf, "      - {}", note).unwrap();
+                    }
+                }
+                writeln!(&mut buf, "Total: {}", currency.format(&total)).unwrap();
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
+            PackingList { persons, url } => {
+                // writeln! cannot return Err when writing to a String. unwrap() below is Ok
+                let mut buf = String::new();
+
+                writeln!(&mut buf, "📦 Packing list (<{}|printable>):", url).unwrap();
+                for (person_name, lines) in persons {
+                    writeln!(&mut buf, "{}:", person_name).unwrap();
+                    for line in lines {
+                        write!(
+                            &mut buf,
+                            " - {} x {}. {}",
+                            line.quantity, line.menu_item.number, line.menu_item.name
+                        ).unwrap();
+                        if !line.notes.is_empty() {
+                            write!(&mut buf, " ({})", line.notes.join("; ")).unwrap();
+                        }
+                        buf.push('\n');
+                    }
+                }
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
             ClosedOrder => SlackResponse {
                 response_type: ResponseType::InChannel,
                 text: format!("No longer taking orders"),
                 ..Default::default()
             },
+            PollStarted {
+                started_by,
+                choices,
+            } => {
+                let mut buf = String::new();
+                writeln!(&mut buf, "🗳️ {} wants to know where to order from:", started_by).unwrap();
+                for (i, choice) in choices.iter().enumerate() {
+                    writeln!(&mut buf, "{}. {}", i + 1, choice).unwrap();
+                }
+                write!(&mut buf, "Vote with /ffs vote NUMBER or /ffs vote RESTAURANT").unwrap();
+
+                SlackResponse {
+                    response_type: ResponseType::InChannel,
+                    text: buf,
+                    ..Default::default()
+                }
+            }
+            Poll { started_by, tally } => SlackResponse {
+                text: format!("🗳️ Poll started by {}:\n{}", started_by, describe_tally(&tally)),
+                ..Default::default()
+            },
+            Voted { restaurant_name } => SlackResponse {
+                text: format!("🗳️ You voted for {}", restaurant_name),
+                ..Default::default()
+            },
+            PollClosed {
+                tally,
+                winner,
+                opened,
+            } => {
+                let mut text = match winner {
+                    Some(winner) => format!("🏆 {} won the poll", winner),
+                    None => format!("🤷 The poll ended without a winner"),
+                };
+                text.push_str(&format!("\n{}", describe_tally(&tally)));
+                if let Some(opened) = opened {
+                    text.push_str(&format!("\n{}", SlackResponse::from(*opened).text));
+                }
+
+                SlackResponse {
+                    response_type: ResponseType::InChannel,
+                    text,
+                    ..Default::default()
+                }
+            }
             Clear => SlackResponse {
                 response_type: ResponseType::InChannel,
                 text: format!("🙍 So that's how it's going to be!"),
                 ..Default::default()
             },
+            Favorites {
+                restaurant_name,
+                favorites,
+                url,
+            } => {
+                let mut buf = String::new();
+
+                if favorites.is_empty() {
+                    writeln!(&mut buf, "🙍 You have no favorites at {}", restaurant_name).unwrap();
+                } else {
+                    writeln!(&mut buf, "💁 Your favorites at {} are:", restaurant_name).unwrap();
+                }
+                for (name, items) in favorites {
+                    let items = items
+                        .into_iter()
+                        .map(|(number, note)| match note {
+                            Some(note) => format!("{} ({})", number, note),
+                            None => number.to_string(),
+                        })
+                        .collect::<Vec<_>>();
+                    writeln!(&mut buf, " - {}: {}", name, items.join(", ")).unwrap();
+                }
+                writeln!(&mut buf, "Everyone's favorites are at {}", url).unwrap();
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
+            FavoriteSaved { name, item_count } => SlackResponse {
+                text: format!(
+                    "💁 Saved your {} items as {:?}. Order them again with /ffs fav order {}",
+                    item_count, name, name
+                ),
+                ..Default::default()
+            },
+            FavoriteRemoved { name } => SlackResponse {
+                text: format!("💁 Forgot your favorite {:?}", name),
+                ..Default::default()
+            },
+            Undone { currency, undo } => SlackResponse {
+                response_type: ResponseType::InChannel,
+                text: match undo {
+                    Undo::RemoveOrderItems(ref items) if items.len() == 1 => {
+                        format!("↩️ Took the item you just ordered off the order")
+                    }
+                    Undo::RemoveOrderItems(items) => {
+                        format!("↩️ Took the {} items you just ordered off the order", items.len())
+                    }
+                    Undo::RestoreOrderItems(items) => {
+                        format!("↩️ Put the {} items you cleared back on the order", items.len())
+                    }
+                    Undo::SetOverhead(overhead_in_cents) => format!(
+                        "↩️ Set the overhead back to {}",
+                        currency.format_cents(overhead_in_cents)
+                    ),
+                    Undo::Reopen => format!("↩️ Opened the order again"),
+                },
+                ..Default::default()
+            },
+            UndoRefused { over_budget } => SlackResponse {
+                text: format!(
+                    "🙅 Not putting the items you cleared back: that would put you at {}, over the budget of {}",
+                    over_budget.currency.format(&over_budget.total),
+                    over_budget.currency.format(&over_budget.budget)
+                ),
+                ..Default::default()
+            },
             Associations { associations } => {
                 let associations = associations
                     .into_iter()
@@ -197,34 +687,144 @@ impl From<cmd::Response> for SlackResponse {
                 ),
                 ..Default::default()
             },
-            Sharebill { url } => SlackResponse {
+            Settled {
+                receipt: Receipt::Posted { url },
+                backend,
+            } => SlackResponse {
+                response_type: ResponseType::InChannel,
+                text: format!("💸 Posted to <{}|{}> and closed order ✔️", url, backend),
+                ..Default::default()
+            },
+            Settled {
+                receipt: Receipt::Written { path },
+                backend,
+            } => SlackResponse {
                 response_type: ResponseType::InChannel,
-                text: format!("💸 Posted to <{}|Sharebill> and closed order ✔️", url),
+                text: format!("💸 Wrote {} bill to {} and closed order ✔️", backend, path),
                 ..Default::default()
             },
-            Overhead { overhead_in_cents } => SlackResponse {
+            Settled {
+                receipt: Receipt::Recorded,
+                backend,
+            } => SlackResponse {
+                response_type: ResponseType::InChannel,
+                text: format!("💸 Recorded bill in the {} and closed order ✔️", backend),
+                ..Default::default()
+            },
+            SettlementPreview {
+                backend,
+                payload,
+                pending,
+            } => {
+                let mut buf = String::new();
+
+                if pending {
+                    writeln!(
+                        &mut buf,
+                        "💁 This is pending from an earlier attempt, \
+                         and will be sent to {} unchanged:",
+                        backend
+                    )
+                    .unwrap();
+                } else {
+                    writeln!(&mut buf, "💁 This is what I would send to {}:", backend).unwrap();
+                }
+                writeln!(&mut buf, "```\n{}\n```", payload).unwrap();
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
+            MissingAssociations { slack_names } => SlackResponse {
                 text: format!(
-                    "💁 Overhead is set to {}.{:02}",
-                    overhead_in_cents / 100,
-                    overhead_in_cents % 100
+                    "🙍 These people have no account to bill: {}\n\
+                     Use /ffs associate [SLACK_NAME] SHAREBILL_ACCOUNT",
+                    slack_names.join(", ")
+                ),
+                ..Default::default()
+            },
+            History { orders } => {
+                // writeln! cannot return Err when writing to a String. unwrap() below is Ok
+                let mut buf = String::new();
+
+                writeln!(&mut buf, "💁 The most recent orders were:").unwrap();
+                for (restaurant_name, order) in orders {
+                    let closed = time::at(time::Timespec::new(order.closed.unwrap_or(0).into(), 0));
+                    write!(
+                        &mut buf,
+                        " - {} {}",
+                        closed.strftime("%Y-%m-%d").unwrap(),
+                        restaurant_name
+                    )
+                    .unwrap();
+                    match order.billed_post_url {
+                        Some(url) => writeln!(&mut buf, " {}", url).unwrap(),
+                        None => writeln!(&mut buf, "").unwrap(),
+                    }
+                }
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
+            Log { entries, url } => {
+                let mut buf = String::new();
+
+                if entries.is_empty() {
+                    writeln!(&mut buf, "💁 Nothing has happened yet").unwrap();
+                } else {
+                    writeln!(&mut buf, "💁 The most recent commands were:").unwrap();
+                }
+                for entry in entries.into_iter().rev() {
+                    let created = time::at(time::Timespec::new(entry.created.into(), 0));
+                    write!(
+                        &mut buf,
+                        " - {} {}",
+                        created.strftime("%Y-%m-%d %H:%M").unwrap(),
+                        entry.effective_user
+                    ).unwrap();
+                    if entry.real_user != entry.effective_user {
+                        write!(&mut buf, " (sudo by {})", entry.real_user).unwrap();
+                    }
+                    writeln!(
+                        &mut buf,
+                        ": {} {} ({})",
+                        entry.command, entry.args, entry.outcome
+                    ).unwrap();
+                }
+                writeln!(&mut buf, "More at {}", url).unwrap();
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
+            Overhead {
+                currency,
+                overhead_in_cents,
+            } => SlackResponse {
+                text: format!(
+                    "💁 Overhead is set to {}",
+                    currency.format_cents(overhead_in_cents)
                 ),
                 ..Default::default()
             },
             OverheadSet {
+                currency,
                 prev_overhead_in_cents,
                 new_overhead_in_cents,
             } => SlackResponse {
                 response_type: ResponseType::InChannel,
                 text: format!(
-                    "💁 Overhead changed from {}.{:02} to {}.{:02}",
-                    prev_overhead_in_cents / 100,
-                    prev_overhead_in_cents % 100,
-                    new_overhead_in_cents / 100,
-                    new_overhead_in_cents % 100
+                    "💁 Overhead changed from {} to {}",
+                    currency.format_cents(prev_overhead_in_cents),
+                    currency.format_cents(new_overhead_in_cents)
                 ),
                 ..Default::default()
             },
-            Summary { orders } => {
+            Summary { orders, minimum } => {
                 // writeln! cannot return Err when writing to a String. unwrap() below is Ok
                 let mut buf = String::new();
 
@@ -235,14 +835,41 @@ impl From<cmd::Response> for SlackResponse {
                     }
                 }
 
+                if let Some(minimum) = minimum {
+                    let cmd::MinimumOrder {
+                        currency,
+                        total,
+                        minimum,
+                    } = minimum;
+
+                    if total < minimum {
+                        writeln!(
+                            &mut buf,
+                            "Minimum order: {} of {} ({} to go)",
+                            currency.format(&total),
+                            currency.format(&minimum),
+                            currency.format(&(&minimum - &total))
+                        ).unwrap();
+                    } else {
+                        writeln!(
+                            &mut buf,
+                            "Minimum order: {} of {} ✔️",
+                            currency.format(&total),
+                            currency.format(&minimum)
+                        ).unwrap();
+                    }
+                }
+
                 SlackResponse {
                     text: buf,
                     ..Default::default()
                 }
             }
             Price {
+                currency,
                 overhead,
                 overhead_per_person,
+                adjustments,
                 summary,
             } => {
                 // writeln! cannot return Err when writing to a String. unwrap() below is Ok
@@ -252,20 +879,25 @@ impl From<cmd::Response> for SlackResponse {
                     writeln!(
                         &mut buf,
                         "Total overhead {}, per person: {}",
-                        overhead, overhead_per_person
+                        currency.format(&overhead),
+                        currency.format(&overhead_per_person)
                     )
                     .unwrap();
                 }
 
+                for (adjustment, total) in adjustments {
+                    writeln!(&mut buf, "{}", adjustment.describe(&total, &currency)).unwrap();
+                }
+
                 for (person_name, total, items) in summary {
-                    writeln!(&mut buf, "{}: {:.2}", person_name, total).unwrap();
+                    writeln!(&mut buf, "{}: {}", person_name, currency.format(&total)).unwrap();
                     for menu_item in items {
                         writeln!(
                             &mut buf,
-                            " - {}. {}: {:.2}",
+                            " - {}. {}: {}",
                             menu_item.number,
                             menu_item.name,
-                            menu_item.price_in_cents as f64 / 100.
+                            currency.format_cents(menu_item.price_in_cents)
                         )
                         .unwrap();
                     }
@@ -276,17 +908,21 @@ impl From<cmd::Response> for SlackResponse {
                     ..Default::default()
                 }
             }
-            Suggest { balances } => {
+            Suggest {
+                backend,
+                currency,
+                balances,
+            } => {
                 let mut buf = String::new();
 
-                writeln!(&mut buf, "💁 The poorest people on sharebill are:").unwrap();
+                writeln!(&mut buf, "💁 The poorest people on {} are:", backend).unwrap();
                 for (account_name, old_balance, new_balance) in balances {
                     writeln!(
                         &mut buf,
                         " - {} ({}, projected new balance: {})",
                         account_name,
-                        old_balance.0.to_integer(),
-                        new_balance.0.to_integer()
+                        currency.format(&old_balance),
+                        currency.format(&new_balance)
                     )
                     .unwrap();
                 }
@@ -297,24 +933,329 @@ impl From<cmd::Response> for SlackResponse {
                     ..Default::default()
                 }
             }
+            Balance {
+                account,
+                balance,
+                currency,
+            } => SlackResponse {
+                text: format!(
+                    "💁 The balance of {} is {}",
+                    account,
+                    currency.format(&balance)
+                ),
+                ..Default::default()
+            },
+            Balances {
+                backend,
+                currency,
+                balances,
+            } => {
+                let mut buf = String::new();
+
+                writeln!(&mut buf, "💁 These are the balances on {}:", backend).unwrap();
+                for (account, balance) in balances {
+                    writeln!(&mut buf, " - {}: {}", account, currency.format(&balance)).unwrap();
+                }
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
+            Paid {
+                from,
+                to,
+                amount,
+                currency,
+            } => SlackResponse {
+                response_type: ResponseType::InChannel,
+                text: format!(
+                    "💸 Recorded a payment of {} from {} to {}",
+                    currency.format(&amount),
+                    from,
+                    to
+                ),
+                ..Default::default()
+            },
+            Currency {
+                restaurant_name,
+                currency,
+            } => SlackResponse {
+                text: format!("💁 Prices at {} are in {}", restaurant_name, currency.code),
+                ..Default::default()
+            },
+            CurrencySet {
+                restaurant_name,
+                currency,
+            } => SlackResponse {
+                response_type: ResponseType::InChannel,
+                text: format!(
+                    "💁 Prices at {} are now in {}",
+                    restaurant_name, currency.code
+                ),
+                ..Default::default()
+            },
+            ExchangeRate {
+                ref from, ref to, ..
+            } if from == to => SlackResponse {
+                text: format!("💁 Prices are in {}, so no exchange rate is needed", to.code),
+                ..Default::default()
+            },
+            ExchangeRate {
+                from,
+                to,
+                rate: Some(rate),
+            } => SlackResponse {
+                text: format!("💁 1 {} is {:#.4} {}", from.code, rate, to.code),
+                ..Default::default()
+            },
+            ExchangeRate {
+                from,
+                to,
+                rate: None,
+            } => SlackResponse {
+                text: format!(
+                    "🙍 I know of no exchange rate from {} to {}\n\
+                     Use /ffs rate RATE",
+                    from.code, to.code
+                ),
+                ..Default::default()
+            },
+            ExchangeRateSet { from, to, rate } => SlackResponse {
+                response_type: ResponseType::InChannel,
+                text: format!(
+                    "💱 Converting at 1 {} = {:#.4} {} for this order",
+                    from.code, rate, to.code
+                ),
+                ..Default::default()
+            },
+            Adjustments {
+                ref adjustments,
+                ..
+            } if adjustments.is_empty() => SlackResponse {
+                text: format!("💁 There are no adjustments to this order"),
+                ..Default::default()
+            },
+            Adjustments {
+                currency,
+                adjustments,
+            } => {
+                let mut buf = String::new();
+
+                writeln!(&mut buf, "💁 These are the adjustments to this order:").unwrap();
+                for (adjustment, total) in adjustments {
+                    writeln!(
+                        &mut buf,
+                        " - {}. {}",
+                        adjustment.id,
+                        adjustment.describe(&total, &currency)
+                    )
+                    .unwrap();
+                }
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
+            AdjustmentAdded {
+                currency,
+                adjustment,
+            } => {
+                let description = match adjustment.kind {
+                    AdjustmentKind::Fixed => currency.format(&adjustment.amount),
+                    AdjustmentKind::Percentage => format!("{}%", adjustment.amount),
+                    AdjustmentKind::PerPerson => {
+                        format!("{} per person", currency.format(&adjustment.amount))
+                    }
+                };
+
+                SlackResponse {
+                    response_type: ResponseType::InChannel,
+                    text: format!(
+                        "💁 Added {} of {} to the order as adjustment {}",
+                        adjustment.description, description, adjustment.id
+                    ),
+                    ..Default::default()
+                }
+            }
+            AdjustmentRemoved { id } => SlackResponse {
+                response_type: ResponseType::InChannel,
+                text: format!("💁 Removed adjustment {}", id),
+                ..Default::default()
+            },
+            Contacts {
+                restaurant_name,
+                phone_numbers,
+                email_addresses,
+            } => {
+                let mut buf = String::new();
+
+                if phone_numbers.is_empty() && email_addresses.is_empty() {
+                    write!(&mut buf, "🙍 I have no contact details for {}", restaurant_name).unwrap();
+                } else {
+                    writeln!(&mut buf, "💁 You can reach {} at:", restaurant_name).unwrap();
+                    for number in phone_numbers {
+                        writeln!(&mut buf, " ☎️ {}", number).unwrap();
+                    }
+                    for address in email_addresses {
+                        writeln!(&mut buf, " ✉️ {}", address).unwrap();
+                    }
+                }
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
+            ItemAliases {
+                restaurant_name,
+                aliases,
+            } => {
+                let mut buf = String::new();
+
+                if aliases.is_empty() {
+                    write!(&mut buf, "🙍 There are no nicknames for the food at {}", restaurant_name)
+                        .unwrap();
+                } else {
+                    writeln!(&mut buf, "💁 Nicknames for the food at {}:", restaurant_name).unwrap();
+                    for (alias, number, name) in aliases {
+                        match name {
+                            Some(name) => writeln!(&mut buf, " - {:?}: {}. {}", alias, number, name),
+                            None => writeln!(&mut buf, " - {:?}: {} (not on the menu)", alias, number),
+                        }.unwrap();
+                    }
+                }
+
+                SlackResponse {
+                    text: buf,
+                    ..Default::default()
+                }
+            }
+            ItemAliasAdded {
+                restaurant_name,
+                alias,
+                menu_item,
+            } => SlackResponse {
+                text: format!(
+                    "💁 {:?} now means {}. {} at {}",
+                    alias, menu_item.number, menu_item.name, restaurant_name
+                ),
+                ..Default::default()
+            },
+            ItemAliasRemoved {
+                restaurant_name,
+                alias,
+            } => SlackResponse {
+                text: format!("💁 {:?} no longer means anything at {}", alias, restaurant_name),
+                ..Default::default()
+            },
+            ContactAdded {
+                restaurant_name,
+                contact,
+            } => SlackResponse {
+                text: format!("💁 Added {} to the contacts for {}", contact, restaurant_name),
+                ..Default::default()
+            },
+            ContactRemoved {
+                restaurant_name,
+                contact,
+            } => SlackResponse {
+                text: format!("💁 Removed {} from the contacts for {}", contact, restaurant_name),
+                ..Default::default()
+            },
+            Limits {
+                currency,
+                minimum_order_in_cents,
+                budget_per_person_in_cents,
+            } => {
+                let limits =
+                    describe_limits(&currency, minimum_order_in_cents, budget_per_person_in_cents);
+
+                SlackResponse {
+                    text: if limits.is_empty() {
+                        format!("💁 This order has no minimum order or budget")
+                    } else {
+                        format!("💁 This order has a {}", limits)
+                    },
+                    ..Default::default()
+                }
+            }
+            LimitsSet {
+                restaurant_name,
+                currency,
+                minimum_order_in_cents,
+                budget_per_person_in_cents,
+            } => {
+                let limits =
+                    describe_limits(&currency, minimum_order_in_cents, budget_per_person_in_cents);
+
+                SlackResponse {
+                    response_type: ResponseType::InChannel,
+                    text: if limits.is_empty() {
+                        format!(
+                            "💁 Removed the minimum order and budget for this and later orders from {}",
+                            restaurant_name
+                        )
+                    } else {
+                        format!(
+                            "💁 This and later orders from {} have a {}",
+                            restaurant_name, limits
+                        )
+                    },
+                    ..Default::default()
+                }
+            }
             Help => SlackResponse {
                 text: "USAGE: /ffs command args...\n\
+                    adjust [AMOUNT] [DESCRIPTION]\n    Add a fee or discount to the current order, or list them. AMOUNT is fixed and split evenly (79, -50), a percentage of what everyone ordered (10%, -20%) or per person (15/person)\n\
+                    adjust remove ID\n    Remove an adjustment from the current order\n\
+                    alias\n    List the nicknames for menu items at the restaurant of the current order\n\
+                    alias add|remove NAME [NUMBER]\n    Let NAME be used in place of menu item NUMBER when ordering from this restaurant, or stop that\n\
                     associate [SLACK_NAME] SHAREBILL_ACCOUNT\n    Associate the given slack name (defaults to your name) with the given sharebill account\n\
                     associate\n    Display all slack name-sharebill account associations\n\
+                    balance [NAME]\n    Show the balance of the given person or account (defaults to yours)\n\
+                    balances\n    Show all non-zero balances\n\
                     clear\n    Withdraw all your current orders\n\
                     closeorder\n    Close the current order\n\
+                    contact [RESTAURANT]\n    Show the phone numbers and email addresses of the given restaurant (defaults to the one of the current order)\n\
+                    contact add|remove CONTACT\n    Add or remove a phone number or email address for the restaurant of the current order\n\
+                    currency [CODE]\n    Get/set the currency of the restaurant of the current order, such as EUR\n\
+                    fav\n    List your favorites at the restaurant of the current order\n\
+                    fav save|order|remove NAME\n    Save what you have in the current order as a favorite, order a favorite or forget it\n\
                     help\n    This help\n\
-                    openorder RESTAURANT\n    Start a new order from the given restaurant\n\
-                    order QUERY\n    Order whatever matches QUERY in the menu\n\
+                    history [COUNT]\n    List the most recently closed orders\n\
+                    limits [min=AMOUNT] [budget=AMOUNT]\n    Get/set the minimum order value and the budget per person for the current order and later orders from the same restaurant. Use none to remove a limit\n\
+                    log [COUNT]\n    Show who did what most recently, for the current order if there is one\n\
+                    openorder RESTAURANT [min=AMOUNT] [budget=AMOUNT]\n    Start a new order from the given restaurant, given by name, alias, id or the start of a name, optionally overriding its minimum order value and budget per person\n\
+                    order QUERY [xCOUNT] [(NOTE)], ...\n    Order whatever matches QUERY in the menu, optionally up to 20 of it and with a note for the restaurant, like 513 x2 (no wasabi). Separate items with commas\n\
+                    ordersheet [packing]\n    Show the current order by menu item with counts, as the restaurant needs it, or by person for packing\n\
                     overhead [VALUE]\n    Get/set overhead (delivery cost, gratuity, etc) for current order\n\
+                    pay NAME AMOUNT\n    Record in the built-in ledger that you have paid AMOUNT to NAME\n\
+                    pick NUMBER\n    Pick from the list you were shown when your order matched several items\n\
+                    poll\n    Show the votes so far in the current poll\n\
+                    poll start [RESTAURANT, ...]\n    Start a poll on where to order from, between the given restaurants or all of them\n\
+                    poll close [open]\n    End the current poll and announce the winner. Use open to also open an order at the winner\n\
                     price\n    Like summary, but with price annotations\n\
-                    repeat\n    Repeat your last order for the current restaurant\n\
+                    rate [RATE]\n    Get/set the exchange rate into the home currency for the current order\n\
+                    repeat [NUMBER]\n    Repeat your last order for the current restaurant, or another recent one\n\
+                    repeat list\n    List your recent orders for the current restaurant\n\
+                    restaurant RESTAURANT\n    Show the details of the given restaurant\n\
+                    restaurant alias RESTAURANT = ALIAS\n    Let ALIAS refer to the given restaurant, for instance in openorder\n\
+                    restaurant unalias ALIAS\n    Remove the given alias\n\
+                    restaurant archive|unarchive RESTAURANT\n    Hide the given restaurant from the list of restaurants, or bring it back\n\
+                    restaurant rename OLD_NAME = NEW_NAME\n    Rename the given restaurant\n\
                     restaurants\n    List known restaurants\n\
                     search QUERY\n    See what matches QUERY in the menu\n\
-                    sharebill [CREDIT_ACCOUNT]\n    Post order to Sharebill. CREDIT_ACCOUNT defaults to your account\n\
+                    send [again] [NOTE]\n    Email the current order to the restaurant, with an optional note. Use again to send it once more\n\
+                    settle preview [CREDIT_ACCOUNT]\n    Show what would be settled without settling it\n\
+                    settle [CREDIT_ACCOUNT]\n    Settle the order with the configured backend, by default Sharebill if web.sharebill_url is set and the built-in ledger otherwise. CREDIT_ACCOUNT defaults to your account. Retrying resends the same post\n\
+                    sharebill [CREDIT_ACCOUNT]\n    Same as settle\n\
                     sudo USER args...\n    Perform the command specified in args as USER\n\
-                    suggest\n    Suggest who should pay for the order based on Sharebill balance\n\
+                    suggest\n    Suggest who should pay for the order based on account balances\n\
                     summary\n    See the current order\n\
+                    undo\n    Undo your most recent order, clear or change of overhead for the current order, or closing the most recent order\n\
+                    vote RESTAURANT|NUMBER\n    Vote in the current poll, by name or by number in the list of choices. Voting again changes your vote\n\
                     ".to_owned(),
                 ..Default::default()
             },
@@ -362,6 +1303,8 @@ fn slack_core(maybe_slack_token: &Option<&str>, req: &mut Request) -> Result<Sla
             args: args,
             user_name: user_name,
             env: &env,
+            frontend: Frontend::Slack,
+            real_user_name: user_name,
         },
     )
     .map(Into::into)
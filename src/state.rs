@@ -4,6 +4,9 @@ extern crate time;
 use diesel;
 use ingest;
 use models::*;
+use search;
+use serde_json;
+use sharebill::Rational;
 use takedown;
 
 use diesel::prelude::*;
@@ -15,9 +18,12 @@ quick_error! {
         Ingest(err: ingest::Error) { from() }
         OrderAlreadyOpen(current_open_order: Order) { }
         OrderAlreadyClosed(order: Order) { }
+        PollAlreadyOpen(current_open_poll: Poll) { }
         CouldntCreateTransaction(err: diesel::result::Error) { }
         NoOpenOrder
+        NoOpenPoll
         NotFound
+        NameTaken(name: String) { }
     }
 }
 
@@ -31,14 +37,47 @@ impl<'a, 'b> Query<'a>
 where
     'b: 'a,
 {
-    pub fn interpret_string(input: &'b str) -> Query<'a> {
-        match input.parse::<i32>() {
-            Ok(integer) => Query::ExactInteger(integer),
-            Err(_) => Query::FuzzyString(input),
+    /// Takes the input as a menu number, with or without a leading `#`,
+    /// then as one of `aliases` and finally as something to search for
+    pub fn interpret_string(input: &'b str, aliases: &[ItemAlias]) -> Query<'a> {
+        if let Ok(integer) = input.trim_start_matches('#').parse::<i32>() {
+            return Query::ExactInteger(integer);
+        }
+
+        let input = input.trim();
+        let lowercase = input.to_lowercase();
+        match aliases.iter().find(|x| x.alias.to_lowercase() == lowercase) {
+            Some(item_alias) => Query::ExactInteger(item_alias.number),
+            None => Query::FuzzyString(input),
         }
     }
 }
 
+pub enum RestaurantMatch {
+    /// Matched an id, a name, an alias or a unique prefix of a name or alias
+    Found(Restaurant),
+
+    /// The clearly best of the fuzzy matches, which should be confirmed
+    /// before it is used
+    Suggestion(Restaurant),
+
+    /// Several restaurants match about equally well
+    Ambiguous(Vec<Restaurant>),
+
+    NoMatch,
+}
+
+/// What to record in the audit log
+pub struct Audit<'a> {
+    pub frontend: Frontend,
+    pub real_user: &'a str,
+    pub effective_user: &'a str,
+    pub order: Option<OrderId>,
+    pub command: &'a str,
+    pub args: &'a str,
+    pub outcome: &'a str,
+}
+
 pub struct State {
     db_connection: diesel::sqlite::SqliteConnection,
 }
@@ -58,6 +97,23 @@ impl State {
         }
     }
 
+    /// Runs `f` in a transaction, so that either all or none of the changes
+    /// it makes are kept
+    pub fn transaction<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: From<diesel::result::Error>,
+    {
+        self.db_connection.transaction(f)
+    }
+
+    fn last_insert_rowid(&self) -> Result<i32, Error> {
+        use diesel::expression::sql_literal::sql;
+        use diesel::types::Integer;
+
+        Ok(sql::<Integer>("SELECT last_insert_rowid()").get_result(&self.db_connection)?)
+    }
+
     pub fn create_restaurant(&self, name: &str) -> Result<RestaurantId, Error> {
         use schema::restaurants;
 
@@ -81,10 +137,23 @@ impl State {
         Ok(restaurant_id)
     }
 
+    /// The restaurants that have not been archived
     pub fn restaurants(&self) -> Result<Vec<Restaurant>, Error> {
         use schema::restaurants::dsl::*;
 
-        Ok(restaurants.load::<Restaurant>(&self.db_connection)?)
+        Ok(restaurants
+            .filter(archived.eq(false))
+            .order(name.asc())
+            .load::<Restaurant>(&self.db_connection)?)
+    }
+
+    pub fn archived_restaurants(&self) -> Result<Vec<Restaurant>, Error> {
+        use schema::restaurants::dsl::*;
+
+        Ok(restaurants
+            .filter(archived.eq(true))
+            .order(name.asc())
+            .load::<Restaurant>(&self.db_connection)?)
     }
 
     pub fn restaurant(&self, restaurant_id: RestaurantId) -> Result<Option<Restaurant>, Error> {
@@ -96,7 +165,15 @@ impl State {
             .pop())
     }
 
+    /// Finds a restaurant that has not been archived
     pub fn restaurant_by_name(&self, query_name: &str) -> Result<Option<Restaurant>, Error> {
+        Ok(self
+            .any_restaurant_by_name(query_name)?
+            .filter(|x| !x.archived))
+    }
+
+    /// Finds a restaurant whether it has been archived or not
+    pub fn any_restaurant_by_name(&self, query_name: &str) -> Result<Option<Restaurant>, Error> {
         use schema::restaurants::dsl::*;
 
         Ok(restaurants
@@ -106,6 +183,485 @@ impl State {
             .pop())
     }
 
+    /// Looks up a restaurant that has not been archived the way a person
+    /// would refer to it, by id, name, alias or something close to them
+    pub fn find_restaurant(&self, query: &str) -> Result<RestaurantMatch, Error> {
+        /// Fuzzy matches further away than this are not considered at all
+        const MAX_DISTANCE: usize = 300;
+
+        /// The best fuzzy match must be this much better than the next one
+        /// to be suggested on its own
+        const MIN_LEAD: usize = 50;
+
+        let query = query.trim();
+        if query.len() == 0 {
+            return Ok(RestaurantMatch::NoMatch);
+        }
+
+        let mut restaurants = self.restaurants()?;
+
+        // Names can be numbers too, so those are tried when no id matches
+        if let Ok(id) = query.parse::<i32>() {
+            if let Some(index) = restaurants.iter().position(|x| i32::from(x.id) == id) {
+                return Ok(RestaurantMatch::Found(restaurants.swap_remove(index)));
+            }
+        }
+
+        let aliases = self.restaurant_aliases()?;
+
+        // Every name the restaurants go by, with the index of the restaurant
+        let names = restaurants
+            .iter()
+            .enumerate()
+            .map(|(index, x)| (x.name.to_lowercase(), index))
+            .chain(aliases.iter().filter_map(|alias| {
+                restaurants
+                    .iter()
+                    .position(|x| x.id == alias.restaurant)
+                    .map(|index| (alias.alias.to_lowercase(), index))
+            }))
+            .collect::<Vec<_>>();
+
+        let lowercase_query = query.to_lowercase();
+
+        let mut indices = match names.iter().find(|x| x.0 == lowercase_query) {
+            Some(&(_, index)) => vec![index],
+            None => names
+                .iter()
+                .filter(|x| x.0.starts_with(&lowercase_query))
+                .map(|x| x.1)
+                .collect(),
+        };
+        indices.sort();
+        indices.dedup();
+
+        let take = |mut restaurants: Vec<Restaurant>, indices: &[usize]| {
+            let mut taken = vec![];
+            for &index in indices.iter().rev() {
+                taken.push(restaurants.swap_remove(index));
+            }
+            taken.sort_by(|a, b| a.name.cmp(&b.name));
+            taken
+        };
+
+        if indices.len() == 1 {
+            return Ok(RestaurantMatch::Found(restaurants.swap_remove(indices[0])));
+        }
+        if indices.len() > 1 {
+            return Ok(RestaurantMatch::Ambiguous(take(restaurants, &indices)));
+        }
+
+        let mut distances = names
+            .iter()
+            .map(|&(ref name, index)| (distance(&lowercase_query, name), index))
+            .filter(|x| x.0 <= MAX_DISTANCE)
+            .collect::<Vec<_>>();
+        distances.sort();
+
+        // Only the best distance for each restaurant counts
+        let mut indices: Vec<(usize, usize)> = vec![];
+        for (distance, index) in distances {
+            if !indices.iter().any(|x| x.1 == index) {
+                indices.push((distance, index));
+            }
+        }
+
+        Ok(match indices.len() {
+            0 => RestaurantMatch::NoMatch,
+            1 => RestaurantMatch::Suggestion(restaurants.swap_remove(indices[0].1)),
+            _ if indices[1].0 - indices[0].0 >= MIN_LEAD => {
+                RestaurantMatch::Suggestion(restaurants.swap_remove(indices[0].1))
+            }
+            _ => {
+                let best = indices[0].0;
+                let mut close = indices
+                    .iter()
+                    .filter(|x| x.0 - best < MIN_LEAD)
+                    .map(|x| x.1)
+                    .collect::<Vec<_>>();
+                close.sort();
+                RestaurantMatch::Ambiguous(take(restaurants, &close))
+            }
+        })
+    }
+
+    pub fn restaurant_aliases(&self) -> Result<Vec<RestaurantAlias>, Error> {
+        use schema::restaurant_aliases::dsl::*;
+
+        Ok(restaurant_aliases
+            .order(alias.asc())
+            .load::<RestaurantAlias>(&self.db_connection)?)
+    }
+
+    /// Makes `new_alias` refer to the given restaurant, even if it
+    /// referred to another one before
+    pub fn add_restaurant_alias(
+        &self,
+        restaurant_id: RestaurantId,
+        new_alias: &str,
+    ) -> Result<(), Error> {
+        use schema::restaurant_aliases;
+
+        #[derive(Insertable)]
+        #[table_name = "restaurant_aliases"]
+        struct NewAlias<'a> {
+            alias: &'a str,
+            restaurant: i32,
+        }
+
+        let new_alias = NewAlias {
+            alias: new_alias,
+            restaurant: i32::from(restaurant_id),
+        };
+
+        diesel::insert_or_replace(&new_alias)
+            .into(restaurant_aliases::table)
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    /// Returns whether there was such an alias
+    pub fn remove_restaurant_alias(&self, old_alias: &str) -> Result<bool, Error> {
+        use schema::restaurant_aliases::dsl::*;
+
+        let deleted = diesel::delete(restaurant_aliases.filter(alias.eq(old_alias)))
+            .execute(&self.db_connection)?;
+
+        Ok(deleted > 0)
+    }
+
+    pub fn rename_restaurant(&self, restaurant_id: RestaurantId, new_name: &str) -> Result<(), Error> {
+        use schema::restaurants::dsl::*;
+
+        // Only a change of case may reuse the name, as names are unique
+        // regardless of case
+        if let Some(existing) = self.any_restaurant_by_name(new_name)? {
+            if existing.id != restaurant_id {
+                return Err(Error::NameTaken(existing.name));
+            }
+        }
+
+        diesel::update(restaurants.find(i32::from(restaurant_id)))
+            .set(name.eq(new_name))
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    pub fn set_restaurant_archived(
+        &self,
+        restaurant_id: RestaurantId,
+        new_archived: bool,
+    ) -> Result<(), Error> {
+        use schema::restaurants::dsl::*;
+
+        diesel::update(restaurants.find(i32::from(restaurant_id)))
+            .set(archived.eq(new_archived))
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    pub fn set_restaurant_details(
+        &self,
+        restaurant_id: RestaurantId,
+        new_website: Option<&str>,
+        new_address: Option<&str>,
+        new_cuisine: Option<&str>,
+        new_notes: Option<&str>,
+    ) -> Result<(), Error> {
+        use schema::restaurants::dsl::*;
+
+        diesel::update(restaurants.find(i32::from(restaurant_id)))
+            .set((
+                website.eq(new_website),
+                address.eq(new_address),
+                cuisine.eq(new_cuisine),
+                notes.eq(new_notes),
+            ))
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    pub fn set_restaurant_currency(
+        &self,
+        restaurant_id: RestaurantId,
+        new_currency: Option<&str>,
+    ) -> Result<(), Error> {
+        use schema::restaurants::dsl::*;
+
+        diesel::update(restaurants.find(i32::from(restaurant_id)))
+            .set(currency.eq(new_currency))
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    pub fn set_restaurant_limits(
+        &self,
+        restaurant_id: RestaurantId,
+        minimum: Option<i32>,
+        budget: Option<i32>,
+    ) -> Result<(), Error> {
+        use schema::restaurants::dsl::*;
+
+        diesel::update(restaurants.find(i32::from(restaurant_id)))
+            .set((
+                minimum_order_in_cents.eq(minimum),
+                budget_per_person_in_cents.eq(budget),
+            ))
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    pub fn item_aliases(&self, restaurant_id: RestaurantId) -> Result<Vec<ItemAlias>, Error> {
+        use schema::item_aliases::dsl::*;
+
+        Ok(item_aliases
+            .filter(restaurant.eq(i32::from(restaurant_id)))
+            .order(alias.asc())
+            .load::<ItemAlias>(&self.db_connection)?)
+    }
+
+    /// Makes `new_alias` refer to the menu item with the given number, even
+    /// if it referred to another one before
+    pub fn add_item_alias(
+        &self,
+        restaurant_id: RestaurantId,
+        new_alias: &str,
+        item_number: i32,
+    ) -> Result<(), Error> {
+        use schema::item_aliases;
+
+        #[derive(Insertable)]
+        #[table_name = "item_aliases"]
+        struct NewItemAlias<'a> {
+            restaurant: i32,
+            alias: &'a str,
+            number: i32,
+        }
+
+        let new_item_alias = NewItemAlias {
+            restaurant: i32::from(restaurant_id),
+            alias: new_alias,
+            number: item_number,
+        };
+
+        diesel::insert_or_replace(&new_item_alias)
+            .into(item_aliases::table)
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    /// Returns whether the restaurant had such an alias
+    pub fn remove_item_alias(
+        &self,
+        restaurant_id: RestaurantId,
+        old_alias: &str,
+    ) -> Result<bool, Error> {
+        use schema::item_aliases::dsl::*;
+
+        let deleted = diesel::delete(
+            item_aliases
+                .filter(restaurant.eq(i32::from(restaurant_id)))
+                .filter(alias.eq(old_alias)),
+        ).execute(&self.db_connection)?;
+
+        Ok(deleted > 0)
+    }
+
+    /// The favorites of the person at the restaurant, ordered by name
+    pub fn favorites(
+        &self,
+        query_name: &str,
+        restaurant_id: RestaurantId,
+    ) -> Result<Vec<Favorite>, Error> {
+        use schema::favorites::dsl::*;
+
+        Ok(favorites
+            .filter(person_name.eq(query_name))
+            .filter(restaurant.eq(i32::from(restaurant_id)))
+            .order((name.asc(), id.asc()))
+            .load::<Favorite>(&self.db_connection)?)
+    }
+
+    /// Everyone's favorites everywhere, ordered by person, restaurant and
+    /// name
+    pub fn all_favorites(&self) -> Result<Vec<Favorite>, Error> {
+        use schema::favorites::dsl::*;
+
+        Ok(favorites
+            .order((person_name.asc(), restaurant.asc(), name.asc(), id.asc()))
+            .load::<Favorite>(&self.db_connection)?)
+    }
+
+    /// Saves the menu numbers, with notes, as the favorite with the given
+    /// name, replacing any earlier favorite by that name
+    pub fn save_favorite(
+        &self,
+        new_person_name: &str,
+        restaurant_id: RestaurantId,
+        new_name: &str,
+        items: &[(i32, Option<&str>)],
+    ) -> Result<(), Error> {
+        use schema::favorites;
+
+        #[derive(Insertable)]
+        #[table_name = "favorites"]
+        struct NewFavorite<'a> {
+            person_name: &'a str,
+            restaurant: i32,
+            name: &'a str,
+            number: i32,
+            note: Option<&'a str>,
+        }
+
+        self.db_connection.transaction(|| {
+            self.remove_favorite(new_person_name, restaurant_id, new_name)?;
+
+            for &(number, note) in items {
+                let new_favorite = NewFavorite {
+                    person_name: new_person_name,
+                    restaurant: i32::from(restaurant_id),
+                    name: new_name,
+                    number,
+                    note,
+                };
+
+                diesel::insert(&new_favorite)
+                    .into(favorites::table)
+                    .execute(&self.db_connection)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Returns whether the person had such a favorite
+    pub fn remove_favorite(
+        &self,
+        query_name: &str,
+        restaurant_id: RestaurantId,
+        favorite_name: &str,
+    ) -> Result<bool, Error> {
+        use schema::favorites::dsl::*;
+
+        let deleted = diesel::delete(
+            favorites
+                .filter(person_name.eq(query_name))
+                .filter(restaurant.eq(i32::from(restaurant_id)))
+                .filter(name.eq(favorite_name)),
+        ).execute(&self.db_connection)?;
+
+        Ok(deleted > 0)
+    }
+
+    pub fn phone_numbers(&self, restaurant_id: RestaurantId) -> Result<Vec<PhoneNumber>, Error> {
+        use schema::phone_numbers::dsl::*;
+
+        Ok(phone_numbers
+            .filter(restaurant.eq(i32::from(restaurant_id)))
+            .order(number.asc())
+            .load::<PhoneNumber>(&self.db_connection)?)
+    }
+
+    pub fn add_phone_number(&self, restaurant_id: RestaurantId, number: &str) -> Result<(), Error> {
+        use schema::phone_numbers;
+
+        #[derive(Insertable)]
+        #[table_name = "phone_numbers"]
+        struct NewPhoneNumber<'a> {
+            restaurant: i32,
+            number: &'a str,
+        }
+
+        let new_phone_number = NewPhoneNumber {
+            restaurant: i32::from(restaurant_id),
+            number: number,
+        };
+
+        diesel::insert_or_replace(&new_phone_number)
+            .into(phone_numbers::table)
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    /// Returns whether the restaurant had the given number
+    pub fn remove_phone_number(
+        &self,
+        restaurant_id: RestaurantId,
+        number: &str,
+    ) -> Result<bool, Error> {
+        use schema::phone_numbers;
+
+        let deleted = diesel::delete(
+            phone_numbers::table
+                .filter(phone_numbers::restaurant.eq(i32::from(restaurant_id)))
+                .filter(phone_numbers::number.eq(number)),
+        )
+        .execute(&self.db_connection)?;
+
+        Ok(deleted > 0)
+    }
+
+    pub fn email_addresses(&self, restaurant_id: RestaurantId) -> Result<Vec<EmailAddress>, Error> {
+        use schema::email_addresses::dsl::*;
+
+        Ok(email_addresses
+            .filter(restaurant.eq(i32::from(restaurant_id)))
+            .order(email_address.asc())
+            .load::<EmailAddress>(&self.db_connection)?)
+    }
+
+    pub fn add_email_address(
+        &self,
+        restaurant_id: RestaurantId,
+        email_address: &str,
+    ) -> Result<(), Error> {
+        use schema::email_addresses;
+
+        #[derive(Insertable)]
+        #[table_name = "email_addresses"]
+        struct NewEmailAddress<'a> {
+            restaurant: i32,
+            email_address: &'a str,
+        }
+
+        let new_email_address = NewEmailAddress {
+            restaurant: i32::from(restaurant_id),
+            email_address: email_address,
+        };
+
+        diesel::insert_or_replace(&new_email_address)
+            .into(email_addresses::table)
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    /// Returns whether the restaurant had the given address
+    pub fn remove_email_address(
+        &self,
+        restaurant_id: RestaurantId,
+        email_address: &str,
+    ) -> Result<bool, Error> {
+        use schema::email_addresses;
+
+        let deleted = diesel::delete(
+            email_addresses::table
+                .filter(email_addresses::restaurant.eq(i32::from(restaurant_id)))
+                .filter(email_addresses::email_address.eq(email_address)),
+        )
+        .execute(&self.db_connection)?;
+
+        Ok(deleted > 0)
+    }
+
     pub fn menus_for_restaurant(&self, restaurant_id: RestaurantId) -> Result<Vec<Menu>, Error> {
         use schema::menus::dsl::*;
 
@@ -120,7 +676,7 @@ impl State {
 
         Ok(menus
             .filter(restaurant.eq(i32::from(restaurant_id)))
-            .order(imported.desc())
+            .order((imported.desc(), id.desc()))
             .limit(1)
             .load::<Menu>(&self.db_connection)?
             .pop()
@@ -154,6 +710,15 @@ impl State {
         Ok(())
     }
 
+    pub fn order(&self, order_id: OrderId) -> Result<Option<Order>, Error> {
+        use schema::orders::dsl::*;
+
+        Ok(orders
+            .find(i32::from(order_id))
+            .load::<Order>(&self.db_connection)?
+            .pop())
+    }
+
     pub fn current_open_order(&self) -> Result<Option<Order>, Error> {
         use schema::orders::dsl::*;
 
@@ -168,7 +733,12 @@ impl State {
         self.current_open_order()?.ok_or(Error::NoOpenOrder)
     }
 
-    pub fn create_order(&self, menu_id: MenuId) -> Result<(), Error> {
+    pub fn create_order(
+        &self,
+        menu_id: MenuId,
+        minimum_order_in_cents: Option<i32>,
+        budget_per_person_in_cents: Option<i32>,
+    ) -> Result<(), Error> {
         use schema::orders;
 
         #[derive(Insertable)]
@@ -177,6 +747,8 @@ impl State {
             pub menu: i32,
             pub overhead_in_cents: i32,
             pub opened: i32,
+            pub minimum_order_in_cents: Option<i32>,
+            pub budget_per_person_in_cents: Option<i32>,
         }
 
         self.db_connection.transaction(|| {
@@ -188,6 +760,8 @@ impl State {
                 menu: i32::from(menu_id),
                 overhead_in_cents: 0,
                 opened: timestamp(),
+                minimum_order_in_cents: minimum_order_in_cents,
+                budget_per_person_in_cents: budget_per_person_in_cents,
             };
 
             diesel::insert(&new_order)
@@ -219,6 +793,180 @@ impl State {
         Ok(())
     }
 
+    pub fn closed_orders(&self, count: i64) -> Result<Vec<Order>, Error> {
+        use schema::orders::dsl::*;
+
+        Ok(orders
+            .filter(closed.is_not_null())
+            .order(closed.desc())
+            .limit(count)
+            .load::<Order>(&self.db_connection)?)
+    }
+
+    pub fn current_open_poll(&self) -> Result<Option<Poll>, Error> {
+        use schema::polls::dsl::*;
+
+        Ok(polls
+            .filter(closed.is_null())
+            .limit(1)
+            .load::<Poll>(&self.db_connection)?
+            .pop())
+    }
+
+    pub fn demand_open_poll(&self) -> Result<Poll, Error> {
+        self.current_open_poll()?.ok_or(Error::NoOpenPoll)
+    }
+
+    /// Opens a poll between the given restaurants
+    pub fn create_poll(
+        &self,
+        new_started_by: &str,
+        restaurant_ids: &[RestaurantId],
+    ) -> Result<PollId, Error> {
+        use schema::{poll_options, polls};
+
+        #[derive(Insertable)]
+        #[table_name = "polls"]
+        struct NewPoll<'a> {
+            started_by: &'a str,
+            opened: i32,
+        }
+
+        #[derive(Insertable)]
+        #[table_name = "poll_options"]
+        struct NewPollOption {
+            poll: i32,
+            restaurant: i32,
+        }
+
+        self.db_connection.transaction(|| {
+            if let Some(current) = self.current_open_poll()? {
+                return Err(Error::PollAlreadyOpen(current));
+            }
+
+            let new_poll = NewPoll {
+                started_by: new_started_by,
+                opened: timestamp(),
+            };
+
+            diesel::insert(&new_poll)
+                .into(polls::table)
+                .execute(&self.db_connection)?;
+
+            let poll = self.demand_open_poll()?;
+
+            for &restaurant_id in restaurant_ids {
+                let new_option = NewPollOption {
+                    poll: i32::from(poll.id),
+                    restaurant: i32::from(restaurant_id),
+                };
+
+                diesel::insert_or_replace(&new_option)
+                    .into(poll_options::table)
+                    .execute(&self.db_connection)?;
+            }
+
+            Ok(poll.id)
+        })
+    }
+
+    /// The restaurants to choose between in the poll, ordered by name
+    pub fn poll_restaurants(&self, poll_id: PollId) -> Result<Vec<Restaurant>, Error> {
+        use schema::poll_options::dsl::*;
+
+        let options = poll_options
+            .filter(poll.eq(i32::from(poll_id)))
+            .load::<PollOption>(&self.db_connection)?;
+
+        let mut restaurants = Vec::new();
+        for option in options {
+            restaurants.push(self.restaurant(option.restaurant)?.ok_or(Error::NotFound)?);
+        }
+        restaurants.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+
+        Ok(restaurants)
+    }
+
+    /// Records the vote of the person, replacing any earlier vote of theirs
+    /// in the same poll
+    pub fn vote(
+        &self,
+        poll_id: PollId,
+        new_person_name: &str,
+        restaurant_id: RestaurantId,
+    ) -> Result<(), Error> {
+        use schema::poll_votes;
+
+        #[derive(Insertable)]
+        #[table_name = "poll_votes"]
+        struct NewPollVote<'a> {
+            poll: i32,
+            person_name: &'a str,
+            restaurant: i32,
+        }
+
+        let new_vote = NewPollVote {
+            poll: i32::from(poll_id),
+            person_name: new_person_name,
+            restaurant: i32::from(restaurant_id),
+        };
+
+        diesel::insert_or_replace(&new_vote)
+            .into(poll_votes::table)
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    /// The votes in the poll, ordered by person
+    pub fn poll_votes(&self, poll_id: PollId) -> Result<Vec<PollVote>, Error> {
+        use schema::poll_votes::dsl::*;
+
+        Ok(poll_votes
+            .filter(poll.eq(i32::from(poll_id)))
+            .order(person_name.asc())
+            .load::<PollVote>(&self.db_connection)?)
+    }
+
+    pub fn close_current_poll(&self) -> Result<Poll, Error> {
+        use schema::polls::dsl::*;
+
+        self.db_connection.transaction(|| {
+            let current = self.demand_open_poll()?;
+
+            diesel::update(polls.find(i32::from(current.id)))
+                .set(closed.eq(timestamp()))
+                .execute(&self.db_connection)?;
+
+            Ok(current)
+        })
+    }
+
+    pub fn set_sharebill_post(
+        &self,
+        order_id: OrderId,
+        post_id: &str,
+        payload: &str,
+    ) -> Result<(), Error> {
+        use schema::orders::dsl::*;
+
+        diesel::update(orders.find(i32::from(order_id)))
+            .set((sharebill_post_id.eq(post_id), sharebill_payload.eq(payload)))
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    pub fn set_billed_post_url(&self, order_id: OrderId, url: &str) -> Result<(), Error> {
+        use schema::orders::dsl::*;
+
+        diesel::update(orders.find(i32::from(order_id)))
+            .set(billed_post_url.eq(url))
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
     pub fn set_overhead(&self, order_id: OrderId, new_overhead_in_cents: i32) -> Result<(), Error> {
         use schema::orders::dsl::*;
 
@@ -229,7 +977,58 @@ impl State {
         Ok(())
     }
 
-    pub fn query_menu(&self, menu_id: MenuId, query: &Query) -> Result<Vec<MenuItem>, Error> {
+    pub fn set_order_limits(
+        &self,
+        order_id: OrderId,
+        minimum: Option<i32>,
+        budget: Option<i32>,
+    ) -> Result<(), Error> {
+        use schema::orders::dsl::*;
+
+        diesel::update(orders.find(i32::from(order_id)))
+            .set((
+                minimum_order_in_cents.eq(minimum),
+                budget_per_person_in_cents.eq(budget),
+            ))
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    pub fn set_order_sent(&self, order_id: OrderId) -> Result<(), Error> {
+        use schema::orders::dsl::*;
+
+        diesel::update(orders.find(i32::from(order_id)))
+            .set(sent.eq(Some(timestamp())))
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    /// Puts back when the order was sent before, if at all, after a
+    /// failed attempt to send it
+    pub fn reset_order_sent(&self, order_id: OrderId, previous: Option<i32>) -> Result<(), Error> {
+        use schema::orders::dsl::*;
+
+        diesel::update(orders.find(i32::from(order_id)))
+            .set(sent.eq(previous))
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    pub fn set_exchange_rate(&self, order_id: OrderId, rate: Option<&Rational>) -> Result<(), Error> {
+        use schema::orders::dsl::*;
+
+        diesel::update(orders.find(i32::from(order_id)))
+            .set(exchange_rate.eq(rate.map(|x| x.to_string())))
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    /// Returns the matching items, best first
+    pub fn query_menu(&self, menu_id: MenuId, query: &Query) -> Result<Vec<search::Match>, Error> {
         use schema::menu_items::dsl::*;
 
         let all_items = menu_items.filter(menu.eq(i32::from(menu_id)));
@@ -238,12 +1037,17 @@ impl State {
             Query::ExactInteger(integer) => Ok(all_items
                 .filter(number.eq(integer))
                 .limit(1)
-                .load::<MenuItem>(&self.db_connection)?),
-            Query::FuzzyString(string) => {
-                let mut items = all_items.load::<MenuItem>(&self.db_connection)?;
-                items.sort_by_key(|x| distance(&string, &x.name));
-                Ok(items)
-            }
+                .load::<MenuItem>(&self.db_connection)?
+                .into_iter()
+                .map(|menu_item| search::Match {
+                    score: 100,
+                    menu_item,
+                })
+                .collect()),
+            Query::FuzzyString(string) => Ok(search::rank(
+                string,
+                all_items.load::<MenuItem>(&self.db_connection)?,
+            )),
         }
     }
 
@@ -252,7 +1056,8 @@ impl State {
         order: OrderId,
         person_name: &str,
         menu_item: MenuItemId,
-    ) -> Result<(), Error> {
+        note: Option<&str>,
+    ) -> Result<OrderItemId, Error> {
         use schema::order_items;
 
         #[derive(Insertable)]
@@ -261,18 +1066,28 @@ impl State {
             pub order: i32,
             pub person_name: &'a str,
             pub menu_item: i32,
+            pub note: Option<&'a str>,
         }
 
         let new_order_item = NewOrderItem {
             order: i32::from(order),
             person_name: person_name,
             menu_item: i32::from(menu_item),
+            note: note,
         };
 
         diesel::insert(&new_order_item)
             .into(order_items::table)
             .execute(&self.db_connection)?;
 
+        Ok(OrderItemId::from(self.last_insert_rowid()?))
+    }
+
+    pub fn remove_order_item(&self, order_item_id: OrderItemId) -> Result<(), Error> {
+        use schema::order_items::dsl::*;
+
+        diesel::delete(order_items.find(i32::from(order_item_id))).execute(&self.db_connection)?;
+
         Ok(())
     }
 
@@ -289,6 +1104,72 @@ impl State {
         Ok(())
     }
 
+    /// Remembers the menu numbers `person_name` was asked to choose
+    /// between, replacing any earlier choice
+    pub fn set_pending_choice(
+        &self,
+        order: OrderId,
+        person_name: &str,
+        numbers: &[i32],
+        note: Option<&str>,
+        quantity: i32,
+    ) -> Result<(), Error> {
+        use schema::pending_choices;
+
+        #[derive(Insertable)]
+        #[table_name = "pending_choices"]
+        struct NewPendingChoice<'a> {
+            person_name: &'a str,
+            order: i32,
+            numbers: &'a str,
+            note: Option<&'a str>,
+            created: i32,
+            quantity: i32,
+        }
+
+        let numbers = numbers.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(",");
+
+        let new_pending_choice = NewPendingChoice {
+            person_name,
+            order: i32::from(order),
+            numbers: &numbers,
+            note,
+            created: timestamp(),
+            quantity,
+        };
+
+        diesel::insert_or_replace(&new_pending_choice)
+            .into(pending_choices::table)
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    /// The choice the person has yet to make, unless it was offered more
+    /// than `max_age` seconds ago
+    pub fn pending_choice(
+        &self,
+        query_name: &str,
+        max_age: i32,
+    ) -> Result<Option<PendingChoice>, Error> {
+        use schema::pending_choices::dsl::*;
+
+        Ok(pending_choices
+            .filter(person_name.eq(query_name))
+            .filter(created.ge(timestamp() - max_age))
+            .load::<PendingChoice>(&self.db_connection)?
+            .pop())
+    }
+
+    pub fn clear_pending_choice(&self, query_name: &str) -> Result<(), Error> {
+        use schema::pending_choices::dsl::*;
+
+        diesel::delete(pending_choices.filter(person_name.eq(query_name)))
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
     pub fn items_in_order(&self, order_id: OrderId) -> Result<Vec<(MenuItem, OrderItem)>, Error> {
         use schema::menu_items;
         use schema::order_items;
@@ -302,6 +1183,220 @@ impl State {
         Ok(result)
     }
 
+    /// Records how to undo a change `person_name` made to the order
+    pub fn record_undo(&self, order: OrderId, person_name: &str, undo: &Undo) -> Result<(), Error> {
+        use schema::journal_entries;
+
+        #[derive(Insertable)]
+        #[table_name = "journal_entries"]
+        struct NewJournalEntry<'a> {
+            person_name: &'a str,
+            order: i32,
+            created: i32,
+            undo: String,
+        }
+
+        let new_journal_entry = NewJournalEntry {
+            person_name,
+            order: i32::from(order),
+            created: timestamp(),
+            undo: serde_json::to_string(undo).expect("Serialization cannot fail"),
+        };
+
+        diesel::insert(&new_journal_entry)
+            .into(journal_entries::table)
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    /// The most recent change the person made that has not been undone
+    pub fn last_journal_entry(&self, query_name: &str) -> Result<Option<JournalEntry>, Error> {
+        use schema::journal_entries::dsl::*;
+
+        Ok(journal_entries
+            .filter(person_name.eq(query_name))
+            .order(id.desc())
+            .limit(1)
+            .load::<JournalEntry>(&self.db_connection)?
+            .pop())
+    }
+
+    /// Undoes the change and removes it from the journal
+    pub fn undo(&self, journal_entry: &JournalEntry) -> Result<Undo, Error> {
+        use schema::{journal_entries, orders};
+
+        let undo = journal_entry.undo();
+
+        self.db_connection.transaction(|| {
+            match undo {
+                Undo::RemoveOrderItems(ref items) => {
+                    use schema::order_items;
+
+                    for &(menu_item, ref note) in items {
+                        // The most recent match is the one that was just ordered
+                        let order_item = order_items::table
+                            .filter(order_items::order.eq(i32::from(journal_entry.order)))
+                            .filter(order_items::person_name.eq(&journal_entry.person_name))
+                            .filter(order_items::menu_item.eq(menu_item))
+                            .order(order_items::id.desc())
+                            .load::<OrderItem>(&self.db_connection)?
+                            .into_iter()
+                            .find(|x| x.note == *note);
+
+                        if let Some(order_item) = order_item {
+                            self.remove_order_item(order_item.id)?;
+                        }
+                    }
+                }
+                Undo::RestoreOrderItems(ref items) => {
+                    for &(menu_item, ref note) in items {
+                        self.add_order_item(
+                            journal_entry.order,
+                            &journal_entry.person_name,
+                            MenuItemId::from(menu_item),
+                            note.as_ref().map(|x| x.as_str()),
+                        )?;
+                    }
+                }
+                Undo::SetOverhead(overhead_in_cents) => {
+                    self.set_overhead(journal_entry.order, overhead_in_cents)?;
+                }
+                Undo::Reopen => {
+                    if let Some(current) = self.current_open_order()? {
+                        return Err(Error::OrderAlreadyOpen(current));
+                    }
+
+                    diesel::update(orders::table.find(i32::from(journal_entry.order)))
+                        .set(orders::closed.eq(None::<i32>))
+                        .execute(&self.db_connection)?;
+                }
+            }
+
+            diesel::delete(journal_entries::table.find(i32::from(journal_entry.id)))
+                .execute(&self.db_connection)?;
+
+            Ok(())
+        })?;
+
+        Ok(undo)
+    }
+
+    pub fn record_audit(&self, audit: &Audit) -> Result<(), Error> {
+        use schema::audit_log;
+
+        #[derive(Insertable)]
+        #[table_name = "audit_log"]
+        struct NewAuditEntry<'a> {
+            created: i32,
+            frontend: &'a str,
+            real_user: &'a str,
+            effective_user: &'a str,
+            order: Option<i32>,
+            command: &'a str,
+            args: &'a str,
+            outcome: &'a str,
+        }
+
+        let new_audit_entry = NewAuditEntry {
+            created: timestamp(),
+            frontend: audit.frontend.as_str(),
+            real_user: audit.real_user,
+            effective_user: audit.effective_user,
+            order: audit.order.map(i32::from),
+            command: audit.command,
+            args: audit.args,
+            outcome: audit.outcome,
+        };
+
+        diesel::insert(&new_audit_entry)
+            .into(audit_log::table)
+            .execute(&self.db_connection)?;
+
+        Ok(())
+    }
+
+    /// The most recent entries in the audit log, most recent first,
+    /// optionally only those for the given order
+    pub fn audit_log(
+        &self,
+        order_id: Option<OrderId>,
+        count: i64,
+    ) -> Result<Vec<AuditEntry>, Error> {
+        use schema::audit_log::dsl::*;
+
+        match order_id {
+            Some(order_id) => Ok(audit_log
+                .filter(order.eq(i32::from(order_id)))
+                .order(id.desc())
+                .limit(count)
+                .load::<AuditEntry>(&self.db_connection)?),
+            None => Ok(audit_log
+                .order(id.desc())
+                .limit(count)
+                .load::<AuditEntry>(&self.db_connection)?),
+        }
+    }
+
+    pub fn add_order_adjustment(
+        &self,
+        order: OrderId,
+        kind: AdjustmentKind,
+        amount: &Rational,
+        description: &str,
+    ) -> Result<OrderAdjustmentId, Error> {
+        use schema::order_adjustments;
+
+        #[derive(Insertable)]
+        #[table_name = "order_adjustments"]
+        struct NewAdjustment<'a> {
+            order: i32,
+            kind: &'a str,
+            amount: String,
+            description: &'a str,
+        }
+
+        let new_adjustment = NewAdjustment {
+            order: i32::from(order),
+            kind: kind.as_str(),
+            amount: format!("{}", amount),
+            description: description,
+        };
+
+        diesel::insert(&new_adjustment)
+            .into(order_adjustments::table)
+            .execute(&self.db_connection)?;
+
+        Ok(OrderAdjustmentId::from(self.last_insert_rowid()?))
+    }
+
+    pub fn order_adjustments(&self, order_id: OrderId) -> Result<Vec<OrderAdjustment>, Error> {
+        use schema::order_adjustments;
+
+        Ok(order_adjustments::table
+            .filter(order_adjustments::order.eq(i32::from(order_id)))
+            .order(order_adjustments::id.asc())
+            .load::<OrderAdjustment>(&self.db_connection)?)
+    }
+
+    /// Returns whether there was such an adjustment on the order
+    pub fn remove_order_adjustment(
+        &self,
+        order_id: OrderId,
+        adjustment_id: OrderAdjustmentId,
+    ) -> Result<bool, Error> {
+        use schema::order_adjustments;
+
+        let deleted = diesel::delete(
+            order_adjustments::table
+                .filter(order_adjustments::id.eq(i32::from(adjustment_id)))
+                .filter(order_adjustments::order.eq(i32::from(order_id))),
+        )
+        .execute(&self.db_connection)?;
+
+        Ok(deleted > 0)
+    }
+
     pub fn set_association(&self, slack_name: &str, sharebill_account: &str) -> Result<(), Error> {
         use schema::sharebill_associations;
 
@@ -332,35 +1427,107 @@ impl State {
             .load::<SharebillAssociation>(&self.db_connection)?)
     }
 
+    /// The closed orders from the restaurant the person ordered something
+    /// in, most recent first, along with what the person ordered
     pub fn previous_orders(
         &self,
         slack_name: &str,
         restaurant: RestaurantId,
-    ) -> Result<Vec<MenuItem>, Error> {
+        count: i64,
+    ) -> Result<Vec<(Order, Vec<(MenuItem, OrderItem)>)>, Error> {
         use diesel::expression::sql_literal::sql;
         use diesel::types::*;
 
+        // Orders that are still open have no closing time, and are left out
         const SQL: &str = r"
-            SELECT menu_items.*
+            SELECT DISTINCT orders.id
             FROM order_items
-                INNER JOIN menu_items ON order_items.menu_item=menu_items.id
-                WHERE person_name=?
-                AND order_items.'order' = (
-                    SELECT order_items.'order'
-                    FROM order_items
-                    INNER JOIN orders ON order_items.'order'='orders'.id
-                    INNER JOIN menus ON menus.id=orders.menu
-                    WHERE order_items.person_name=?
-                    AND menus.restaurant=?
-                    ORDER BY orders.closed DESC
-                    LIMIT 1
-                )
+                INNER JOIN orders ON order_items.'order'='orders'.id
+                INNER JOIN menus ON menus.id=orders.menu
+                WHERE order_items.person_name=?
+                AND menus.restaurant=?
+                AND orders.closed IS NOT NULL
+                ORDER BY orders.closed DESC, orders.id DESC
+                LIMIT ?
             ;";
 
-        Ok(sql::<(Integer, Integer, Integer, Text, Integer)>(SQL)
-            .bind::<Text, _>(slack_name)
+        let order_ids = sql::<Integer>(SQL)
             .bind::<Text, _>(slack_name)
             .bind::<Integer, _>(i32::from(restaurant))
-            .load::<MenuItem>(&self.db_connection)?)
+            .bind::<BigInt, _>(count)
+            .load::<i32>(&self.db_connection)?;
+
+        let mut previous_orders = vec![];
+        for order_id in order_ids {
+            let order = self.order(OrderId::from(order_id))?.ok_or(Error::NotFound)?;
+            let items = self
+                .items_in_order(order.id)?
+                .into_iter()
+                .filter(|x| x.1.person_name.to_lowercase() == slack_name.to_lowercase())
+                .collect();
+            previous_orders.push((order, items));
+        }
+
+        Ok(previous_orders)
+    }
+
+    pub fn record_ledger_transaction(
+        &self,
+        order: Option<OrderId>,
+        description: &str,
+        created: i32,
+        entries: &[(String, Rational)],
+    ) -> Result<LedgerTransactionId, Error> {
+        use schema::{ledger_entries, ledger_transactions};
+
+        #[derive(Insertable)]
+        #[table_name = "ledger_transactions"]
+        struct NewTransaction<'a> {
+            order: Option<i32>,
+            description: &'a str,
+            created: i32,
+        }
+
+        #[derive(Insertable)]
+        #[table_name = "ledger_entries"]
+        struct NewEntry<'a> {
+            transaction: i32,
+            account: &'a str,
+            amount: String,
+        }
+
+        self.db_connection.transaction(|| {
+            let new_transaction = NewTransaction {
+                order: order.map(i32::from),
+                description: description,
+                created: created,
+            };
+
+            diesel::insert(&new_transaction)
+                .into(ledger_transactions::table)
+                .execute(&self.db_connection)?;
+
+            let transaction_id = self.last_insert_rowid()?;
+
+            for &(ref account, ref amount) in entries {
+                let new_entry = NewEntry {
+                    transaction: transaction_id,
+                    account: account,
+                    amount: format!("{}", amount),
+                };
+
+                diesel::insert(&new_entry)
+                    .into(ledger_entries::table)
+                    .execute(&self.db_connection)?;
+            }
+
+            Ok(LedgerTransactionId::from(transaction_id))
+        })
+    }
+
+    pub fn ledger_entries(&self) -> Result<Vec<LedgerEntry>, Error> {
+        use schema::ledger_entries::dsl::*;
+
+        Ok(ledger_entries.load::<LedgerEntry>(&self.db_connection)?)
     }
 }
@@ -4,16 +4,24 @@ extern crate router;
 extern crate serde_json;
 extern crate urlencoded;
 
-use models::{self, MenuId, RestaurantId};
+use config;
+use currency::Currency;
+use mail;
+use models::{self, Frontend, MenuId, OrderId, RestaurantId};
+use ordersheet;
+use sharebill::client::Auth;
+use sharebill::Rational;
 use slack;
 use state;
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::sync::{Arc, Mutex};
+use time;
 
 use self::iron::prelude::*;
 use self::iron::{status, typemap, BeforeMiddleware};
 use self::router::Router;
-use self::urlencoded::UrlEncodedBody;
+use self::urlencoded::{UrlEncodedBody, UrlEncodedQuery};
 
 quick_error! {
     #[derive(Debug)]
@@ -65,7 +73,14 @@ impl BeforeMiddleware for StateContainer {
 pub struct Env {
     pub base_url: String,
     pub maybe_sharebill_url: Option<String>,
-    pub sharebill_cookies: Vec<String>,
+    pub sharebill_auth: Auth,
+    pub settlement: Option<config::SettlementConfig>,
+    pub currency: Currency,
+    pub exchange_rates: BTreeMap<String, Rational>,
+    pub enforce_budget: bool,
+    pub email: Option<config::EmailConfig>,
+    /// `email.smtp_password`, resolved at startup
+    pub smtp_password: Option<String>,
 }
 
 #[derive(Clone)]
@@ -82,6 +97,26 @@ impl BeforeMiddleware for EnvContainer {
     }
 }
 
+/// Records a change made through the web pages in the audit log. The
+/// pages have no users, so the client address stands in for one. The
+/// change has been made by now, so failing to record it is only logged
+fn audit(state: &state::State, req: &Request, command: &str, args: &str) {
+    let client = req.remote_addr.ip().to_string();
+
+    let result = state.record_audit(&state::Audit {
+        frontend: Frontend::Web,
+        real_user: &client,
+        effective_user: &client,
+        order: None,
+        command,
+        args,
+        outcome: "ok",
+    });
+    if let Err(err) = result {
+        eprintln!("Unable to record {:?} in the audit log: {:?}", command, err);
+    }
+}
+
 fn index(req: &mut Request) -> IronResult<Response> {
     let state = req
         .extensions
@@ -95,12 +130,14 @@ fn index(req: &mut Request) -> IronResult<Response> {
     #[template = "templates/index.html"]
     struct Index {
         restaurants: Vec<models::Restaurant>,
+        archived_restaurants: Vec<models::Restaurant>,
     }
 
     Ok(Response::with((
         status::Ok,
         Layout::new(&Index {
             restaurants: state.restaurants()?,
+            archived_restaurants: state.archived_restaurants()?,
         }),
     )))
 }
@@ -132,6 +169,7 @@ fn create_restaurant(req: &mut Request) -> IronResult<Response> {
         Ok(id) => id,
         Err(_) => return Ok(Response::with((status::InternalServerError, "Error!"))),
     };
+    audit(&state, req, "create_restaurant", name);
 
     let created_url = format!("{}restaurant/{}", &env.base_url, i32::from(id));
 
@@ -165,18 +203,188 @@ fn restaurant(req: &mut Request) -> IronResult<Response> {
     #[template = "templates/restaurant.html"]
     struct Restaurant {
         restaurant: models::Restaurant,
+        website: String,
+        address: String,
+        cuisine: String,
+        notes: String,
+        archive_label: &'static str,
+        archive_value: &'static str,
+        phone_numbers: Vec<models::PhoneNumber>,
+        email_addresses: Vec<models::EmailAddress>,
         menus: Vec<models::Menu>,
     }
 
+    let restaurant = match state.restaurant(restaurant_id)? {
+        Some(restaurant) => restaurant,
+        None => return Ok(Response::with(status::NotFound)),
+    };
+
+    let (archive_label, archive_value) = if restaurant.archived {
+        ("Restore", "false")
+    } else {
+        ("Archive", "true")
+    };
+
     Ok(Response::with((
         status::Ok,
         Layout::new(&Restaurant {
-            restaurant: state.restaurant(restaurant_id)?.unwrap(),
+            website: restaurant.website.clone().unwrap_or_default(),
+            address: restaurant.address.clone().unwrap_or_default(),
+            cuisine: restaurant.cuisine.clone().unwrap_or_default(),
+            notes: restaurant.notes.clone().unwrap_or_default(),
+            archive_label,
+            archive_value,
+            restaurant,
+            phone_numbers: state.phone_numbers(restaurant_id)?,
+            email_addresses: state.email_addresses(restaurant_id)?,
             menus: state.menus_for_restaurant(restaurant_id)?,
         }),
     )))
 }
 
+fn update_restaurant(req: &mut Request) -> IronResult<Response> {
+    use self::iron::headers::Location;
+    use self::iron::modifiers::Header;
+
+    let restaurant_id: RestaurantId = req
+        .extensions
+        .get::<Router>()
+        .unwrap()
+        .find("id")
+        .unwrap()
+        .parse::<i32>()
+        .unwrap()
+        .into();
+
+    let hashmap = req.get::<UrlEncodedBody>().unwrap();
+    let field = |key: &str| {
+        hashmap
+            .get(key)
+            .and_then(|x| x.get(0))
+            .map(|x| x.trim().to_owned())
+            .filter(|x| x.len() > 0)
+    };
+
+    let state = req
+        .extensions
+        .get::<StateContainer>()
+        .unwrap()
+        .0
+        .lock()
+        .unwrap();
+
+    // The archive button posts only the archived field, the details form
+    // everything else
+    if let Some(archived) = field("archived") {
+        state.set_restaurant_archived(restaurant_id, archived == "true")?;
+        audit(&state, req, "archive_restaurant", &format!("{} {}", restaurant_id, archived));
+    } else {
+        let name = match field("name") {
+            Some(name) => name,
+            None => return Ok(Response::with(status::BadRequest)),
+        };
+
+        match state.rename_restaurant(restaurant_id, &name) {
+            Err(state::Error::NameTaken(name)) => {
+                return Ok(Response::with((
+                    status::Conflict,
+                    format!("There is already a restaurant called {}", name),
+                )))
+            }
+            x => x?,
+        }
+
+        let (website, address, cuisine, notes) =
+            (field("website"), field("address"), field("cuisine"), field("notes"));
+        state.set_restaurant_details(
+            restaurant_id,
+            website.as_ref().map(String::as_ref),
+            address.as_ref().map(String::as_ref),
+            cuisine.as_ref().map(String::as_ref),
+            notes.as_ref().map(String::as_ref),
+        )?;
+        audit(&state, req, "update_restaurant", &format!("{} {}", restaurant_id, name));
+    }
+
+    let ref env = req.extensions.get::<EnvContainer>().unwrap().0;
+    let restaurant_url = format!("{}restaurant/{}", &env.base_url, i32::from(restaurant_id));
+
+    Ok(Response::with((
+        status::SeeOther,
+        Header(Location(restaurant_url)),
+    )))
+}
+
+fn update_contacts(req: &mut Request) -> IronResult<Response> {
+    use self::iron::headers::Location;
+    use self::iron::modifiers::Header;
+
+    let restaurant_id: RestaurantId = req
+        .extensions
+        .get::<Router>()
+        .unwrap()
+        .find("id")
+        .unwrap()
+        .parse::<i32>()
+        .unwrap()
+        .into();
+
+    let hashmap = req.get::<UrlEncodedBody>().unwrap();
+    let field = |key: &str| {
+        hashmap
+            .get(key)
+            .and_then(|x| x.get(0))
+            .map(|x| x.trim().to_owned())
+            .filter(|x| x.len() > 0)
+    };
+
+    let state = req
+        .extensions
+        .get::<StateContainer>()
+        .unwrap()
+        .0
+        .lock()
+        .unwrap();
+
+    let action = field("action");
+    let contact = field("phone_number").or_else(|| field("email_address"));
+
+    match (
+        action.as_ref().map(String::as_ref),
+        field("phone_number"),
+        field("email_address"),
+    ) {
+        (Some("add"), Some(number), None) => state.add_phone_number(restaurant_id, &number)?,
+        (Some("add"), None, Some(address)) => {
+            if !mail::is_valid_address(&address) {
+                return Ok(Response::with(status::BadRequest));
+            }
+            state.add_email_address(restaurant_id, &address)?;
+        }
+        (Some("remove"), Some(number), None) => {
+            state.remove_phone_number(restaurant_id, &number)?;
+        }
+        (Some("remove"), None, Some(address)) => {
+            state.remove_email_address(restaurant_id, &address)?;
+        }
+        _ => return Ok(Response::with(status::BadRequest)),
+    }
+    audit(
+        &state,
+        req,
+        &format!("{}_contact", action.unwrap_or_default()),
+        &format!("{} {}", restaurant_id, contact.unwrap_or_default()),
+    );
+
+    let ref env = req.extensions.get::<EnvContainer>().unwrap().0;
+    let restaurant_url = format!("{}restaurant/{}", &env.base_url, i32::from(restaurant_id));
+
+    Ok(Response::with((
+        status::SeeOther,
+        Header(Location(restaurant_url)),
+    )))
+}
+
 fn ingest(req: &mut Request) -> IronResult<Response> {
     let restaurant_id: RestaurantId = req
         .extensions
@@ -203,6 +411,7 @@ fn ingest(req: &mut Request) -> IronResult<Response> {
                 .lock()
                 .unwrap();
             state.ingest_menu(restaurant_id, &new_menu)?;
+            audit(&state, req, "ingest", &restaurant_id.to_string());
 
             Ok(Response::with(status::Ok))
         }
@@ -231,34 +440,311 @@ fn menu(req: &mut Request) -> IronResult<Response> {
         .unwrap()
         .into();
 
+    let ref env = req.extensions.get::<EnvContainer>().unwrap().0;
+    let currency_code = match state.menu_object(menu_id)? {
+        Some(menu) => state.restaurant(menu.restaurant)?.and_then(|x| x.currency),
+        None => None,
+    };
+    let currency = currency_code
+        .and_then(|code| Currency::from_code(&code))
+        .unwrap_or_else(|| env.currency.clone());
+
+    struct MenuEntry {
+        number: i32,
+        name: String,
+        price: String,
+    }
+
     #[derive(BartDisplay)]
     #[template = "templates/menu.html"]
     struct Menu {
-        menu: Vec<models::MenuItem>,
+        menu: Vec<MenuEntry>,
     }
 
     Ok(Response::with((
         status::Ok,
         Layout::new(&Menu {
-            menu: state.menu(menu_id)?,
+            menu: state
+                .menu(menu_id)?
+                .into_iter()
+                .map(|item| MenuEntry {
+                    number: item.number,
+                    name: item.name,
+                    price: currency.format_cents(item.price_in_cents),
+                })
+                .collect(),
         }),
     )))
 }
 
+fn order(req: &mut Request) -> IronResult<Response> {
+    let state = req
+        .extensions
+        .get::<StateContainer>()
+        .unwrap()
+        .0
+        .lock()
+        .unwrap();
+
+    let order_id: OrderId = req
+        .extensions
+        .get::<Router>()
+        .unwrap()
+        .find("id")
+        .unwrap()
+        .parse::<i32>()
+        .unwrap()
+        .into();
+
+    let order = match state.order(order_id)? {
+        Some(order) => order,
+        None => return Ok(Response::with(status::NotFound)),
+    };
+    let restaurant = match state.menu_object(order.menu)? {
+        Some(menu) => state.restaurant(menu.restaurant)?,
+        None => None,
+    };
+
+    let ref env = req.extensions.get::<EnvContainer>().unwrap().0;
+    let currency = restaurant
+        .as_ref()
+        .and_then(|x| x.currency.as_ref())
+        .and_then(|code| Currency::from_code(code))
+        .unwrap_or_else(|| env.currency.clone());
+
+    struct Note {
+        text: String,
+    }
+
+    struct Line {
+        quantity: usize,
+        number: i32,
+        name: String,
+        total: String,
+        notes: Vec<Note>,
+    }
+
+    impl Line {
+        fn new(line: ordersheet::Line, currency: &Currency) -> Line {
+            Line {
+                quantity: line.quantity,
+                total: currency.format(&line.total()),
+                number: line.menu_item.number,
+                name: line.menu_item.name,
+                notes: line.notes.into_iter().map(|text| Note { text }).collect(),
+            }
+        }
+    }
+
+    struct Person {
+        name: String,
+        lines: Vec<Line>,
+    }
+
+    #[derive(BartDisplay)]
+    #[template = "templates/order.html"]
+    struct Order {
+        restaurant_name: String,
+        lines: Vec<Line>,
+        total: String,
+        persons: Vec<Person>,
+    }
+
+    let items = state.items_in_order(order_id)?;
+    let lines = ordersheet::by_menu_item(items.clone());
+    let total = currency.format(&ordersheet::total(&lines));
+    let persons = ordersheet::by_person(items);
+
+    Ok(Response::with((
+        status::Ok,
+        Layout::new(&Order {
+            restaurant_name: restaurant.map(|x| x.name).unwrap_or_default(),
+            lines: lines.into_iter().map(|x| Line::new(x, &currency)).collect(),
+            total,
+            persons: persons
+                .into_iter()
+                .map(|(name, lines)| Person {
+                    name,
+                    lines: lines.into_iter().map(|x| Line::new(x, &currency)).collect(),
+                })
+                .collect(),
+        }),
+    )))
+}
+
+fn favorites(req: &mut Request) -> IronResult<Response> {
+    let state = req
+        .extensions
+        .get::<StateContainer>()
+        .unwrap()
+        .0
+        .lock()
+        .unwrap();
+
+    struct Favorite {
+        name: String,
+        restaurant_id: i32,
+        restaurant_name: String,
+        items: String,
+    }
+
+    struct Person {
+        name: String,
+        favorites: Vec<Favorite>,
+    }
+
+    #[derive(BartDisplay)]
+    #[template = "templates/favorites.html"]
+    struct Favorites {
+        persons: Vec<Person>,
+    }
+
+    let mut restaurant_names = BTreeMap::new();
+    let mut persons: Vec<Person> = vec![];
+
+    // The favorites come one item at a time, sorted by person, restaurant
+    // and name
+    for x in state.all_favorites()? {
+        let item = match x.note {
+            Some(ref note) => format!("{} ({})", x.number, note),
+            None => x.number.to_string(),
+        };
+
+        let new_person = match persons.last() {
+            Some(person) => person.name.to_lowercase() != x.person_name.to_lowercase(),
+            None => true,
+        };
+        if new_person {
+            persons.push(Person {
+                name: x.person_name.clone(),
+                favorites: vec![],
+            });
+        }
+        let favorites = &mut persons.last_mut().unwrap().favorites;
+
+        let restaurant_id = i32::from(x.restaurant);
+        let new_favorite = match favorites.last() {
+            Some(favorite) => {
+                favorite.restaurant_id != restaurant_id
+                    || favorite.name.to_lowercase() != x.name.to_lowercase()
+            }
+            None => true,
+        };
+        if new_favorite {
+            if !restaurant_names.contains_key(&restaurant_id) {
+                let name = state.restaurant(x.restaurant)?.map(|x| x.name).unwrap_or_default();
+                restaurant_names.insert(restaurant_id, name);
+            }
+
+            favorites.push(Favorite {
+                name: x.name.clone(),
+                restaurant_id,
+                restaurant_name: restaurant_names[&restaurant_id].clone(),
+                items: item,
+            });
+        } else {
+            let favorite = favorites.last_mut().unwrap();
+            favorite.items.push_str(", ");
+            favorite.items.push_str(&item);
+        }
+    }
+
+    Ok(Response::with((status::Ok, Layout::new(&Favorites { persons }))))
+}
+
+fn log(req: &mut Request) -> IronResult<Response> {
+    let order_id: Option<OrderId> = req
+        .get::<UrlEncodedQuery>()
+        .ok()
+        .and_then(|x| x.get("order").and_then(|x| x.get(0)).cloned())
+        .and_then(|x| x.parse::<i32>().ok())
+        .map(OrderId::from);
+
+    let state = req
+        .extensions
+        .get::<StateContainer>()
+        .unwrap()
+        .0
+        .lock()
+        .unwrap();
+
+    struct OrderRef {
+        id: i32,
+    }
+
+    struct Entry {
+        created: String,
+        frontend: String,
+        user: String,
+        orders: Vec<OrderRef>,
+        command: String,
+        args: String,
+        outcome: String,
+    }
+
+    #[derive(BartDisplay)]
+    #[template = "templates/log.html"]
+    struct Log {
+        title: String,
+        entries: Vec<Entry>,
+    }
+
+    let entries = state
+        .audit_log(order_id, 200)?
+        .into_iter()
+        .map(|x| {
+            let created = time::at(time::Timespec::new(x.created.into(), 0));
+            let user = if x.real_user == x.effective_user {
+                x.effective_user
+            } else {
+                format!("{} (sudo by {})", x.effective_user, x.real_user)
+            };
+
+            Entry {
+                created: created.strftime("%Y-%m-%d %H:%M:%S").unwrap().to_string(),
+                frontend: x.frontend,
+                user,
+                orders: x.order.map(|id| OrderRef { id: i32::from(id) }).into_iter().collect(),
+                command: x.command,
+                args: x.args,
+                outcome: x.outcome,
+            }
+        })
+        .collect();
+
+    let title = match order_id {
+        Some(order_id) => format!("Log for order {}", order_id),
+        None => "Log".to_owned(),
+    };
+
+    Ok(Response::with((status::Ok, Layout::new(&Log { title, entries }))))
+}
+
 pub fn run(
     state: Arc<Mutex<state::State>>,
     bind: &str,
     base_url: String,
     slack_token: Option<String>,
     sharebill_url: Option<String>,
-    sharebill_cookies: Vec<String>,
+    sharebill_auth: Auth,
+    settlement: Option<config::SettlementConfig>,
+    currency: Currency,
+    exchange_rates: BTreeMap<String, Rational>,
+    enforce_budget: bool,
+    email: Option<config::EmailConfig>,
+    smtp_password: Option<String>,
 ) -> Result<(), Error> {
     let mut router = Router::new();
     router.get("/", index, "index");
     router.post("/restaurant/", create_restaurant, "create_restaurant");
     router.get("/restaurant/:id", restaurant, "restaurant");
     router.post("/restaurant/:id", ingest, "ingest");
+    router.post("/restaurant/:id/details", update_restaurant, "update_restaurant");
+    router.post("/restaurant/:id/contacts", update_contacts, "update_contacts");
     router.get("/menu/:id", menu, "menu");
+    router.get("/order/:id", order, "order");
+    router.get("/log", log, "log");
+    router.get("/favorites", favorites, "favorites");
     router.post(
         "/slack",
         move |req: &mut Request| slack::slack(&slack_token.as_ref().map(String::as_ref), req),
@@ -270,7 +756,13 @@ pub fn run(
     chain.link_before(EnvContainer(Arc::new(Env {
         base_url: base_url,
         maybe_sharebill_url: sharebill_url,
-        sharebill_cookies: sharebill_cookies,
+        sharebill_auth: sharebill_auth,
+        settlement: settlement,
+        currency: currency,
+        exchange_rates: exchange_rates,
+        enforce_budget: enforce_budget,
+        email: email,
+        smtp_password: smtp_password,
     })));
 
     let listening = Iron::new(chain).http(bind)?;